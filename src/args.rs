@@ -1,41 +1,655 @@
+use std::convert::TryFrom;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 use crate::chunk_type::ChunkType;
+use crate::png_time::PngTime;
+
+pub const USAGE: &str = "Usage:
+  pngme encode <file> <chunk type> <message> [output file] [--no-watermark]
+  pngme decode <file> <chunk type>
+  pngme decode <file> --all
+  pngme remove <file> <chunk type>
+  pngme print <file>
+  pngme info <file>
+  pngme metadata [--json] <file>
+  pngme hexdump <file>
+  pngme report <file> [--output <csv file>]
+  pngme stats [--detail] <file>
+  pngme timestamp <file> [--time <RFC 3339 time> | --remove]
+  pngme touch <file>
+  pngme strip-comments <file>
+  pngme optimize <file>
+  pngme grep <file> <pattern>
+  pngme extract-idat <file> <output file>
+  pngme replace-idat <template file> <zlib file> <output file>
+  pngme benchmark <directory> [--baseline <json file>] [--save-baseline <json file>]
+  pngme count [--chunk-type <chunk type>] [--json] <directory>";
 
 pub enum PngMeArgs {
     Encode(EncodeArgs),
     Decode(DecodeArgs),
     Remove(RemoveArgs),
     Print(PrintArgs),
+    Info(InfoArgs),
+    Metadata(MetadataArgs),
+    Hexdump(HexdumpArgs),
+    Report(ReportArgs),
+    Stats(StatsArgs),
+    Timestamp(TimestampArgs),
+    Touch(TouchArgs),
+    StripComments(StripCommentsArgs),
+    Optimize(OptimizeArgs),
+    Grep(GrepArgs),
+    ExtractIdat(ExtractIdatArgs),
+    ReplaceIdat(ReplaceIdatArgs),
+    Count(CountArgs),
+    Benchmark(BenchmarkArgs),
 }
 
 /// Encodes a secret message on the Png
 pub struct EncodeArgs {
-    /// Path to the Jpg to modify
-    file_path: PathBuf,
+    /// Path to the Png to modify
+    pub file_path: PathBuf,
     /// Type of the chunk to modify
-    chunk_type: ChunkType,
+    pub chunk_type: ChunkType,
     /// Message to write
-    message: String,
+    pub message: String,
     /// Optional: File path to output to
-    output_file: PathBuf
+    pub output_file: Option<PathBuf>,
+    /// Add a wMRK chunk naming the pngme version that wrote the file
+    pub watermark: bool
 }
 /// Decodes a message from a Png
 pub struct DecodeArgs {
     /// Path to the Png to decode from
-    file_path: PathBuf,
-    /// Type of the chunk to doecode from
-    chunk_type: ChunkType
+    pub file_path: PathBuf,
+    /// Type of the chunk to decode from. None when decoding every custom chunk.
+    pub chunk_type: Option<ChunkType>,
+    /// Decode every non standard ancillary chunk instead of a single type
+    pub all: bool
 }
 /// Removes a chunk from a Png
 pub struct RemoveArgs {
     /// Path to the Png to remove from
-    file_path: PathBuf,
+    pub file_path: PathBuf,
     /// Chunk type of the chunk to be removed
-    chunk_type: ChunkType
+    pub chunk_type: ChunkType
 }
 /// Prints a Png
 pub struct PrintArgs {
     /// Path to the Png to print
-    file_path: PathBuf
-}
\ No newline at end of file
+    pub file_path: PathBuf
+}
+/// Prints the image properties stored in the IHDR chunk of a Png
+pub struct InfoArgs {
+    /// Path to the Png to describe
+    pub file_path: PathBuf
+}
+/// Prints the metadata of a Png
+pub struct MetadataArgs {
+    /// Path to the Png to describe
+    pub file_path: PathBuf,
+    /// Print the metadata as JSON
+    pub json: bool
+}
+
+/// Prints the raw bytes of a file
+pub struct HexdumpArgs {
+    /// Path to the file to print, which does not need to be a valid Png
+    pub file_path: PathBuf
+}
+
+/// Writes a CSV report describing every chunk of a Png
+pub struct ReportArgs {
+    /// Path to the Png to describe
+    pub file_path: PathBuf,
+    /// Optional: File path to write the report to instead of printing it
+    pub output_file: Option<PathBuf>
+}
+
+/// Prints statistics about the chunk lengths of a Png
+pub struct StatsArgs {
+    /// Path to the Png to describe
+    pub file_path: PathBuf,
+    /// Also print the statistics of every chunk type
+    pub detail: bool
+}
+
+/// Sets or removes the modification time of a Png
+pub struct TimestampArgs {
+    /// Path to the Png to modify
+    pub file_path: PathBuf,
+    /// Time to store. None means now.
+    pub time: Option<PngTime>,
+    /// Remove the modification time instead of setting it
+    pub remove: bool
+}
+
+/// Sets the modification time of a Png to now
+pub struct TouchArgs {
+    /// Path to the Png to modify
+    pub file_path: PathBuf
+}
+
+/// Removes the standard text chunks of a Png
+pub struct StripCommentsArgs {
+    /// Path to the Png to modify
+    pub file_path: PathBuf
+}
+
+/// Makes a Png smaller without changing the image or its text
+pub struct OptimizeArgs {
+    /// Path to the Png to modify
+    pub file_path: PathBuf
+}
+
+/// Searches the text chunks of a Png
+pub struct GrepArgs {
+    /// Path to the Png to search
+    pub file_path: PathBuf,
+    /// Text to look for
+    pub pattern: String
+}
+
+/// Saves the zlib stream of the image data of a Png
+pub struct ExtractIdatArgs {
+    /// Path to the Png to read
+    pub file_path: PathBuf,
+    /// Path where the stream is written
+    pub output_file: PathBuf
+}
+
+/// Writes a Png with the image data of another one replaced by a zlib stream
+pub struct ReplaceIdatArgs {
+    /// Path to the Png to take the other chunks from
+    pub template_file: PathBuf,
+    /// Path to the zlib stream to use as image data
+    pub stream_file: PathBuf,
+    /// Path where the new Png is written
+    pub output_file: PathBuf
+}
+
+/// Counts the PNG files with hidden chunks in a directory
+pub struct CountArgs {
+    /// Directory to search recursively
+    pub dir_path: PathBuf,
+    /// Only count chunks of this type. None counts every non standard ancillary chunk.
+    pub chunk_type: Option<ChunkType>,
+    /// Print the summary as JSON
+    pub json: bool
+}
+
+/// Measures how fast the PNG files in a directory are encoded and decoded
+pub struct BenchmarkArgs {
+    /// Directory to search recursively
+    pub dir_path: PathBuf,
+    /// Fail if an operation is much slower than in this baseline file
+    pub baseline: Option<PathBuf>,
+    /// Write the measured times to this file, to be used as a baseline later
+    pub save_baseline: Option<PathBuf>
+}
+
+// Standard types spelled in a case that is not a valid type are fixed, so "text" is tEXt
+fn parse_chunk_type(s: &str) -> Result<ChunkType, String> {
+    return ChunkType::normalize(s).map_err(|e| e.to_string())
+}
+
+// https://doc.rust-lang.org/book/ch12-01-accepting-command-line-arguments.html
+// The arguments are expected without the program name
+impl TryFrom<&[String]> for PngMeArgs {
+    type Error = String;
+
+    fn try_from(args: &[String]) -> Result<Self, Self::Error> {
+        let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+
+        match args.as_slice() {
+            // --no-watermark is only an option after the message, so it can be the message itself
+            ["encode", file, chunk_type, message, options @ ..] => {
+                let (output_file, watermark) = match options {
+                    [] => (None, true),
+                    ["--no-watermark"] => (None, false),
+                    [output_file] => (Some(PathBuf::from(output_file)), true),
+                    [output_file, "--no-watermark"] => (Some(PathBuf::from(output_file)), false),
+                    _ => return Err(format!("Invalid arguments\n{}", USAGE)),
+                };
+                return Ok(PngMeArgs::Encode(EncodeArgs {
+                    file_path: PathBuf::from(file),
+                    chunk_type: parse_chunk_type(chunk_type)?,
+                    message: message.to_string(),
+                    output_file,
+                    watermark,
+                }))
+            }
+            ["decode", file, "--all"] => {
+                return Ok(PngMeArgs::Decode(DecodeArgs {
+                    file_path: PathBuf::from(file),
+                    chunk_type: None,
+                    all: true,
+                }))
+            }
+            ["decode", file, chunk_type] => {
+                return Ok(PngMeArgs::Decode(DecodeArgs {
+                    file_path: PathBuf::from(file),
+                    chunk_type: Some(parse_chunk_type(chunk_type)?),
+                    all: false,
+                }))
+            }
+            ["remove", file, chunk_type] => {
+                return Ok(PngMeArgs::Remove(RemoveArgs {
+                    file_path: PathBuf::from(file),
+                    chunk_type: parse_chunk_type(chunk_type)?,
+                }))
+            }
+            ["print", file] => {
+                return Ok(PngMeArgs::Print(PrintArgs {
+                    file_path: PathBuf::from(file),
+                }))
+            }
+            ["info", file] => {
+                return Ok(PngMeArgs::Info(InfoArgs {
+                    file_path: PathBuf::from(file),
+                }))
+            }
+            ["metadata", file] | ["metadata", "--json", file] => {
+                return Ok(PngMeArgs::Metadata(MetadataArgs {
+                    file_path: PathBuf::from(file),
+                    json: args.len() == 3,
+                }))
+            }
+            ["hexdump", file] => {
+                return Ok(PngMeArgs::Hexdump(HexdumpArgs {
+                    file_path: PathBuf::from(file),
+                }))
+            }
+            ["report", file] => {
+                return Ok(PngMeArgs::Report(ReportArgs {
+                    file_path: PathBuf::from(file),
+                    output_file: None,
+                }))
+            }
+            ["report", file, "--output", output_file] => {
+                return Ok(PngMeArgs::Report(ReportArgs {
+                    file_path: PathBuf::from(file),
+                    output_file: Some(PathBuf::from(output_file)),
+                }))
+            }
+            ["stats", file] | ["stats", "--detail", file] => {
+                return Ok(PngMeArgs::Stats(StatsArgs {
+                    file_path: PathBuf::from(file),
+                    detail: args.len() == 3,
+                }))
+            }
+            ["timestamp", file, options @ ..] => {
+                let (time, remove) = match options {
+                    [] => (None, false),
+                    ["--time", time] => {
+                        let time = PngTime::from_str(time).map_err(|e| format!("Invalid time: {}", e))?;
+                        (Some(time), false)
+                    }
+                    ["--remove"] => (None, true),
+                    _ => return Err(format!("Invalid arguments\n{}", USAGE)),
+                };
+                return Ok(PngMeArgs::Timestamp(TimestampArgs {
+                    file_path: PathBuf::from(file),
+                    time,
+                    remove,
+                }))
+            }
+            ["touch", file] => {
+                return Ok(PngMeArgs::Touch(TouchArgs {
+                    file_path: PathBuf::from(file),
+                }))
+            }
+            ["strip-comments", file] => {
+                return Ok(PngMeArgs::StripComments(StripCommentsArgs {
+                    file_path: PathBuf::from(file),
+                }))
+            }
+            ["optimize", file] => {
+                return Ok(PngMeArgs::Optimize(OptimizeArgs {
+                    file_path: PathBuf::from(file),
+                }))
+            }
+            ["grep", file, pattern] => {
+                return Ok(PngMeArgs::Grep(GrepArgs {
+                    file_path: PathBuf::from(file),
+                    pattern: pattern.to_string(),
+                }))
+            }
+            ["extract-idat", file, output_file] => {
+                return Ok(PngMeArgs::ExtractIdat(ExtractIdatArgs {
+                    file_path: PathBuf::from(file),
+                    output_file: PathBuf::from(output_file),
+                }))
+            }
+            ["replace-idat", template_file, stream_file, output_file] => {
+                return Ok(PngMeArgs::ReplaceIdat(ReplaceIdatArgs {
+                    template_file: PathBuf::from(template_file),
+                    stream_file: PathBuf::from(stream_file),
+                    output_file: PathBuf::from(output_file),
+                }))
+            }
+            ["benchmark", dir, options @ ..] => {
+                let mut baseline = None;
+                let mut save_baseline = None;
+                let mut options = options.iter();
+                while let Some(option) = options.next() {
+                    match (*option, options.next()) {
+                        ("--baseline", Some(file)) => baseline = Some(PathBuf::from(file)),
+                        ("--save-baseline", Some(file)) => save_baseline = Some(PathBuf::from(file)),
+                        _ => return Err(format!("Invalid arguments\n{}", USAGE)),
+                    }
+                }
+                return Ok(PngMeArgs::Benchmark(BenchmarkArgs {
+                    dir_path: PathBuf::from(dir),
+                    baseline,
+                    save_baseline,
+                }))
+            }
+            ["count", options @ .., dir] if !dir.starts_with("--") => {
+                let mut chunk_type = None;
+                let mut json = false;
+                let mut options = options.iter();
+                while let Some(option) = options.next() {
+                    match *option {
+                        "--chunk-type" => match options.next() {
+                            Some(c) => chunk_type = Some(parse_chunk_type(c)?),
+                            None => return Err(format!("Invalid arguments\n{}", USAGE)),
+                        },
+                        "--json" => json = true,
+                        _ => return Err(format!("Invalid arguments\n{}", USAGE)),
+                    }
+                }
+                return Ok(PngMeArgs::Count(CountArgs {
+                    dir_path: PathBuf::from(dir),
+                    chunk_type,
+                    json,
+                }))
+            }
+            _ => return Err(format!("Invalid arguments\n{}", USAGE)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<PngMeArgs, String> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        PngMeArgs::try_from(args.as_slice())
+    }
+
+    #[test]
+    fn test_parse_encode() {
+        match parse(&["encode", "dice.png", "ruSt", "hello"]).unwrap() {
+            PngMeArgs::Encode(args) => {
+                assert_eq!(args.file_path, PathBuf::from("dice.png"));
+                assert_eq!(args.chunk_type.to_string(), "ruSt");
+                assert_eq!(args.message, "hello");
+                assert!(args.output_file.is_none());
+                assert!(args.watermark);
+            }
+            _ => panic!("expected encode arguments"),
+        }
+        match parse(&["encode", "dice.png", "ruSt", "hello", "out.png"]).unwrap() {
+            PngMeArgs::Encode(args) => assert_eq!(args.output_file, Some(PathBuf::from("out.png"))),
+            _ => panic!("expected encode arguments"),
+        }
+        match parse(&["encode", "dice.png", "ruSt", "hello", "--no-watermark"]).unwrap() {
+            PngMeArgs::Encode(args) => {
+                assert!(args.output_file.is_none());
+                assert!(!args.watermark);
+            }
+            _ => panic!("expected encode arguments"),
+        }
+        match parse(&["encode", "dice.png", "ruSt", "hello", "out.png", "--no-watermark"]).unwrap() {
+            PngMeArgs::Encode(args) => {
+                assert_eq!(args.output_file, Some(PathBuf::from("out.png")));
+                assert!(!args.watermark);
+            }
+            _ => panic!("expected encode arguments"),
+        }
+        // In the place of the message it is the message
+        match parse(&["encode", "dice.png", "ruSt", "--no-watermark"]).unwrap() {
+            PngMeArgs::Encode(args) => {
+                assert_eq!(args.message, "--no-watermark");
+                assert!(args.watermark);
+            }
+            _ => panic!("expected encode arguments"),
+        }
+        assert!(parse(&["encode", "dice.png", "--no-watermark", "ruSt", "hello"]).is_err());
+        assert!(parse(&["encode", "dice.png", "ruSt", "hello", "--no-watermark", "out.png"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_chunk_type_normalized() {
+        match parse(&["remove", "dice.png", "text"]).unwrap() {
+            PngMeArgs::Remove(args) => assert_eq!(args.chunk_type.to_string(), "tEXt"),
+            _ => panic!("expected remove arguments"),
+        }
+        match parse(&["decode", "dice.png", "ruSt"]).unwrap() {
+            PngMeArgs::Decode(args) => assert_eq!(args.chunk_type.unwrap().to_string(), "ruSt"),
+            _ => panic!("expected decode arguments"),
+        }
+        // Valid as given, so not turned into IDAT
+        match parse(&["remove", "dice.png", "iDAT"]).unwrap() {
+            PngMeArgs::Remove(args) => assert_eq!(args.chunk_type.to_string(), "iDAT"),
+            _ => panic!("expected remove arguments"),
+        }
+        assert!(parse(&["remove", "dice.png", "t3xt"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_decode() {
+        match parse(&["decode", "dice.png", "ruSt"]).unwrap() {
+            PngMeArgs::Decode(args) => {
+                assert_eq!(args.chunk_type.unwrap().to_string(), "ruSt");
+                assert!(!args.all);
+            }
+            _ => panic!("expected decode arguments"),
+        }
+        match parse(&["decode", "dice.png", "--all"]).unwrap() {
+            PngMeArgs::Decode(args) => {
+                assert!(args.chunk_type.is_none());
+                assert!(args.all);
+            }
+            _ => panic!("expected decode arguments"),
+        }
+    }
+
+    #[test]
+    fn test_parse_info() {
+        match parse(&["info", "dice.png"]).unwrap() {
+            PngMeArgs::Info(args) => assert_eq!(args.file_path, PathBuf::from("dice.png")),
+            _ => panic!("expected info arguments"),
+        }
+        assert!(parse(&["info"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        match parse(&["metadata", "dice.png"]).unwrap() {
+            PngMeArgs::Metadata(args) => assert!(!args.json),
+            _ => panic!("expected metadata arguments"),
+        }
+        match parse(&["metadata", "--json", "dice.png"]).unwrap() {
+            PngMeArgs::Metadata(args) => {
+                assert_eq!(args.file_path, PathBuf::from("dice.png"));
+                assert!(args.json);
+            }
+            _ => panic!("expected metadata arguments"),
+        }
+        assert!(parse(&["metadata", "dice.png", "--json"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_hexdump() {
+        match parse(&["hexdump", "dice.png"]).unwrap() {
+            PngMeArgs::Hexdump(args) => assert_eq!(args.file_path, PathBuf::from("dice.png")),
+            _ => panic!("expected hexdump arguments"),
+        }
+        assert!(parse(&["hexdump"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_report() {
+        match parse(&["report", "dice.png"]).unwrap() {
+            PngMeArgs::Report(args) => {
+                assert_eq!(args.file_path, PathBuf::from("dice.png"));
+                assert!(args.output_file.is_none());
+            }
+            _ => panic!("expected report arguments"),
+        }
+        match parse(&["report", "dice.png", "--output", "report.csv"]).unwrap() {
+            PngMeArgs::Report(args) => assert_eq!(args.output_file, Some(PathBuf::from("report.csv"))),
+            _ => panic!("expected report arguments"),
+        }
+        assert!(parse(&["report", "dice.png", "report.csv"]).is_err());
+        assert!(parse(&["report", "dice.png", "--output"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_stats() {
+        match parse(&["stats", "dice.png"]).unwrap() {
+            PngMeArgs::Stats(args) => {
+                assert_eq!(args.file_path, PathBuf::from("dice.png"));
+                assert!(!args.detail);
+            }
+            _ => panic!("expected stats arguments"),
+        }
+        match parse(&["stats", "--detail", "dice.png"]).unwrap() {
+            PngMeArgs::Stats(args) => assert!(args.detail),
+            _ => panic!("expected stats arguments"),
+        }
+        assert!(parse(&["stats", "dice.png", "--detail"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        match parse(&["timestamp", "dice.png"]).unwrap() {
+            PngMeArgs::Timestamp(args) => assert!(args.time.is_none() && !args.remove),
+            _ => panic!("expected timestamp arguments"),
+        }
+        match parse(&["timestamp", "dice.png", "--time", "2024-01-02T03:04:05Z"]).unwrap() {
+            PngMeArgs::Timestamp(args) => assert_eq!(args.time.unwrap().to_string(), "2024-01-02T03:04:05Z"),
+            _ => panic!("expected timestamp arguments"),
+        }
+        match parse(&["timestamp", "dice.png", "--remove"]).unwrap() {
+            PngMeArgs::Timestamp(args) => assert!(args.remove),
+            _ => panic!("expected timestamp arguments"),
+        }
+        assert_eq!(
+            parse(&["timestamp", "dice.png", "--time", "yesterday"]).err().unwrap(),
+            "Invalid time: InvalidInput: \"yesterday\" is not an RFC 3339 timestamp"
+        );
+        assert!(parse(&["timestamp", "dice.png", "--time"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_optimize() {
+        match parse(&["optimize", "dice.png"]).unwrap() {
+            PngMeArgs::Optimize(args) => assert_eq!(args.file_path, PathBuf::from("dice.png")),
+            _ => panic!("expected optimize arguments"),
+        }
+        assert!(parse(&["optimize"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_touch() {
+        match parse(&["touch", "dice.png"]).unwrap() {
+            PngMeArgs::Touch(args) => assert_eq!(args.file_path, PathBuf::from("dice.png")),
+            _ => panic!("expected touch arguments"),
+        }
+        assert!(parse(&["touch"]).is_err());
+        assert!(parse(&["touch", "dice.png", "now"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_strip_comments() {
+        match parse(&["strip-comments", "dice.png"]).unwrap() {
+            PngMeArgs::StripComments(args) => assert_eq!(args.file_path, PathBuf::from("dice.png")),
+            _ => panic!("expected strip-comments arguments"),
+        }
+        assert!(parse(&["strip-comments"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_grep() {
+        match parse(&["grep", "dice.png", "author"]).unwrap() {
+            PngMeArgs::Grep(args) => assert_eq!(args.pattern, "author"),
+            _ => panic!("expected grep arguments"),
+        }
+        assert!(parse(&["grep", "dice.png"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_idat() {
+        match parse(&["extract-idat", "dice.png", "dice.zlib"]).unwrap() {
+            PngMeArgs::ExtractIdat(args) => assert_eq!(args.output_file, PathBuf::from("dice.zlib")),
+            _ => panic!("expected extract-idat arguments"),
+        }
+        match parse(&["replace-idat", "dice.png", "dice.zlib", "out.png"]).unwrap() {
+            PngMeArgs::ReplaceIdat(args) => {
+                assert_eq!(args.template_file, PathBuf::from("dice.png"));
+                assert_eq!(args.stream_file, PathBuf::from("dice.zlib"));
+                assert_eq!(args.output_file, PathBuf::from("out.png"));
+            }
+            _ => panic!("expected replace-idat arguments"),
+        }
+        assert!(parse(&["extract-idat", "dice.png"]).is_err());
+        assert!(parse(&["replace-idat", "dice.png", "dice.zlib"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_count() {
+        match parse(&["count", "images"]).unwrap() {
+            PngMeArgs::Count(args) => {
+                assert_eq!(args.dir_path, PathBuf::from("images"));
+                assert!(args.chunk_type.is_none() && !args.json);
+            }
+            _ => panic!("expected count arguments"),
+        }
+        match parse(&["count", "--json", "--chunk-type", "ruSt", "images"]).unwrap() {
+            PngMeArgs::Count(args) => {
+                assert_eq!(args.chunk_type.unwrap().to_string(), "ruSt");
+                assert!(args.json);
+            }
+            _ => panic!("expected count arguments"),
+        }
+        assert!(parse(&["count"]).is_err());
+        assert!(parse(&["count", "--json"]).is_err());
+        assert!(parse(&["count", "--chunk-type", "images"]).is_err());
+        assert!(parse(&["count", "--verbose", "images"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_benchmark() {
+        match parse(&["benchmark", "images"]).unwrap() {
+            PngMeArgs::Benchmark(args) => {
+                assert_eq!(args.dir_path, PathBuf::from("images"));
+                assert!(args.baseline.is_none());
+                assert!(args.save_baseline.is_none());
+            }
+            _ => panic!("expected benchmark arguments"),
+        }
+        match parse(&["benchmark", "images", "--baseline", "old.json", "--save-baseline", "new.json"]).unwrap() {
+            PngMeArgs::Benchmark(args) => {
+                assert_eq!(args.baseline, Some(PathBuf::from("old.json")));
+                assert_eq!(args.save_baseline, Some(PathBuf::from("new.json")));
+            }
+            _ => panic!("expected benchmark arguments"),
+        }
+        assert!(parse(&["benchmark"]).is_err());
+        assert!(parse(&["benchmark", "images", "--baseline"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["decode", "dice.png"]).is_err());
+        assert!(parse(&["remove", "dice.png", "ru5t"]).is_err());
+        assert!(parse(&["paint", "dice.png"]).is_err());
+    }
+}
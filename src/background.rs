@@ -0,0 +1,86 @@
+use crate::error::PngMeError;
+
+/// The suggested background color stored in the bKGD chunk. Its form depends on the color type
+/// in IHDR: a gray level, an RGB triple or a palette index.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.bKGD
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackgroundColor {
+    /// Color types 0 and 4, grayscale with or without alpha
+    Grayscale(u16),
+    /// Color types 2 and 6, truecolor with or without alpha
+    Rgb(u16, u16, u16),
+    /// Color type 3, an index into PLTE
+    Indexed(u8),
+}
+
+impl BackgroundColor {
+    /// Reads the data of a bKGD chunk for an image of the given color type
+    pub fn from_bytes(data: &[u8], color_type: u8) -> Result<BackgroundColor, PngMeError> {
+        let expected = match color_type {
+            0 | 4 => 2,
+            2 | 6 => 6,
+            3 => 1,
+            _ => return Err(PngMeError::InvalidInput(format!("invalid color type {}", color_type))),
+        };
+        if data.len() != expected {
+            return Err(PngMeError::InvalidLength{expected, actual: data.len()})
+        }
+        let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+        return Ok(match color_type {
+            0 | 4 => BackgroundColor::Grayscale(sample(0)),
+            2 | 6 => BackgroundColor::Rgb(sample(0), sample(2), sample(4)),
+            _ => BackgroundColor::Indexed(data[0]),
+        })
+    }
+
+    /// Returns the data of the bKGD chunk for this color, 2, 6 or 1 bytes long
+    pub fn to_bytes(self) -> Vec<u8> {
+        return match self {
+            BackgroundColor::Grayscale(gray) => gray.to_be_bytes().to_vec(),
+            BackgroundColor::Rgb(r, g, b) => [r.to_be_bytes(), g.to_be_bytes(), b.to_be_bytes()].concat(),
+            BackgroundColor::Indexed(index) => vec![index],
+        }
+    }
+
+    /// Returns true if this form of color is the one used by images of the given color type
+    pub fn is_valid_for_color_type(self, color_type: u8) -> bool {
+        return matches!(
+            (self, color_type),
+            (BackgroundColor::Grayscale(_), 0 | 4) | (BackgroundColor::Rgb(..), 2 | 6) | (BackgroundColor::Indexed(_), 3)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_background_color_bytes() {
+        assert_eq!(BackgroundColor::Grayscale(0x1234).to_bytes(), vec![0x12, 0x34]);
+        assert_eq!(BackgroundColor::Rgb(1, 256, 65535).to_bytes(), vec![0, 1, 1, 0, 255, 255]);
+        assert_eq!(BackgroundColor::Indexed(7).to_bytes(), vec![7]);
+    }
+
+    #[test]
+    fn test_background_color_from_bytes() {
+        assert_eq!(BackgroundColor::from_bytes(&[0x12, 0x34], 0).unwrap(), BackgroundColor::Grayscale(0x1234));
+        assert_eq!(BackgroundColor::from_bytes(&[0x12, 0x34], 4).unwrap(), BackgroundColor::Grayscale(0x1234));
+        assert_eq!(BackgroundColor::from_bytes(&[0, 1, 1, 0, 255, 255], 6).unwrap(), BackgroundColor::Rgb(1, 256, 65535));
+        assert_eq!(BackgroundColor::from_bytes(&[7], 3).unwrap(), BackgroundColor::Indexed(7));
+
+        assert!(matches!(
+            BackgroundColor::from_bytes(&[0, 1], 2),
+            Err(PngMeError::InvalidLength{expected: 6, actual: 2})
+        ));
+        assert!(BackgroundColor::from_bytes(&[0, 1], 5).is_err());
+    }
+
+    #[test]
+    fn test_background_color_valid_for_color_type() {
+        assert!(BackgroundColor::Grayscale(0).is_valid_for_color_type(4));
+        assert!(!BackgroundColor::Grayscale(0).is_valid_for_color_type(2));
+        assert!(BackgroundColor::Rgb(0, 0, 0).is_valid_for_color_type(6));
+        assert!(!BackgroundColor::Indexed(0).is_valid_for_color_type(0));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::error::PngMeError;
+
+// https://datatracker.ietf.org/doc/html/rfc4648#section-4
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes the bytes as standard base64 with padding
+pub fn encode(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for group in bytes.chunks(3) {
+        // Pack up to three bytes into the upper 24 bits and take them out six at a time
+        let mut n: u32 = 0;
+        for (i, b) in group.iter().enumerate() {
+            n |= (*b as u32) << (16 - 8 * i);
+        }
+        for i in 0..4 {
+            if i <= group.len() {
+                s.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    return s
+}
+
+/// Decodes standard base64 with padding. Whitespace is ignored.
+pub fn decode(s: &str) -> Result<Vec<u8>, PngMeError> {
+    let symbols: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !symbols.len().is_multiple_of(4) {
+        return Err(PngMeError::InvalidBase64(format!("length {} is not a multiple of 4", symbols.len())))
+    }
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(symbols.len() / 4 * 3);
+    let groups = symbols.len() / 4;
+    for (g, group) in symbols.chunks(4).enumerate() {
+        let padding = group.iter().rev().take_while(|b| **b == b'=').count();
+        if padding > 2 || (padding > 0 && g != groups - 1) {
+            return Err(PngMeError::InvalidBase64(String::from("misplaced padding")))
+        }
+
+        let mut n: u32 = 0;
+        for (i, b) in group[..4 - padding].iter().enumerate() {
+            let value = match ALPHABET.iter().position(|a| a == b) {
+                Some(v) => v as u32,
+                None => return Err(PngMeError::InvalidBase64(format!("invalid character '{}'", *b as char))),
+            };
+            n |= value << (18 - 6 * i);
+        }
+        for i in 0..3 - padding {
+            bytes.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    return Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("").unwrap(), b"");
+        assert_eq!(decode("Zg==").unwrap(), b"f");
+        assert_eq!(decode("Zm8=").unwrap(), b"fo");
+        assert_eq!(decode("Zm9v\nYmFy").unwrap(), b"foobar");
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert!(decode("Zm9").is_err());
+        assert!(decode("Zm9*").is_err());
+        assert!(decode("Zg==Zm9v").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode(&encode(&bytes)).unwrap(), bytes);
+    }
+}
@@ -1,25 +1,178 @@
-use crc;
 use std::convert::TryFrom;
 use std::fmt;
-use std::io::{BufReader, Read};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, Read};
+use std::str::FromStr;
 
 use std::string::FromUtf8Error;
 use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use crate::hexdump;
+use crate::ihdr::IhdrData;
+use crate::itxt::ItxtData;
 
 /// A validated PNG chunk. See the PNG spec for more details.
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
-#[derive(Debug, Clone)]
+/// Chunks are ordered by type and then by data, compared byte by byte.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Chunk {
     chunk_type: ChunkType,
     chunk_data: Vec<u8>,
 }
 
 impl Chunk {
-    /// Creates a new chunk from a Chunk type and a vector of u8
-    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
-        return Chunk {
-            chunk_type: chunk_type,
+    /// Type of the chunk written by `new_watermark`
+    pub const WATERMARK_CHUNK_TYPE: &'static str = "wMRK";
+
+    /// Creates a new chunk from a Chunk type and a vector of u8.
+    /// Returns `PngMeError::DataTooLarge` if the data does not fit in the 4 byte length field.
+    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Result<Chunk, PngMeError> {
+        if !Chunk::data_len_fits_in_u32(data.len()) {
+            return Err(PngMeError::DataTooLarge(data.len()))
+        }
+        return Ok(Chunk {
+            chunk_type,
             chunk_data: data
+        })
+    }
+    /// Checks that a chunk can hold `len` bytes of data, that is, that `len` fits in a u32.
+    /// Note that the PNG spec limits the length further to 2^31 - 1 bytes so that it can be read
+    /// as a signed integer, longer chunks are written anyway but other decoders may reject them.
+    pub fn data_len_fits_in_u32(len: usize) -> bool {
+        return u32::try_from(len).is_ok()
+    }
+
+    /// Creates a chunk from its type and data. Same as `Chunk::new`, the inverse of `into_parts`.
+    pub fn from_parts(chunk_type: ChunkType, data: Vec<u8>) -> Result<Chunk, PngMeError> {
+        return Chunk::new(chunk_type, data)
+    }
+    /// Consumes the chunk returning its type and data without copying them
+    pub fn into_parts(self) -> (ChunkType, Vec<u8>) {
+        return (self.chunk_type, self.chunk_data)
+    }
+    /// Creates the IEND chunk that ends every PNG. It has no data so its CRC is always 0xAE426082.
+    pub fn new_iend() -> Chunk {
+        // No data always fits
+        return Chunk::new(ChunkType::IEND, vec!()).unwrap()
+    }
+    /// Creates an IHDR chunk for an image of the given size and format.
+    /// Compression, filter and interlace methods are set to 0, the only standard values
+    /// (apart from Adam7 interlacing).
+    pub fn new_ihdr(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Result<Chunk, PngMeError> {
+        // Dimensions must fit in 31 bits and can not be zero
+        if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+            return Err(PngMeError::InvalidInput(format!("invalid image dimensions {}x{}", width, height)))
+        }
+        if !IhdrData::is_valid_combination(color_type, bit_depth) {
+            return Err(PngMeError::InvalidInput(format!(
+                "bit depth {} is not allowed for color type {}", bit_depth, color_type
+            )))
+        }
+
+        let ihdr = IhdrData {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: 0,
+        };
+        return Chunk::new(ChunkType::IHDR, ihdr.to_bytes())
+    }
+
+    /// Creates an iTXt chunk holding UTF-8 text in the given language, compressed with zlib if
+    /// `compressed`. The keyword follows the rules of tEXt keywords, 1 to 79 Latin-1 characters.
+    pub fn new_itxt(keyword: &str, language_tag: &str, translated_keyword: &str, text: &str, compressed: bool) -> Result<Chunk, PngMeError> {
+        let itxt = ItxtData {
+            keyword: keyword.to_string(),
+            compressed,
+            language_tag: language_tag.to_string(),
+            translated_keyword: translated_keyword.to_string(),
+            text: text.to_string(),
+        };
+        // iTXt is made of valid letters so this can not fail
+        return Chunk::new(ChunkType::from_str("iTXt").unwrap(), itxt.to_bytes()?)
+    }
+    /// Returns the fields of an iTXt chunk, decompressing the text if needed
+    pub fn parse_itxt(&self) -> Result<ItxtData, PngMeError> {
+        return ItxtData::try_from(self)
+    }
+
+    /// Creates a wMRK chunk recording the tool that wrote the file, stored as `app_name\0version`
+    pub fn new_watermark(app_name: &str, version: &str) -> Chunk {
+        // wMRK is made of valid letters and a name and version always fit in a chunk
+        let chunk_type = ChunkType::from_str(Chunk::WATERMARK_CHUNK_TYPE).unwrap();
+        return Chunk::new(chunk_type, format!("{}\0{}", app_name, version).into_bytes()).unwrap()
+    }
+    /// Returns the application name and version stored in a wMRK chunk
+    pub fn parse_watermark(&self) -> Result<(String, String), PngMeError> {
+        if self.chunk_type().to_string() != Chunk::WATERMARK_CHUNK_TYPE {
+            return Err(PngMeError::InvalidInput(format!("{} is not a watermark chunk", self.chunk_type())))
+        }
+        let text = self.data_as_string()
+            .map_err(|_| PngMeError::InvalidChunkData(String::from("watermark is not valid UTF-8")))?;
+        return match text.split_once('\0') {
+            Some((app_name, version)) => Ok((app_name.to_string(), version.to_string())),
+            None => Err(PngMeError::InvalidChunkData(String::from("watermark has no version"))),
+        }
+    }
+
+    /// Reads the length and type of the next chunk from `reader` and skips its data and CRC
+    /// without storing them, so chunks the caller does not care about cost no allocation.
+    /// The CRC is not checked. Returns the type and the length of the data.
+    pub fn skip_chunk<R: Read>(reader: &mut R) -> Result<(ChunkType, u32), PngMeError> {
+        let mut buffer: [u8; 4] = [0; 4];
+        reader.read_exact(&mut buffer)?;
+        let data_length = u32::from_be_bytes(buffer);
+        reader.read_exact(&mut buffer)?;
+        let chunk_type = ChunkType::from_ascii_bytes(buffer)?;
+
+        // Data plus the 4 bytes of the CRC
+        let to_skip = data_length as u64 + 4;
+        let skipped = io::copy(&mut reader.by_ref().take(to_skip), &mut io::sink())?;
+        if skipped != to_skip {
+            return Err(PngMeError::Io(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("chunk {} ends after {} of its {} bytes", chunk_type, skipped, to_skip),
+            )))
+        }
+        return Ok((chunk_type, data_length))
+    }
+
+    /// Returns false if this chunk can not appear in an image of the given color type, or if its
+    /// data does not have the layout the color type requires. Chunks that do not depend on the
+    /// color type are always valid. See the PNG spec for the rules of each chunk:
+    /// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html
+    pub fn is_valid_for_color_type(&self, color_type: u8) -> bool {
+        if !matches!(color_type, 0 | 2 | 3 | 4 | 6) {
+            return false
+        }
+        let length = self.data().len();
+        return match self.chunk_type().to_string().as_str() {
+            // Required for indexed images, a suggested palette for truecolor ones
+            "PLTE" => matches!(color_type, 2 | 3 | 6),
+            // A histogram only makes sense with a palette
+            "hIST" => matches!(color_type, 2 | 3 | 6),
+            "tRNS" => match color_type {
+                0 => length == 2,
+                2 => length == 6,
+                3 => length <= 256,
+                // Images with an alpha channel do not need it
+                _ => false,
+            },
+            "bKGD" => match color_type {
+                0 | 4 => length == 2,
+                2 | 6 => length == 6,
+                _ => length == 1,
+            },
+            "sBIT" => match color_type {
+                0 => length == 1,
+                4 => length == 2,
+                6 => length == 4,
+                _ => length == 3,
+            },
+            _ => true,
         }
     }
 
@@ -27,6 +180,11 @@ impl Chunk {
     pub fn length(&self) -> u32 {
         return self.data().len().try_into().unwrap()
     }
+    /// Returns the number of bytes this chunk takes in a file: its data plus 12 bytes for
+    /// the length, type and CRC
+    pub fn size_in_file(&self) -> u64 {
+        return self.length() as u64 + 12
+    }
     /// Returns a reference to the ChunkType
     pub fn chunk_type(&self) -> &ChunkType {
         return &self.chunk_type
@@ -35,25 +193,126 @@ impl Chunk {
     pub fn data(&self) -> &[u8] {
         return &self.chunk_data
     }
-    /// Calculates the CRC for the chunk
+    /// Calculates the CRC for the chunk. The CRC is not stored: it is computed from the type and
+    /// data every time, so it can never be stale. A CRC read from a file is only checked when
+    /// the chunk is parsed (see `TryFrom<&[u8]>`).
     pub fn crc(&self) -> u32 {
         // I do not know exacty what algorithm this is but it seems to work
         const ISO: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
-        let to_checksum: Vec<u8> = self
-            .chunk_type
-            .bytes()
-            .iter()
-            .chain(self.data())
-            .copied()
-            .collect();
-
-        return ISO.checksum(&to_checksum)
+        return ISO.checksum(&self.as_bytes_without_crc())
+    }
+    /// Returns the type bytes followed by the data, the bytes the CRC is computed from
+    pub fn as_bytes_without_crc(&self) -> Vec<u8> {
+        let mut v: Vec<u8> = Vec::with_capacity(4 + self.data().len());
+        v.extend(self.chunk_type.bytes());
+        v.extend(self.data());
+        return v
+    }
+    /// Returns true if `expected_crc`, for example one stored somewhere else, matches the CRC of
+    /// the current type and data
+    pub fn crc_is_valid_against(&self, expected_crc: u32) -> bool {
+        return self.crc() == expected_crc
+    }
+    /// Checks the CRC of this chunk against itself. As the CRC is always computed and never
+    /// stored this is always true, a `Chunk` can not hold a wrong CRC. It exists for symmetry
+    /// with formats that store it; use `crc_is_valid_against` to check a CRC from elsewhere.
+    pub fn verify_crc(&self) -> bool {
+        return self.crc_is_valid_against(self.crc())
     }
     /// Returns the data stored in this chunk as a `String`. This function will return an error
     /// if the stored data is not valid UTF-8.
     pub fn data_as_string(&self) -> Result<String, FromUtf8Error> {
         return String::from_utf8(self.chunk_data.to_vec())
     }
+    /// Returns the data that follows the first `offset` bytes, for chunks that store some
+    /// metadata (a marker, a sequence number...) before their payload.
+    /// An offset equal to the length gives an empty slice.
+    pub fn data_from_offset(&self, offset: usize) -> Result<&[u8], PngMeError> {
+        return self.data().get(offset..).ok_or_else(|| PngMeError::InvalidInput(format!(
+            "offset {} is past the end of a chunk with {} bytes of data", offset, self.data().len()
+        )))
+    }
+    /// Returns the data read as big endian u32 values, like the points of cHRM. The length of the
+    /// data must be a multiple of 4.
+    pub fn data_as_u32_be(&self) -> Result<Vec<u32>, PngMeError> {
+        if !self.data().len().is_multiple_of(4) {
+            return Err(PngMeError::InvalidChunkData(format!(
+                "{} bytes of data can not be read as u32 values", self.data().len()
+            )))
+        }
+        return Ok(self.data().chunks(4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]])).collect())
+    }
+    /// Returns the data read as big endian u16 values, like the frequencies of hIST. The length of
+    /// the data must be a multiple of 2.
+    pub fn data_as_u16_be(&self) -> Result<Vec<u16>, PngMeError> {
+        if !self.data().len().is_multiple_of(2) {
+            return Err(PngMeError::InvalidChunkData(format!(
+                "{} bytes of data can not be read as u16 values", self.data().len()
+            )))
+        }
+        return Ok(self.data().chunks(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect())
+    }
+    /// Splits the data into the first `offset` bytes and the rest, for chunks with a fixed size
+    /// header followed by variable data. An offset equal to the length gives an empty tail.
+    pub fn data_split_at(&self, offset: usize) -> Result<(&[u8], &[u8]), PngMeError> {
+        if offset > self.data().len() {
+            return Err(PngMeError::InvalidInput(format!(
+                "offset {} is past the end of a chunk with {} bytes of data", offset, self.data().len()
+            )))
+        }
+        return Ok(self.data().split_at(offset))
+    }
+    /// Reads the big endian u32 that starts `offset` bytes into the data
+    pub fn data_read_u32_be_at(&self, offset: usize) -> Result<u32, PngMeError> {
+        return match self.data().get(offset..offset.saturating_add(4)) {
+            Some(&[a, b, c, d]) => Ok(u32::from_be_bytes([a, b, c, d])),
+            _ => Err(PngMeError::InvalidLength{expected: offset.saturating_add(4), actual: self.data().len()}),
+        }
+    }
+    /// Reads the big endian u16 that starts `offset` bytes into the data
+    pub fn data_read_u16_be_at(&self, offset: usize) -> Result<u16, PngMeError> {
+        return match self.data().get(offset..offset.saturating_add(2)) {
+            Some(&[a, b]) => Ok(u16::from_be_bytes([a, b])),
+            _ => Err(PngMeError::InvalidLength{expected: offset.saturating_add(2), actual: self.data().len()}),
+        }
+    }
+    /// Splits the data on every null byte, like the `keyword\0text` of tEXt or the five fields
+    /// of iTXt, and returns the segments. Every segment must be valid UTF-8.
+    pub fn data_as_utf8_pairs(&self) -> Result<Vec<&str>, PngMeError> {
+        return self
+            .data()
+            .split(|b| *b == 0)
+            .enumerate()
+            .map(|(i, segment)| std::str::from_utf8(segment).map_err(|e| PngMeError::InvalidChunkData(format!(
+                "segment {} is not valid UTF-8: {}", i, e
+            ))))
+            .collect()
+    }
+    /// Returns the Shannon entropy of the data in bits per byte, from 0 (a single repeated byte or
+    /// no data) to 8 (every byte value equally frequent). Text is around 4.5, while compressed or
+    /// encrypted data is close to 8.
+    pub fn data_entropy(&self) -> f64 {
+        let mut counts = [0usize; 256];
+        for b in self.data() {
+            counts[*b as usize] += 1;
+        }
+        let len = self.chunk_data.len() as f64;
+        return counts
+            .iter()
+            .filter(|count| **count > 0)
+            .map(|count| {
+                let p = *count as f64 / len;
+                return -p * p.log2()
+            })
+            .sum()
+    }
+    /// Same as `data_as_string` but skipping the first `offset` bytes of the data
+    pub fn parse_as_utf8_with_offset(&self, offset: usize) -> Result<String, PngMeError> {
+        let data = self.data_from_offset(offset)?;
+        return String::from_utf8(data.to_vec()).map_err(|e| PngMeError::InvalidChunkData(format!(
+            "data after offset {} is not valid UTF-8: {}", offset, e
+        )))
+    }
     /// Returns this chunk as a byte sequences described by the PNG spec.
     /// The following data is included in this byte sequence in order:
     /// 1. Length of the data *(4 bytes)*
@@ -71,6 +330,38 @@ impl Chunk {
     
         return v
     }
+    /// Returns the bytes of this chunk, as written by `as_bytes`, formatted like `xxd` does
+    pub fn to_hexdump(&self) -> String {
+        return hexdump::hexdump(&self.as_bytes())
+    }
+    /// Returns this chunk in the type-length-value layout some external tools use:
+    /// 1. Chunk type *(4 bytes)*
+    /// 2. Length of the data *(4 bytes, big endian)*
+    /// 3. The data itself *(`length` bytes)*
+    ///
+    /// There is no CRC, `from_tlv` computes a new one.
+    pub fn to_tlv(&self) -> Vec<u8> {
+        let mut v: Vec<u8> = Vec::with_capacity(self.data().len() + 8);
+        v.extend(self.chunk_type.bytes());
+        v.extend(self.length().to_be_bytes());
+        v.extend(self.data());
+        return v
+    }
+    /// Creates a chunk from the layout written by `to_tlv`. Trailing bytes are an error.
+    pub fn from_tlv(bytes: &[u8]) -> Result<Chunk, PngMeError> {
+        if bytes.len() < 8 {
+            return Err(PngMeError::InvalidInput(format!("{} bytes are too short for a TLV chunk", bytes.len())))
+        }
+        let chunk_type = ChunkType::from_ascii_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])?;
+        let data_length = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        let data = &bytes[8..];
+        if data.len() != data_length {
+            return Err(PngMeError::InvalidInput(format!(
+                "TLV chunk {} declares {} bytes of data but has {}", chunk_type, data_length, data.len()
+            )))
+        }
+        return Chunk::new(chunk_type, data.to_vec())
+    }
 }
 
 // https://doc.rust-lang.org/std/convert/trait.TryFrom.html
@@ -85,11 +376,15 @@ impl TryFrom<&[u8]> for Chunk {
         let mut reader = BufReader::new(bytes);
         let mut buffer: [u8; 4] = [0, 0, 0, 0];
 
+        // The length was checked above so the first reads can not fail
         reader.read_exact(&mut buffer).unwrap();
         let data_length: u32 = u32::from_be_bytes(buffer);
+        if bytes.len() - 12 < data_length as usize {
+            return Err("Given vector is shorter than the length of its data")
+        }
 
         reader.read_exact(&mut buffer).unwrap();
-        let chunk_type: ChunkType = ChunkType::try_from(buffer).unwrap();
+        let chunk_type: ChunkType = ChunkType::try_from(buffer).map_err(|_| "Given chunk type is not valid")?;
 
         // Create a big buffer for the data of the chunk 
         // Warning: what happens when the lenght is zero? 
@@ -101,7 +396,7 @@ impl TryFrom<&[u8]> for Chunk {
         reader.read_exact(&mut buffer).unwrap();
         let crc: u32 = u32::from_be_bytes(buffer);
 
-        let possible_chunk = Chunk{chunk_type: chunk_type, chunk_data: chunk_data};
+        let possible_chunk = Chunk{chunk_type, chunk_data};
         let p: u32 = possible_chunk.crc();
         if p == crc {
             return Ok(possible_chunk)
@@ -111,6 +406,40 @@ impl TryFrom<&[u8]> for Chunk {
     }
 }
 
+// https://doc.rust-lang.org/std/convert/trait.From.html
+impl From<Chunk> for Vec<u8> {
+    fn from(chunk: Chunk) -> Self {
+        return chunk.as_bytes()
+    }
+}
+
+impl From<&Chunk> for Vec<u8> {
+    fn from(chunk: &Chunk) -> Self {
+        return chunk.as_bytes()
+    }
+}
+
+// https://doc.rust-lang.org/std/hash/trait.Hash.html
+/// Hashes the type bytes followed by the data, the same bytes the CRC is computed from.
+/// Two chunks are equal exactly when their types and data are equal, so equal chunks always
+/// hash the same. The type is always 4 bytes long, so the split between type and data is never
+/// ambiguous and no length prefix is needed.
+impl Hash for Chunk {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write(&self.chunk_type.bytes());
+        state.write(self.data());
+    }
+}
+
+// https://doc.rust-lang.org/std/default/trait.Default.html
+/// The default chunk is an empty aNUL chunk (see `ChunkType::default`). It is a placeholder
+/// and should be given a real type and data before being written to a file.
+impl Default for Chunk {
+    fn default() -> Self {
+        return Chunk{chunk_type: ChunkType::default(), chunk_data: vec!()}
+    }
+}
+
 // https://doc.rust-lang.org/std/fmt/trait.Display.html
 impl fmt::Display for Chunk {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -124,6 +453,57 @@ impl fmt::Display for Chunk {
     }
 }
 
+// https://doc.rust-lang.org/std/fmt/trait.LowerHex.html
+/// Formats the CRC as 8 hex digits, so `format!("{:x}", chunk)` is a quick way to show it
+impl fmt::LowerHex for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}", self.crc())
+    }
+}
+
+// https://doc.rust-lang.org/std/fmt/trait.UpperHex.html
+impl fmt::UpperHex for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08X}", self.crc())
+    }
+}
+
+/// A chunk that serializes with a given CRC instead of the computed one. `Chunk` can not hold a
+/// wrong CRC, so tests use this to build bytes that must fail CRC validation when parsed.
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub struct ChunkWithBadCrc {
+    chunk: Chunk,
+    crc: u32,
+}
+
+#[cfg(test)]
+impl Chunk {
+    /// Creates a chunk that will be written with `override_crc` as its CRC
+    pub fn new_with_crc_override(chunk_type: ChunkType, data: Vec<u8>, override_crc: u32) -> ChunkWithBadCrc {
+        return ChunkWithBadCrc{chunk: Chunk{chunk_type, chunk_data: data}, crc: override_crc}
+    }
+}
+
+#[cfg(test)]
+impl ChunkWithBadCrc {
+    /// Returns the overriding CRC, the one written by `as_bytes`
+    pub fn crc(&self) -> u32 {
+        return self.crc
+    }
+    /// Returns the chunk with the same type and data, which has the correct CRC
+    pub fn chunk(&self) -> &Chunk {
+        return &self.chunk
+    }
+    /// Same as `Chunk::as_bytes` but ending with the overriding CRC
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut v: Vec<u8> = self.chunk.as_bytes();
+        v.truncate(v.len() - 4);
+        v.extend(self.crc.to_be_bytes());
+        return v
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -152,17 +532,30 @@ mod tests {
     fn test_new_chunk() {
         let chunk_type = ChunkType::from_str("RuSt").unwrap();
         let data = "This is where your secret message will be!".as_bytes().to_vec();
-        let chunk = Chunk::new(chunk_type, data);
+        let chunk = Chunk::new(chunk_type, data).unwrap();
         assert_eq!(chunk.length(), 42);
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[test]
+    fn test_data_len_fits_in_u32() {
+        assert!(Chunk::data_len_fits_in_u32(0));
+        assert!(Chunk::data_len_fits_in_u32(u32::MAX as usize));
+        assert!(!Chunk::data_len_fits_in_u32(u32::MAX as usize + 1));
+    }
+
     #[test]
     fn test_chunk_length() {
         let chunk = testing_chunk();
         assert_eq!(chunk.length(), 42);
     }
 
+    #[test]
+    fn test_size_in_file() {
+        assert_eq!(testing_chunk().size_in_file(), 54);
+        assert_eq!(Chunk::new_iend().size_in_file(), 12);
+    }
+
     #[test]
     fn test_chunk_type() {
         let chunk = testing_chunk();
@@ -183,6 +576,28 @@ mod tests {
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[test]
+    fn test_crc_is_valid_against() {
+        let chunk = testing_chunk();
+        assert!(chunk.crc_is_valid_against(2882656334));
+        assert!(!chunk.crc_is_valid_against(2882656333));
+    }
+
+    #[test]
+    fn test_crc_is_never_stale() {
+        let chunk = testing_chunk();
+        let (chunk_type, mut data) = chunk.clone().into_parts();
+        data[0] = b't';
+        let changed = Chunk::from_parts(chunk_type, data).unwrap();
+
+        // Changing the data changes the CRC, and every chunk agrees with its own CRC
+        assert!(!changed.crc_is_valid_against(chunk.crc()));
+        for c in [&chunk, &changed, &Chunk::new_iend(), &Chunk::default()] {
+            assert!(c.crc_is_valid_against(c.crc()));
+            assert!(c.verify_crc());
+        }
+    }
+
     #[test]
     fn test_valid_chunk_from_bytes() {
         let data_length: u32 = 42;
@@ -231,6 +646,343 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_data_from_offset() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.data_from_offset(0).unwrap(), chunk.data());
+        assert_eq!(chunk.data_from_offset(4).unwrap(), b" is where your secret message will be!");
+        assert_eq!(chunk.data_from_offset(42).unwrap(), b"");
+        assert!(matches!(chunk.data_from_offset(43), Err(PngMeError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_parse_as_utf8_with_offset() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.parse_as_utf8_with_offset(0).unwrap(), "This is where your secret message will be!");
+        assert_eq!(chunk.parse_as_utf8_with_offset(4).unwrap(), " is where your secret message will be!");
+        assert_eq!(chunk.parse_as_utf8_with_offset(42).unwrap(), "");
+        assert!(chunk.parse_as_utf8_with_offset(43).is_err());
+
+        // A binary marker followed by text
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"\xff\x00\x00\x01hi".to_vec()).unwrap();
+        assert!(matches!(chunk.parse_as_utf8_with_offset(0), Err(PngMeError::InvalidChunkData(_))));
+        assert_eq!(chunk.parse_as_utf8_with_offset(4).unwrap(), "hi");
+    }
+
+    #[test]
+    fn test_into_parts() {
+        let chunk = testing_chunk();
+        let (chunk_type, data) = chunk.clone().into_parts();
+        assert_eq!(chunk_type.to_string(), "RuSt");
+        assert_eq!(data, b"This is where your secret message will be!");
+        assert_eq!(Chunk::from_parts(chunk_type, data).unwrap(), chunk);
+    }
+
+    #[test]
+    fn test_tlv_round_trip() {
+        let chunk = testing_chunk();
+        let tlv = chunk.to_tlv();
+        assert_eq!(&tlv[..8], b"RuSt\0\0\0\x2a");
+        assert_eq!(tlv.len(), 50);
+
+        let parsed = Chunk::from_tlv(&tlv).unwrap();
+        assert_eq!(parsed.chunk_type(), chunk.chunk_type());
+        assert_eq!(parsed.data(), chunk.data());
+        assert_eq!(parsed.crc(), 2882656334);
+    }
+
+    #[test]
+    fn test_from_tlv_invalid() {
+        let tlv = testing_chunk().to_tlv();
+        assert!(matches!(Chunk::from_tlv(&tlv[..7]), Err(PngMeError::InvalidInput(_))));
+        assert!(matches!(Chunk::from_tlv(&tlv[..49]), Err(PngMeError::InvalidInput(_))));
+        let mut longer = tlv.clone();
+        longer.push(0);
+        assert!(matches!(Chunk::from_tlv(&longer), Err(PngMeError::InvalidInput(_))));
+        assert!(matches!(Chunk::from_tlv(b"Ru5t\0\0\0\0"), Err(PngMeError::InvalidChunkType{..})));
+    }
+
+    #[test]
+    fn test_new_iend() {
+        let chunk = Chunk::new_iend();
+        assert_eq!(chunk.chunk_type().to_string(), "IEND");
+        assert_eq!(chunk.length(), 0);
+        assert_eq!(chunk.crc(), 0xAE426082);
+        assert_eq!(chunk.as_bytes(), vec![0, 0, 0, 0, 73, 69, 78, 68, 0xAE, 0x42, 0x60, 0x82]);
+    }
+
+    #[test]
+    fn test_watermark() {
+        let chunk = Chunk::new_watermark("pngme", "0.1.0");
+        assert_eq!(chunk.chunk_type().to_string(), "wMRK");
+        assert_eq!(chunk.data(), b"pngme\x000.1.0");
+        assert_eq!(chunk.parse_watermark().unwrap(), (String::from("pngme"), String::from("0.1.0")));
+    }
+
+    #[test]
+    fn test_parse_watermark_invalid() {
+        assert!(matches!(testing_chunk().parse_watermark(), Err(PngMeError::InvalidInput(_))));
+        let chunk = Chunk::new(ChunkType::from_str("wMRK").unwrap(), b"pngme".to_vec()).unwrap();
+        assert!(matches!(chunk.parse_watermark(), Err(PngMeError::InvalidChunkData(_))));
+    }
+
+    #[test]
+    fn test_skip_chunk() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes.extend(Chunk::new_iend().as_bytes());
+        let mut reader = bytes.as_slice();
+
+        let (chunk_type, length) = Chunk::skip_chunk(&mut reader).unwrap();
+        assert_eq!(chunk_type.to_string(), "RuSt");
+        assert_eq!(length, 42);
+        // The reader is left at the start of the next chunk
+        assert_eq!(Chunk::try_from(reader).unwrap(), Chunk::new_iend());
+        let (chunk_type, length) = Chunk::skip_chunk(&mut reader).unwrap();
+        assert_eq!(chunk_type, ChunkType::IEND);
+        assert_eq!(length, 0);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_skip_chunk_truncated() {
+        let bytes = testing_chunk().as_bytes();
+        assert!(matches!(Chunk::skip_chunk(&mut &bytes[..20]), Err(PngMeError::Io(_))));
+        assert!(matches!(Chunk::skip_chunk(&mut &bytes[..6]), Err(PngMeError::Io(_))));
+        assert!(matches!(Chunk::skip_chunk(&mut &b"\0\0\0\0Ru5t"[..]), Err(PngMeError::InvalidChunkType{..})));
+    }
+
+    #[test]
+    fn test_is_valid_for_color_type() {
+        let plte = Chunk::new(ChunkType::PLTE, vec![0, 0, 0]).unwrap();
+        assert!(!plte.is_valid_for_color_type(0));
+        assert!(plte.is_valid_for_color_type(2));
+        assert!(plte.is_valid_for_color_type(3));
+        assert!(!plte.is_valid_for_color_type(4));
+
+        let trns = Chunk::new(ChunkType::from_str("tRNS").unwrap(), vec![0, 1]).unwrap();
+        assert!(trns.is_valid_for_color_type(0));
+        assert!(!trns.is_valid_for_color_type(2));
+        assert!(!trns.is_valid_for_color_type(6));
+
+        let bkgd = Chunk::new(ChunkType::from_str("bKGD").unwrap(), vec![7]).unwrap();
+        assert!(bkgd.is_valid_for_color_type(3));
+        assert!(!bkgd.is_valid_for_color_type(0));
+
+        assert!(testing_chunk().is_valid_for_color_type(0));
+        assert!(!testing_chunk().is_valid_for_color_type(5));
+    }
+
+    #[test]
+    fn test_new_ihdr() {
+        let chunk = Chunk::new_ihdr(50, 40, 8, 6).unwrap();
+        assert_eq!(chunk.length(), 13);
+
+        let ihdr = IhdrData::try_from(&chunk).unwrap();
+        assert_eq!(ihdr.width, 50);
+        assert_eq!(ihdr.height, 40);
+        assert_eq!(ihdr.bit_depth, 8);
+        assert_eq!(ihdr.color_type, 6);
+        assert_eq!(ihdr.compression_method, 0);
+        assert_eq!(ihdr.filter_method, 0);
+        assert_eq!(ihdr.interlace_method, 0);
+    }
+
+    #[test]
+    fn test_new_ihdr_invalid() {
+        assert!(Chunk::new_ihdr(0, 10, 8, 2).is_err());
+        assert!(Chunk::new_ihdr(10, 1 << 31, 8, 2).is_err());
+        assert!(Chunk::new_ihdr(10, 10, 4, 2).is_err());
+        assert!(Chunk::new_ihdr(10, 10, 8, 5).is_err());
+    }
+
+    #[test]
+    fn test_into_bytes() {
+        let chunk = testing_chunk();
+        assert_eq!(Vec::<u8>::from(&chunk), chunk.as_bytes());
+        assert_eq!(Vec::<u8>::from(chunk.clone()), chunk.as_bytes());
+    }
+
+    #[test]
+    fn test_to_hexdump() {
+        assert_eq!(Chunk::new_iend().to_hexdump(), "00000000: 0000 0000 4945 4e44 ae42 6082            ....IEND.B`.\n");
+    }
+
+    #[test]
+    fn test_data_as_u16_be() {
+        // hIST with the frequencies of a 4 color palette
+        let hist = Chunk::new(ChunkType::from_str("hIST").unwrap(), vec![0, 0, 0, 1, 1, 0, 255, 255]).unwrap();
+        assert_eq!(hist.data_as_u16_be().unwrap(), vec![0, 1, 256, 65535]);
+        assert_eq!(Chunk::new_iend().data_as_u16_be().unwrap(), Vec::<u16>::new());
+
+        let odd = Chunk::new(ChunkType::from_str("hIST").unwrap(), vec![0, 0, 1]).unwrap();
+        assert!(matches!(odd.data_as_u16_be(), Err(PngMeError::InvalidChunkData(_))));
+    }
+
+    #[test]
+    fn test_data_as_u32_be() {
+        // gAMA of 1/2.2
+        let gama = Chunk::new(ChunkType::from_str("gAMA").unwrap(), 45455u32.to_be_bytes().to_vec()).unwrap();
+        assert_eq!(gama.data_as_u32_be().unwrap(), vec![45455]);
+
+        let two = Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![0, 0, 1, 0, 255, 255, 255, 255]).unwrap();
+        assert_eq!(two.data_as_u32_be().unwrap(), vec![256, u32::MAX]);
+        assert!(testing_chunk().data_as_u32_be().is_err());
+    }
+
+    #[test]
+    fn test_parse_phys_with_offsets() {
+        // 3780 pixels per metre (96 DPI) on both axes, unit is the metre
+        let mut data: Vec<u8> = vec!();
+        data.extend(3780u32.to_be_bytes());
+        data.extend(3779u32.to_be_bytes());
+        data.push(1);
+        let phys = Chunk::new(ChunkType::from_str("pHYs").unwrap(), data).unwrap();
+
+        assert_eq!(phys.data_read_u32_be_at(0).unwrap(), 3780);
+        assert_eq!(phys.data_read_u32_be_at(4).unwrap(), 3779);
+        let (head, unit) = phys.data_split_at(8).unwrap();
+        assert_eq!(head.len(), 8);
+        assert_eq!(unit, &[1]);
+        assert_eq!(phys.data_read_u16_be_at(6).unwrap(), 3779);
+
+        assert_eq!(phys.data_split_at(9).unwrap().1, &[] as &[u8]);
+        assert!(phys.data_split_at(10).is_err());
+        assert!(matches!(phys.data_read_u32_be_at(6), Err(PngMeError::InvalidLength{expected: 10, actual: 9})));
+        assert!(phys.data_read_u16_be_at(8).is_err());
+        assert!(phys.data_read_u32_be_at(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_data_as_utf8_pairs() {
+        let text = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Comment\0hello world".to_vec()).unwrap();
+        assert_eq!(text.data_as_utf8_pairs().unwrap(), vec!["Comment", "hello world"]);
+
+        // iTXt: keyword, compression flag and method, language, translated keyword and text
+        let itxt = Chunk::new(ChunkType::from_str("iTXt").unwrap(), b"Title\0\0\0en\0Titre\0h\xC3\xA9llo".to_vec()).unwrap();
+        assert_eq!(itxt.data_as_utf8_pairs().unwrap(), vec!["Title", "", "", "en", "Titre", "héllo"]);
+
+        let latin1 = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Author\0J\xF6rg".to_vec()).unwrap();
+        assert!(matches!(latin1.data_as_utf8_pairs(), Err(PngMeError::InvalidChunkData(_))));
+        assert_eq!(Chunk::new_iend().data_as_utf8_pairs().unwrap(), vec![""]);
+    }
+
+    #[test]
+    fn test_data_entropy() {
+        let chunk = |data: Vec<u8>| Chunk::new(ChunkType::from_str("RuSt").unwrap(), data).unwrap();
+        assert_eq!(chunk(vec!()).data_entropy(), 0.0);
+        assert_eq!(chunk(vec![7; 100]).data_entropy(), 0.0);
+        assert_eq!(chunk(vec![0, 1, 0, 1]).data_entropy(), 1.0);
+        assert_eq!(chunk((0..=255).collect()).data_entropy(), 8.0);
+    }
+
+    #[test]
+    fn test_itxt_round_trip() {
+        for compressed in [false, true] {
+            let chunk = Chunk::new_itxt("Title", "zh-cn", "\u{6807}\u{9898}", "\u{4f60}\u{597d}\u{4e16}\u{754c}", compressed).unwrap();
+            assert_eq!(&chunk.chunk_type().to_string(), "iTXt");
+            let itxt = chunk.parse_itxt().unwrap();
+            assert_eq!(itxt.keyword, "Title");
+            assert_eq!(itxt.compressed, compressed);
+            assert_eq!(itxt.language_tag, "zh-cn");
+            assert_eq!(itxt.translated_keyword, "\u{6807}\u{9898}");
+            assert_eq!(itxt.text, "\u{4f60}\u{597d}\u{4e16}\u{754c}");
+        }
+        assert!(Chunk::new_itxt("\u{6807}\u{9898}", "zh-cn", "", "text", false).is_err());
+        assert!(Chunk::new_iend().parse_itxt().is_err());
+    }
+
+    #[test]
+    fn test_new_with_crc_override() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let data = b"This is where your secret message will be!".to_vec();
+        let bad = Chunk::new_with_crc_override(chunk_type.clone(), data.clone(), 0xDEADBEEF);
+
+        assert_eq!(bad.crc(), 0xDEADBEEF);
+        assert_eq!(bad.chunk().crc(), 2882656334);
+        assert!(!bad.chunk().crc_is_valid_against(bad.crc()));
+        assert_eq!(bad.as_bytes().len(), bad.chunk().as_bytes().len());
+        assert_eq!(&bad.as_bytes()[bad.as_bytes().len() - 4..], &[0xDE, 0xAD, 0xBE, 0xEF]);
+        assert!(Chunk::try_from(bad.as_bytes().as_ref()).is_err());
+
+        // With the real CRC the same bytes parse fine
+        let good = Chunk::new_with_crc_override(chunk_type, data, 2882656334);
+        assert_eq!(Chunk::try_from(good.as_bytes().as_ref()).unwrap(), testing_chunk());
+    }
+
+    #[test]
+    fn test_as_bytes_without_crc() {
+        const ISO: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+        let chunk = testing_chunk();
+        let bytes = chunk.as_bytes_without_crc();
+
+        assert_eq!(&bytes[..4], b"RuSt");
+        assert_eq!(&bytes[4..], chunk.data());
+        assert_eq!(bytes, chunk.as_bytes()[4..chunk.as_bytes().len() - 4].to_vec());
+        assert_eq!(ISO.checksum(&bytes), chunk.crc());
+        assert_eq!(Chunk::new_iend().as_bytes_without_crc(), b"IEND");
+    }
+
+    #[test]
+    fn test_hex_formatting() {
+        let chunk = testing_chunk();
+        assert_eq!(format!("{:x}", chunk), "abd1d84e");
+        assert_eq!(format!("{:X}", chunk), "ABD1D84E");
+        assert_eq!(format!("{:X}", chunk), format!("{:08X}", chunk.crc()));
+        assert_eq!(format!("{:x}", Chunk::new_iend()), "ae426082");
+    }
+
+    #[test]
+    fn test_chunk_ordering() {
+        let chunk = |t: &str, d: &[u8]| Chunk::new(ChunkType::from_str(t).unwrap(), d.to_vec()).unwrap();
+        let mut chunks = [
+            chunk("tEXt", b"b"),
+            chunk("IEND", b""),
+            chunk("tEXt", b"ab"),
+            chunk("IHDR", b"0123456789abc"),
+            chunk("tEXt", b"a"),
+        ];
+        chunks.sort();
+        let sorted: Vec<(String, Vec<u8>)> = chunks.iter().map(|c| (c.chunk_type().to_string(), c.data().to_vec())).collect();
+        assert_eq!(sorted, vec![
+            (String::from("IEND"), b"".to_vec()),
+            (String::from("IHDR"), b"0123456789abc".to_vec()),
+            (String::from("tEXt"), b"a".to_vec()),
+            (String::from("tEXt"), b"ab".to_vec()),
+            (String::from("tEXt"), b"b".to_vec()),
+        ]);
+
+        let set: std::collections::BTreeSet<Chunk> = chunks.iter().cloned().chain(chunks.iter().cloned()).collect();
+        assert_eq!(set.len(), 5);
+        assert_eq!(set.first().unwrap().chunk_type(), &ChunkType::IEND);
+    }
+
+    #[test]
+    fn test_hash_map_keys() {
+        use std::collections::HashMap;
+
+        let mut map: HashMap<Chunk, &str> = HashMap::new();
+        map.insert(testing_chunk(), "message");
+        map.insert(Chunk::new_iend(), "end");
+        map.insert(Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"This is where your secret message will be!".to_vec()).unwrap(), "lowercase");
+        assert_eq!(map.len(), 3);
+
+        let message = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"This is where your secret message will be!".to_vec()).unwrap();
+        let end = Chunk::new(ChunkType::from_str("IEND").unwrap(), vec!()).unwrap();
+        let lowercase = Chunk::from_tlv(&map.keys().find(|c| c.chunk_type().to_string() == "ruSt").unwrap().to_tlv()).unwrap();
+        assert_eq!(map.get(&message), Some(&"message"));
+        assert_eq!(map.get(&end), Some(&"end"));
+        assert_eq!(map.get(&lowercase), Some(&"lowercase"));
+        assert_eq!(map.get(&Chunk::default()), None);
+    }
+
+    #[test]
+    fn test_default() {
+        let chunk = Chunk::default();
+        assert_eq!(chunk.chunk_type().to_string(), "aNUL");
+        assert_eq!(chunk.length(), 0);
+        assert_eq!(chunk.crc(), 0x607C4B1A);
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -1,14 +1,75 @@
 use std::str::FromStr;
 use std::fmt;
 
+use crate::error::PngMeError;
+
 /// A validated PNG chunk type. See the PNG spec for more details.
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Chunk types are ordered by their bytes, so uppercase letters come before lowercase ones.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ChunkType {
     data: [u8; 4]
 }
 
 impl ChunkType {
+    /// Image header, always the first chunk
+    pub const IHDR: ChunkType = ChunkType{data: *b"IHDR"};
+    /// Palette
+    pub const PLTE: ChunkType = ChunkType{data: *b"PLTE"};
+    /// Image data
+    pub const IDAT: ChunkType = ChunkType{data: *b"IDAT"};
+    /// Image trailer, always the last chunk
+    pub const IEND: ChunkType = ChunkType{data: *b"IEND"};
+
+    /// Every chunk type defined by the PNG spec. Anything else is a private or custom chunk.
+    pub const STANDARD_TYPES: [&'static str; 18] = [
+        "IHDR", "PLTE", "IDAT", "IEND",
+        "cHRM", "gAMA", "iCCP", "sBIT", "sRGB",
+        "bKGD", "hIST", "tRNS", "pHYs", "sPLT", "tIME",
+        "iTXt", "tEXt", "zTXt",
+    ];
+    /// The standard types holding a keyword and text, see `is_text_type`
+    pub const TEXT_TYPES: [&'static str; 3] = ["tEXt", "zTXt", "iTXt"];
+
+    /// Creates a chunk type from its bytes. Every byte must be an ASCII letter and the third one
+    /// must be uppercase (reserved bit unset). On failure the error tells which byte is wrong.
+    pub fn from_ascii_bytes(bytes: [u8; 4]) -> Result<ChunkType, PngMeError> {
+        for (i, b) in bytes.iter().enumerate() {
+            if !ChunkType::is_valid_byte(*b) {
+                return Err(PngMeError::InvalidChunkType{byte_index: i, byte_value: *b})
+            }
+        }
+        let chunk_type = ChunkType{data: bytes};
+        if !chunk_type.is_reserved_bit_valid() {
+            return Err(PngMeError::InvalidChunkType{byte_index: 2, byte_value: bytes[2]})
+        }
+        return Ok(chunk_type)
+    }
+
+    /// Number of valid chunk types: 52 letters for the first, second and fourth bytes and
+    /// 26 uppercase letters for the third
+    pub const VALID_COUNT: usize = 52 * 52 * 26 * 52;
+
+    /// Returns a lazy iterator over every valid chunk type, in byte order (`AAAA` first, `zzZz` last)
+    pub fn iter_all_valid() -> impl Iterator<Item = ChunkType> {
+        let letters = || (b'A'..=b'Z').chain(b'a'..=b'z');
+        return letters().flat_map(move |b0| {
+            letters().flat_map(move |b1| {
+                (b'A'..=b'Z').flat_map(move |b2| {
+                    letters().map(move |b3| ChunkType{data: [b0, b1, b2, b3]})
+                })
+            })
+        })
+    }
+
+    /// Same as `from_ascii_bytes` for a slice, which must be exactly 4 bytes long
+    pub fn try_from_slice(slice: &[u8]) -> Result<ChunkType, PngMeError> {
+        let bytes: [u8; 4] = slice
+            .try_into()
+            .map_err(|_| PngMeError::InvalidLength{expected: 4, actual: slice.len()})?;
+        return ChunkType::try_from(bytes)
+    }
+
     /// Returns the raw bytes contained in this chunk
     pub fn bytes(&self) -> [u8; 4] {
         self.data
@@ -62,10 +123,48 @@ impl ChunkType {
         return false
     }
 
+    /// Parses a chunk type, fixing the case of the standard ones when it is not valid as given:
+    /// "ihdr" gives IHDR and "text" gives tEXt. A valid type is always kept as given, so "iDAT"
+    /// stays a type of its own instead of becoming IDAT.
+    pub fn normalize(s: &str) -> Result<ChunkType, PngMeError> {
+        let parsed = ChunkType::from_str(s).map_err(|e| PngMeError::InvalidInput(format!("invalid chunk type \"{}\": {}", s, e)));
+        if matches!(&parsed, Ok(chunk_type) if chunk_type.is_valid()) {
+            return parsed
+        }
+        if let Some(standard) = ChunkType::STANDARD_TYPES.iter().find(|t| t.eq_ignore_ascii_case(s)) {
+            return Ok(ChunkType::from_str(standard).unwrap())
+        }
+        // Neither valid nor standard: tells which byte is wrong, like the reserved bit of "abcd"
+        return parsed.and_then(|chunk_type| ChunkType::from_ascii_bytes(chunk_type.bytes()))
+    }
+
+    /// Returns the type followed by its four properties, for example
+    /// `RuSt [critical=true, public=false, reserved_valid=true, safe_to_copy=true]`
+    pub fn display_properties(&self) -> String {
+        return format!(
+            "{} [critical={}, public={}, reserved_valid={}, safe_to_copy={}]",
+            self,
+            self.is_critical(),
+            self.is_public(),
+            self.is_reserved_bit_valid(),
+            self.is_safe_to_copy()
+        )
+    }
+
+    /// Returns true if this is one of the chunk types defined by the PNG spec
+    pub fn is_standard(&self) -> bool {
+        return ChunkType::STANDARD_TYPES.contains(&self.to_string().as_str())
+    }
+
+    /// Returns true if this is tEXt, zTXt or iTXt, the chunk types that hold a keyword and text
+    pub fn is_text_type(&self) -> bool {
+        return ChunkType::TEXT_TYPES.contains(&self.to_string().as_str())
+    }
+
     /// Returns true if the reserved byte is valid and all four bytes are represented by the characters A-Z or a-z.
     /// Note that this chunk type should always be valid as it is validated during construction.
     pub fn is_valid(&self) -> bool {
-        if self.is_reserved_bit_valid() == false {
+        if !self.is_reserved_bit_valid() {
             return false
         }
 
@@ -82,20 +181,16 @@ impl ChunkType {
     /// Valid bytes are represented by the characters A-Z or a-z (or 65-90 and 97-122 decimal)
     pub fn is_valid_byte(byte: u8) -> bool {
         // Also can be done with u8::is_ascii_uppercase and u8::is_ascii_lowercase
-        return (65 <= byte && byte <= 90) | (97 <= byte && byte <= 122)
+        return (65..=90).contains(&byte) | (97..=122).contains(&byte)
     }
 }
 
 // https://doc.rust-lang.org/std/convert/trait.TryFrom.html
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = &'static str;
+    type Error = PngMeError;
 
     fn try_from(arr: [u8; 4]) -> Result<Self, Self::Error> {
-        let possible_chunk: ChunkType = ChunkType{data: arr};
-        if possible_chunk.is_valid() {
-            return Ok(possible_chunk)
-        }
-        Err("Invalid chunk when converting from array (Chunk bytes not within upper and lowercase ASCII letters or third byte not uppercase)") 
+        return ChunkType::from_ascii_bytes(arr)
     }
 }
 
@@ -120,6 +215,15 @@ impl FromStr for ChunkType {
     }
 }
 
+// https://doc.rust-lang.org/std/default/trait.Default.html
+/// The default chunk type is aNUL, an ancillary type with a valid reserved bit that no decoder
+/// knows. It is a placeholder, not a meaningful chunk type.
+impl Default for ChunkType {
+    fn default() -> Self {
+        return ChunkType{data: *b"aNUL"}
+    }
+}
+
 // https://doc.rust-lang.org/std/fmt/trait.Display.html
 impl fmt::Display for ChunkType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -130,6 +234,21 @@ impl fmt::Display for ChunkType {
     }
 }
 
+// https://doc.rust-lang.org/std/fmt/trait.LowerHex.html
+/// Formats the 4 bytes read as a big endian u32, so IHDR is `49484452`
+impl fmt::LowerHex for ChunkType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}", u32::from_be_bytes(self.data))
+    }
+}
+
+// https://doc.rust-lang.org/std/fmt/trait.UpperHex.html
+impl fmt::UpperHex for ChunkType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08X}", u32::from_be_bytes(self.data))
+    }
+}
+
 
 
 #[cfg(test)]
@@ -146,6 +265,51 @@ mod tests {
         assert_eq!(expected, actual.bytes());
     }
 
+    #[test]
+    pub fn test_chunk_type_from_ascii_bytes() {
+        assert_eq!(ChunkType::from_ascii_bytes(*b"RuSt").unwrap().to_string(), "RuSt");
+
+        for i in 0..4 {
+            let mut bytes = *b"RuSt";
+            bytes[i] = b'5';
+            match ChunkType::from_ascii_bytes(bytes) {
+                Err(PngMeError::InvalidChunkType{byte_index, byte_value}) => {
+                    assert_eq!(byte_index, i);
+                    assert_eq!(byte_value, b'5');
+                }
+                _ => panic!("expected an invalid chunk type"),
+            }
+        }
+        // A lowercase third letter sets the reserved bit
+        assert!(matches!(
+            ChunkType::from_ascii_bytes(*b"Rust"),
+            Err(PngMeError::InvalidChunkType{byte_index: 2, byte_value: b's'})
+        ));
+        // The first invalid byte is reported
+        assert!(matches!(
+            ChunkType::from_ascii_bytes(*b"Ru1 "),
+            Err(PngMeError::InvalidChunkType{byte_index: 2, byte_value: b'1'})
+        ));
+    }
+
+    #[test]
+    pub fn test_chunk_type_try_from_slice() {
+        let bytes = b"RuStIDAT";
+        assert_eq!(ChunkType::try_from_slice(&bytes[..4]).unwrap().to_string(), "RuSt");
+        assert_eq!(ChunkType::try_from_slice(&bytes[4..]).unwrap(), ChunkType::IDAT);
+        assert!(matches!(ChunkType::try_from_slice(b"Ru5t"), Err(PngMeError::InvalidChunkType{byte_index: 2, ..})));
+
+        for length in [3, 5, 0] {
+            match ChunkType::try_from_slice(&bytes[..length]) {
+                Err(PngMeError::InvalidLength{expected, actual}) => {
+                    assert_eq!(expected, 4);
+                    assert_eq!(actual, length);
+                }
+                _ => panic!("expected an invalid length"),
+            }
+        }
+    }
+
     #[test]
     pub fn test_chunk_type_from_str() {
         let expected = ChunkType::try_from([82, 117, 83, 116]).unwrap();
@@ -222,6 +386,112 @@ mod tests {
         assert_eq!(&chunk.to_string(), "RuSt");
     }
 
+    #[test]
+    pub fn test_critical_constants() {
+        assert_eq!(ChunkType::IHDR, ChunkType::from_str("IHDR").unwrap());
+        assert_eq!(ChunkType::PLTE.to_string(), "PLTE");
+        assert_eq!(ChunkType::IDAT.to_string(), "IDAT");
+        assert_eq!(ChunkType::IEND.to_string(), "IEND");
+        for chunk_type in [ChunkType::IHDR, ChunkType::PLTE, ChunkType::IDAT, ChunkType::IEND] {
+            assert!(chunk_type.is_valid());
+            assert!(chunk_type.is_critical());
+        }
+    }
+
+    #[test]
+    pub fn test_chunk_type_is_standard() {
+        assert!(ChunkType::IHDR.is_standard());
+        assert!(ChunkType::from_str("tEXt").unwrap().is_standard());
+        assert!(!ChunkType::from_str("TEXT").unwrap().is_standard());
+        assert!(!ChunkType::from_str("RuSt").unwrap().is_standard());
+    }
+
+    #[test]
+    pub fn test_chunk_type_is_text_type() {
+        assert!(ChunkType::from_str("tEXt").unwrap().is_text_type());
+        assert!(ChunkType::from_str("zTXt").unwrap().is_text_type());
+        assert!(ChunkType::from_str("iTXt").unwrap().is_text_type());
+        assert!(!ChunkType::from_str("TEXT").unwrap().is_text_type());
+        assert!(!ChunkType::IHDR.is_text_type());
+    }
+
+    #[test]
+    pub fn test_chunk_type_ordering() {
+        let mut types: Vec<ChunkType> = ["tEXt", "IHDR", "IEND", "gAMA", "IDAT"]
+            .iter()
+            .map(|s| ChunkType::from_str(s).unwrap())
+            .collect();
+        types.sort();
+        let names: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+        assert_eq!(names, vec!["IDAT", "IEND", "IHDR", "gAMA", "tEXt"]);
+    }
+
+    #[test]
+    pub fn test_chunk_type_default() {
+        let chunk_type = ChunkType::default();
+        assert_eq!(chunk_type.to_string(), "aNUL");
+        assert!(chunk_type.is_valid());
+        assert!(!chunk_type.is_critical());
+        assert!(!chunk_type.is_standard());
+    }
+
+    #[test]
+    pub fn test_display_properties() {
+        let properties = |s: &str| ChunkType::from_str(s).unwrap().display_properties();
+        assert_eq!(properties("RuSt"), "RuSt [critical=true, public=false, reserved_valid=true, safe_to_copy=true]");
+        assert_eq!(properties("ruSt"), "ruSt [critical=false, public=false, reserved_valid=true, safe_to_copy=true]");
+        assert_eq!(properties("RUSt"), "RUSt [critical=true, public=true, reserved_valid=true, safe_to_copy=true]");
+        assert_eq!(properties("Rust"), "Rust [critical=true, public=false, reserved_valid=false, safe_to_copy=true]");
+        assert_eq!(properties("RuST"), "RuST [critical=true, public=false, reserved_valid=true, safe_to_copy=false]");
+    }
+
+    #[test]
+    pub fn test_iter_all_valid() {
+        let mut count = 0;
+        let mut previous: Option<ChunkType> = None;
+        for chunk_type in ChunkType::iter_all_valid() {
+            assert!(chunk_type.is_valid());
+            // Strictly increasing, so there are no duplicates
+            assert!(previous.is_none_or(|p| p < chunk_type));
+            previous = Some(chunk_type);
+            count += 1;
+        }
+        assert_eq!(count, ChunkType::VALID_COUNT);
+        assert_eq!(count, 3_655_808);
+        assert_eq!(ChunkType::iter_all_valid().next().unwrap().to_string(), "AAAA");
+        assert_eq!(previous.unwrap().to_string(), "zzZz");
+        assert!(ChunkType::iter_all_valid().any(|t| t.to_string() == "RuSt"));
+    }
+
+    #[test]
+    pub fn test_chunk_type_hex_formatting() {
+        assert_eq!(format!("{:X}", ChunkType::IHDR), "49484452");
+        assert_eq!(format!("{:x}", ChunkType::IHDR), "49484452");
+        assert_eq!(format!("{:X}", ChunkType::from_str("ruSt").unwrap()), "72755374");
+        assert_eq!(format!("{:x}", ChunkType::from_str("RuSt").unwrap()), "52755374");
+        assert_eq!(format!("{:X}", ChunkType::from_str("zzZz").unwrap()), "7A7A5A7A");
+    }
+
+    #[test]
+    pub fn test_normalize() {
+        for standard in ChunkType::STANDARD_TYPES {
+            assert_eq!(ChunkType::normalize(standard).unwrap().to_string(), standard);
+            // Lowercase sets the reserved bit, so it is never valid as given
+            assert_eq!(ChunkType::normalize(&standard.to_lowercase()).unwrap().to_string(), standard);
+        }
+        assert_eq!(ChunkType::normalize("ihdr").unwrap(), ChunkType::IHDR);
+        assert_eq!(ChunkType::normalize("text").unwrap().to_string(), "tEXt");
+
+        // Valid types are kept even if they only differ from a standard one in case
+        for custom in ["ruSt", "RuSt", "wMRK", "prIv", "iDAT", "TEXT"] {
+            assert_eq!(ChunkType::normalize(custom).unwrap().to_string(), custom);
+        }
+        assert!(ChunkType::normalize("ru5t").is_err());
+        assert!(matches!(ChunkType::normalize("abcd"), Err(PngMeError::InvalidChunkType{byte_index: 2, byte_value: b'c'})));
+        assert_eq!(ChunkType::normalize("ruSt").unwrap().to_string(), "ruSt");
+        assert!(ChunkType::normalize("toolong").is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_trait_impls() {
         let chunk_type_1: ChunkType = TryFrom::try_from([82, 117, 83, 116]).unwrap();
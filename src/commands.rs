@@ -0,0 +1,993 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::args::{
+    BenchmarkArgs, CountArgs, DecodeArgs, EncodeArgs, ExtractIdatArgs, GrepArgs, HexdumpArgs, InfoArgs, MetadataArgs, OptimizeArgs, PngMeArgs, PrintArgs, RemoveArgs, ReplaceIdatArgs,
+    ReportArgs, StatsArgs, StripCommentsArgs, TimestampArgs, TouchArgs,
+};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use crate::hexdump::hexdump as format_hexdump;
+use crate::ihdr::IhdrData;
+use crate::png::Png;
+use crate::png_time::PngTime;
+use crate::Result;
+use rayon::prelude::*;
+
+/// Runs the command described by the arguments, writing its output to `out`
+pub fn run<W: Write>(args: PngMeArgs, out: &mut W) -> Result<()> {
+    match args {
+        PngMeArgs::Encode(args) => encode(&args),
+        PngMeArgs::Decode(args) => decode(&args, out),
+        PngMeArgs::Remove(args) => remove(&args, out),
+        PngMeArgs::Print(args) => print(&args, out),
+        PngMeArgs::Info(args) => info(&args, out),
+        PngMeArgs::Metadata(args) => metadata(&args, out),
+        PngMeArgs::Hexdump(args) => hexdump(&args, out),
+        PngMeArgs::Report(args) => report(&args, out),
+        PngMeArgs::Stats(args) => stats(&args, out),
+        PngMeArgs::Timestamp(args) => timestamp(&args, out),
+        PngMeArgs::Touch(args) => touch(&args, out),
+        PngMeArgs::StripComments(args) => strip_comments(&args, out),
+        PngMeArgs::Optimize(args) => optimize(&args, out),
+        PngMeArgs::Grep(args) => grep(&args, out),
+        PngMeArgs::ExtractIdat(args) => extract_idat(&args, out),
+        PngMeArgs::ReplaceIdat(args) => replace_idat(&args, out),
+        PngMeArgs::Count(args) => count(&args, out),
+        PngMeArgs::Benchmark(args) => benchmark(&args, out),
+    }
+}
+
+fn read_png(path: &Path) -> Result<Png> {
+    return Ok(Png::from_file(path)?)
+}
+
+fn write_png(path: &Path, png: &Png) -> Result<()> {
+    // A Png that does not read back the same is a bug in the method that modified it
+    debug_assert_eq!(png.reparse().ok().as_ref(), Some(png));
+    png.save_to_file(path)?;
+    return Ok(())
+}
+
+/// Hidden chunks are the non standard ancillary chunks, apart from the watermark pngme adds itself
+fn is_hidden_chunk(chunk: &Chunk) -> bool {
+    let chunk_type = chunk.chunk_type();
+    return !chunk_type.is_critical()
+        && !chunk_type.is_standard()
+        && chunk_type.to_string() != Chunk::WATERMARK_CHUNK_TYPE
+}
+
+/// Returns the data as text if it is valid UTF-8, as lowercase hex otherwise
+fn data_for_display(chunk: &Chunk) -> String {
+    return match chunk.data_as_string() {
+        Ok(s) => s,
+        Err(_) => chunk.data().iter().map(|b| format!("{:02x}", b)).collect(),
+    }
+}
+
+/// Encodes a message into a PNG file
+pub fn encode(args: &EncodeArgs) -> Result<()> {
+    // A type with the reserved bit set could be written but not read back
+    ChunkType::from_ascii_bytes(args.chunk_type.bytes())?;
+    let mut png = read_png(&args.file_path)?;
+    png.add_before_iend(Chunk::new(args.chunk_type.clone(), args.message.clone().into_bytes())?);
+    if args.watermark {
+        // Keep a single watermark, the one of the latest version that wrote the file
+        let _ = png.remove_first_chunk(Chunk::WATERMARK_CHUNK_TYPE);
+        png.add_before_iend(Chunk::new_watermark(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+    }
+
+    let output = args.output_file.as_ref().unwrap_or(&args.file_path);
+    write_png(output, &png)?;
+    return Ok(())
+}
+
+/// Searches for a message hidden in a PNG file and prints the message if one is found.
+/// With `--all` every hidden chunk is printed in the order they appear.
+pub fn decode<W: Write>(args: &DecodeArgs, out: &mut W) -> Result<()> {
+    let png = read_png(&args.file_path)?;
+
+    if args.all {
+        for c in png.chunks().iter().filter(|c| is_hidden_chunk(c)) {
+            writeln!(out, "{}: {}", c.chunk_type(), data_for_display(c))?;
+        }
+        return Ok(())
+    }
+
+    let chunk_type = match &args.chunk_type {
+        Some(chunk_type) => chunk_type.to_string(),
+        None => return Err("A chunk type or --all is needed to decode".into()),
+    };
+    let chunk = png.chunk_at_type_or_err(&chunk_type)?;
+    writeln!(out, "{}", data_for_display(chunk))?;
+    return Ok(())
+}
+
+/// Removes a chunk from a PNG file and saves the result
+pub fn remove<W: Write>(args: &RemoveArgs, out: &mut W) -> Result<()> {
+    let mut png = read_png(&args.file_path)?;
+    let removed = png.remove_first_chunk(&args.chunk_type.to_string())?;
+    write_png(&args.file_path, &png)?;
+
+    writeln!(out, "Removed chunk {}", removed.chunk_type())?;
+    return Ok(())
+}
+
+/// Prints all of the chunks in a PNG file
+pub fn print<W: Write>(args: &PrintArgs, out: &mut W) -> Result<()> {
+    let png = read_png(&args.file_path)?;
+    write!(out, "{}", png)?;
+    return Ok(())
+}
+
+/// Prints the size, format and interlacing of a PNG file
+pub fn info<W: Write>(args: &InfoArgs, out: &mut W) -> Result<()> {
+    let png = read_png(&args.file_path)?;
+    let ihdr = IhdrData::try_from(png.ihdr_or_err()?)?;
+
+    writeln!(out, "Size: {}x{}", ihdr.width, ihdr.height)?;
+    writeln!(out, "Bit depth: {}", ihdr.bit_depth)?;
+    writeln!(out, "Color type: {}", ihdr.color_type)?;
+    let interlacing = if png.is_progressive() {
+        "Adam7 (progressive)"
+    } else if png.is_non_interlaced() {
+        "none"
+    } else {
+        "unknown"
+    };
+    writeln!(out, "Interlacing: {}", interlacing)?;
+    return Ok(())
+}
+
+/// Prints the metadata of a PNG file, as JSON with `--json`
+pub fn metadata<W: Write>(args: &MetadataArgs, out: &mut W) -> Result<()> {
+    let png = read_png(&args.file_path)?;
+    let metadata = png.extract_metadata()?;
+
+    if args.json {
+        write!(out, "{}", metadata.to_json_string())?;
+        return Ok(())
+    }
+    writeln!(out, "Size: {}x{}", metadata.width, metadata.height)?;
+    writeln!(out, "Bit depth: {}", metadata.bit_depth)?;
+    writeln!(out, "Color type: {}", metadata.color_type)?;
+    if let Some(gamma) = metadata.gamma {
+        writeln!(out, "Gamma: {}", gamma)?;
+    }
+    if let Some(time) = metadata.modification_time {
+        writeln!(out, "Modified: {}", time)?;
+    }
+    for (keyword, text) in &metadata.text_entries {
+        writeln!(out, "{}: {}", keyword, text)?;
+    }
+    writeln!(out, "Progressive: {}", metadata.is_progressive)?;
+    writeln!(out, "sRGB: {}", metadata.has_srgb)?;
+    return Ok(())
+}
+
+/// Prints the bytes of a file like `xxd` does. The file is not parsed, so this also works
+/// for corrupted PNG files.
+pub fn hexdump<W: Write>(args: &HexdumpArgs, out: &mut W) -> Result<()> {
+    let bytes = fs::read(&args.file_path)?;
+    write!(out, "{}", format_hexdump(&bytes))?;
+    return Ok(())
+}
+
+/// Writes a CSV report with one line per chunk of a PNG file, to `out` or to the output file
+pub fn report<W: Write>(args: &ReportArgs, out: &mut W) -> Result<()> {
+    let png = read_png(&args.file_path)?;
+
+    match &args.output_file {
+        Some(output_file) => {
+            let mut file = fs::File::create(output_file)?;
+            png.write_chunks_report(&mut file)?;
+            writeln!(out, "Wrote a report of {} chunks to {}", png.chunks().len(), output_file.display())?;
+        }
+        None => png.write_chunks_report(out)?,
+    }
+    return Ok(())
+}
+
+/// Prints statistics about the data lengths of the chunks of a PNG file, and of every chunk
+/// type with `--detail`
+pub fn stats<W: Write>(args: &StatsArgs, out: &mut W) -> Result<()> {
+    let png = read_png(&args.file_path)?;
+    writeln!(out, "All: {}", png.chunk_statistics())?;
+
+    if args.detail {
+        for chunk_type in png.all_chunk_types() {
+            let chunk_type = chunk_type.to_string();
+            // The type comes from the chunks so there is at least one
+            if let Some(stats) = png.chunk_statistics_for_type(&chunk_type) {
+                writeln!(out, "{}: {}", chunk_type, stats)?;
+            }
+        }
+    }
+    return Ok(())
+}
+
+/// Sets the modification time (tIME chunk) of a PNG file, or removes it with `--remove`
+pub fn timestamp<W: Write>(args: &TimestampArgs, out: &mut W) -> Result<()> {
+    let mut png = read_png(&args.file_path)?;
+
+    if args.remove {
+        if !png.remove_modification_time() {
+            // Nothing changed, so the file is left as it is
+            writeln!(out, "There was no modification time to remove")?;
+            return Ok(())
+        }
+        writeln!(out, "Removed the modification time")?;
+    } else {
+        let time = args.time.unwrap_or_else(PngTime::now);
+        png.set_modification_time(time);
+        writeln!(out, "Modification time set to {}", time)?;
+    }
+
+    write_png(&args.file_path, &png)?;
+    return Ok(())
+}
+
+/// Sets the modification time of a PNG file to now
+pub fn touch<W: Write>(args: &TouchArgs, out: &mut W) -> Result<()> {
+    let mut png = read_png(&args.file_path)?;
+    png.touch()?;
+    write_png(&args.file_path, &png)?;
+
+    // touch always stores a time
+    writeln!(out, "Modification time set to {}", png.modification_time()?.unwrap())?;
+    return Ok(())
+}
+
+/// Removes the tEXt, zTXt and iTXt chunks of a PNG file and saves the result
+pub fn strip_comments<W: Write>(args: &StripCommentsArgs, out: &mut W) -> Result<()> {
+    let mut png = read_png(&args.file_path)?;
+    let removed = png.remove_comment_chunks();
+    write_png(&args.file_path, &png)?;
+
+    writeln!(out, "Removed {} text chunks", removed)?;
+    return Ok(())
+}
+
+/// Makes a PNG file smaller with `Png::optimize` and saves the result
+pub fn optimize<W: Write>(args: &OptimizeArgs, out: &mut W) -> Result<()> {
+    let mut png = read_png(&args.file_path)?;
+    let report = png.optimize()?;
+    write_png(&args.file_path, &png)?;
+
+    writeln!(out, "Optimized: {}", report)?;
+    return Ok(())
+}
+
+/// Prints every text chunk of a PNG file that contains the pattern
+pub fn grep<W: Write>(args: &GrepArgs, out: &mut W) -> Result<()> {
+    let png = read_png(&args.file_path)?;
+    for (chunk_type, text) in png.find_text(&args.pattern) {
+        writeln!(out, "{}: {}", chunk_type, text)?;
+    }
+    return Ok(())
+}
+
+/// Writes the image data of a PNG file, all of its IDAT chunks joined, to another file
+pub fn extract_idat<W: Write>(args: &ExtractIdatArgs, out: &mut W) -> Result<()> {
+    let png = read_png(&args.file_path)?;
+    let stream = png.image_data();
+    fs::write(&args.output_file, &stream)?;
+
+    writeln!(out, "Extracted {} bytes of image data", stream.len())?;
+    return Ok(())
+}
+
+/// Replaces the image data of a PNG file with the zlib stream in another file and saves the
+/// result to a third one
+pub fn replace_idat<W: Write>(args: &ReplaceIdatArgs, out: &mut W) -> Result<()> {
+    let mut png = read_png(&args.template_file)?;
+    let stream = fs::read(&args.stream_file)?;
+    png.replace_image_data(&stream);
+    write_png(&args.output_file, &png)?;
+
+    writeln!(out, "Replaced the image data with {} bytes", stream.len())?;
+    return Ok(())
+}
+
+/// Totals printed by `count`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CountSummary {
+    /// PNG files that could be read
+    pub scanned: usize,
+    /// PNG files with at least one hidden chunk
+    pub with_hidden_chunks: usize,
+    /// Hidden chunks found across every file
+    pub hidden_chunks: usize,
+}
+
+/// Collects every file with a .png extension under `dir`, following subdirectories
+fn find_png_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_png_files(&path, files)?;
+        } else if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("png")) {
+            files.push(path);
+        }
+    }
+    return Ok(())
+}
+
+/// Counts the hidden chunks of a single file. A chunk is hidden if it has the given type or,
+/// without one, if `is_hidden_chunk` says so. Files that are not PNGs give None.
+fn count_hidden_chunks(path: &Path, chunk_type: Option<&ChunkType>) -> Option<usize> {
+    let png = read_png(path).ok()?;
+    let hidden = png
+        .chunks()
+        .iter()
+        .filter(|c| match chunk_type {
+            Some(t) => c.chunk_type() == t,
+            None => is_hidden_chunk(c),
+        })
+        .count();
+    return Some(hidden)
+}
+
+/// Scans every PNG under a directory in parallel, on rayon's pool of one thread per core
+pub fn count_directory(dir: &Path, chunk_type: Option<&ChunkType>) -> Result<CountSummary> {
+    let mut files: Vec<PathBuf> = vec!();
+    find_png_files(dir, &mut files)?;
+
+    let counts: Vec<Option<usize>> = files.par_iter().map(|f| count_hidden_chunks(f, chunk_type)).collect();
+
+    let mut summary = CountSummary::default();
+    for hidden in counts.into_iter().flatten() {
+        summary.scanned += 1;
+        if hidden > 0 {
+            summary.with_hidden_chunks += 1;
+            summary.hidden_chunks += hidden;
+        }
+    }
+    return Ok(summary)
+}
+
+/// Prints how many PNG files under a directory have hidden chunks
+pub fn count<W: Write>(args: &CountArgs, out: &mut W) -> Result<()> {
+    let summary = count_directory(&args.dir_path, args.chunk_type.as_ref())?;
+
+    if args.json {
+        writeln!(
+            out,
+            "{{ \"scanned\": {}, \"with_hidden_chunks\": {}, \"hidden_chunks\": {} }}",
+            summary.scanned, summary.with_hidden_chunks, summary.hidden_chunks
+        )?;
+    } else {
+        writeln!(out, "PNG files scanned: {}", summary.scanned)?;
+        writeln!(out, "PNG files with hidden chunks: {}", summary.with_hidden_chunks)?;
+        writeln!(out, "Hidden chunks found: {}", summary.hidden_chunks)?;
+    }
+    return Ok(())
+}
+
+const BENCHMARK_WARMUP_RUNS: usize = 2;
+const BENCHMARK_RUNS: usize = 10;
+/// How many times slower than the baseline an operation may be, relative to the reference
+/// image, before `benchmark` fails
+const BENCHMARK_REGRESSION_FACTOR: f64 = 2.0;
+/// Width and height of the image `benchmark` generates to scale its timings by
+const BENCHMARK_REFERENCE_SIZE: usize = 512;
+
+/// Timing of one operation measured by `benchmark`
+#[derive(Debug)]
+pub struct BenchmarkResult {
+    /// Mean time to process a single file
+    pub mean: Duration,
+    /// Standard deviation of the time to process a single file across runs
+    pub std_dev: Duration,
+    /// Bytes of PNG files processed per second, based on the mean
+    pub bytes_per_second: f64,
+}
+
+/// Runs `operation` on every file `BENCHMARK_WARMUP_RUNS` times without measuring, then
+/// `BENCHMARK_RUNS` times measuring each run
+fn measure<F: Fn(&[u8])>(files: &[Vec<u8>], operation: F) -> BenchmarkResult {
+    for _ in 0..BENCHMARK_WARMUP_RUNS {
+        files.iter().for_each(|f| operation(f));
+    }
+    let per_file: Vec<f64> = (0..BENCHMARK_RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            files.iter().for_each(|f| operation(f));
+            start.elapsed().as_secs_f64() / files.len() as f64
+        })
+        .collect();
+
+    let mean = per_file.iter().sum::<f64>() / per_file.len() as f64;
+    let variance = per_file.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / per_file.len() as f64;
+    let mean_file_size = files.iter().map(|f| f.len()).sum::<usize>() as f64 / files.len() as f64;
+    return BenchmarkResult {
+        mean: Duration::from_secs_f64(mean),
+        std_dev: Duration::from_secs_f64(variance.sqrt()),
+        bytes_per_second: mean_file_size / mean,
+    }
+}
+
+/// An RGBA gradient of `BENCHMARK_REFERENCE_SIZE` pixels square, about 1 MB as its image
+/// data is stored without compression
+fn benchmark_reference_png() -> Vec<u8> {
+    let mut scanlines: Vec<u8> = Vec::with_capacity(BENCHMARK_REFERENCE_SIZE * (1 + BENCHMARK_REFERENCE_SIZE * 4));
+    for y in 0..BENCHMARK_REFERENCE_SIZE {
+        // Filter type none
+        scanlines.push(0);
+        for x in 0..BENCHMARK_REFERENCE_SIZE {
+            scanlines.extend([x as u8, y as u8, (x ^ y) as u8, 255]);
+        }
+    }
+    let size = BENCHMARK_REFERENCE_SIZE as u32;
+    // The size and the color type are valid so this can not fail
+    let chunks = vec![
+        Chunk::new_ihdr(size, size, 8, 6).unwrap(),
+        Chunk::new(ChunkType::from_str("IDAT").unwrap(), crate::zlib::compress_stored(&scanlines)).unwrap(),
+        Chunk::new_iend(),
+    ];
+    return Png::from_chunks(chunks).as_bytes()
+}
+
+/// Formats the mean time of each operation, divided by the mean time of the reference, as a
+/// baseline file, e.g. `{ "encode": { "relative_mean": 0.25 }, "decode": { "relative_mean": 0.1 } }`
+fn baseline_to_json(results: &[(&str, BenchmarkResult)], reference: &BenchmarkResult) -> String {
+    let operations: Vec<String> = results
+        .iter()
+        .map(|(name, result)| format!("{}: {{ \"relative_mean\": {} }}", crate::json::quote(name), relative_mean(result, reference)))
+        .collect();
+    return format!("{{ {} }}\n", operations.join(", "))
+}
+
+fn relative_mean(result: &BenchmarkResult, reference: &BenchmarkResult) -> f64 {
+    return result.mean.as_secs_f64() / reference.mean.as_secs_f64()
+}
+
+/// Compares the results with a baseline file, failing if any operation is more than
+/// `BENCHMARK_REGRESSION_FACTOR` times slower. Both are relative to the reference image,
+/// so a baseline saved on a faster or slower machine still applies.
+fn compare_with_baseline<W: Write>(results: &[(&str, BenchmarkResult)], reference: &BenchmarkResult, baseline: &str, out: &mut W) -> Result<()> {
+    let baseline = crate::json::JsonValue::parse(baseline)?;
+    let mut regressions: Vec<String> = vec!();
+    for (name, result) in results {
+        let baseline_mean = baseline
+            .get(name)
+            .and_then(|operation| operation.get("relative_mean"))
+            .and_then(|mean| mean.as_f64())
+            .filter(|mean| *mean > 0.0)
+            .ok_or_else(|| PngMeError::InvalidJson(format!("missing \"{}\".\"relative_mean\"", name)))?;
+        let ratio = relative_mean(result, reference) / baseline_mean;
+        writeln!(out, "{}: {:.2}x the baseline", name, ratio)?;
+        if ratio > BENCHMARK_REGRESSION_FACTOR {
+            regressions.push(format!("{} is {:.2}x slower than the baseline", name, ratio));
+        }
+    }
+    if !regressions.is_empty() {
+        return Err(regressions.join(", ").into())
+    }
+    return Ok(())
+}
+
+/// Measures encoding a message into, and decoding it from, every PNG file under a directory.
+/// Everything happens in memory, the files are never modified. Files that are not valid PNGs
+/// are skipped. The same operations are measured on a generated reference image, and baselines
+/// store times relative to it.
+pub fn benchmark<W: Write>(args: &BenchmarkArgs, out: &mut W) -> Result<()> {
+    let mut paths: Vec<PathBuf> = vec!();
+    find_png_files(&args.dir_path, &mut paths)?;
+    let files: Vec<Vec<u8>> = paths.iter().filter_map(|p| read_png(p).ok()).map(|png| png.as_bytes()).collect();
+    if files.is_empty() {
+        return Err(format!("No PNG files were found in {}", args.dir_path.display()).into())
+    }
+
+    // ruSt and the message are valid so this can not fail
+    let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"pngme benchmark message".to_vec()).unwrap();
+    let encode = |bytes: &[u8]| {
+        let mut png = Png::try_from(bytes).unwrap();
+        png.add_before_iend(chunk.clone());
+        png.as_bytes()
+    };
+    let encoded: Vec<Vec<u8>> = files.iter().map(|f| encode(f)).collect();
+    let results = [
+        ("encode", measure(&files, |bytes| { encode(bytes); })),
+        ("decode", measure(&encoded, |bytes| { Png::try_from(bytes).unwrap().chunk_by_type("ruSt").unwrap(); })),
+    ];
+    let reference = measure(&[benchmark_reference_png()], |bytes| { encode(bytes); });
+
+    writeln!(out, "{} files, {} runs after {} warmup runs", files.len(), BENCHMARK_RUNS, BENCHMARK_WARMUP_RUNS)?;
+    writeln!(out, "{:<10}{:>14}{:>14}{:>14}", "Operation", "Mean", "Std dev", "MB/s")?;
+    for (name, result) in &results {
+        writeln!(
+            out,
+            "{:<10}{:>14}{:>14}{:>14.1}",
+            name,
+            format!("{:.1?}", result.mean),
+            format!("{:.1?}", result.std_dev),
+            result.bytes_per_second / (1024.0 * 1024.0)
+        )?;
+    }
+    writeln!(
+        out,
+        "Reference {}x{} image: {:.1?} to encode",
+        BENCHMARK_REFERENCE_SIZE, BENCHMARK_REFERENCE_SIZE, reference.mean
+    )?;
+
+    if let Some(path) = &args.save_baseline {
+        fs::write(path, baseline_to_json(&results, &reference))?;
+    }
+    if let Some(path) = &args.baseline {
+        let baseline = fs::read_to_string(path).map_err(|e| PngMeError::from(e).with_context(format!("reading {}", path.display())))?;
+        compare_with_baseline(&results, &reference, &baseline, out)?;
+    }
+    return Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use crate::metadata::PngMetadata;
+
+    /// Writes a blank PNG to a file in the temporary directory that is unique to the test
+    fn temp_png(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("pngme-{}-{}.png", std::process::id(), name));
+        fs::write(&path, Png::new_blank(4, 4, 2, 8).unwrap().as_bytes()).unwrap();
+        path
+    }
+
+    fn encode_message(path: &Path, chunk_type: &str, message: &str) {
+        let args = EncodeArgs {
+            file_path: path.to_path_buf(),
+            chunk_type: ChunkType::from_str(chunk_type).unwrap(),
+            message: message.to_string(),
+            output_file: None,
+            watermark: true,
+        };
+        encode(&args).unwrap();
+    }
+
+    fn decode_to_string(path: &Path, chunk_type: Option<&str>) -> Result<String> {
+        let args = DecodeArgs {
+            file_path: path.to_path_buf(),
+            chunk_type: chunk_type.map(|c| ChunkType::from_str(c).unwrap()),
+            all: chunk_type.is_none(),
+        };
+        let mut out: Vec<u8> = vec!();
+        decode(&args, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let path = temp_png("encode-decode");
+        encode_message(&path, "ruSt", "This is a secret message!");
+        let decoded = decode_to_string(&path, Some("ruSt")).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(decoded, "This is a secret message!\n");
+    }
+
+    #[test]
+    fn test_decode_missing_chunk() {
+        let path = temp_png("decode-missing");
+        let decoded = decode_to_string(&path, Some("ruSt"));
+        fs::remove_file(&path).unwrap();
+
+        assert!(decoded.is_err());
+    }
+
+    #[test]
+    fn test_decode_all() {
+        let path = temp_png("decode-all");
+        encode_message(&path, "ruSt", "first");
+        encode_message(&path, "abCd", "second");
+        encode_message(&path, "seCr", "third");
+        let decoded = decode_to_string(&path, None).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // Standard chunks (IHDR, IDAT, IEND) are not listed
+        assert_eq!(decoded, "ruSt: first\nabCd: second\nseCr: third\n");
+    }
+
+    #[test]
+    fn test_decode_all_binary_as_hex() {
+        let path = temp_png("decode-all-hex");
+        let mut png = read_png(&path).unwrap();
+        png.add_before_iend(Chunk::new(ChunkType::from_str("biNa").unwrap(), vec![0xff, 0x00, 0x10]).unwrap());
+        fs::write(&path, png.as_bytes()).unwrap();
+        let decoded = decode_to_string(&path, None).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(decoded, "biNa: ff0010\n");
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let path = temp_png("timestamp");
+        let time = PngTime::parse_rfc3339("2024-06-30T23:59:59+01:00").unwrap();
+        let args = TimestampArgs{file_path: path.clone(), time: Some(time), remove: false};
+        timestamp(&args, &mut std::io::sink()).unwrap();
+        let stamped = read_png(&path).unwrap().modification_time().unwrap();
+
+        let args = TimestampArgs{file_path: path.clone(), time: None, remove: true};
+        timestamp(&args, &mut std::io::sink()).unwrap();
+        let removed = read_png(&path).unwrap().modification_time().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(stamped.unwrap().to_string(), "2024-06-30T22:59:59Z");
+        assert!(removed.is_none());
+    }
+
+    #[test]
+    fn test_timestamp_remove_without_time_leaves_file() {
+        let path = temp_png("timestamp-remove-none");
+        let modified = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+
+        let mut out: Vec<u8> = vec!();
+        let args = TimestampArgs{file_path: path.clone(), time: None, remove: true};
+        timestamp(&args, &mut out).unwrap();
+        let still_modified = fs::metadata(&path).unwrap().modified().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "There was no modification time to remove\n");
+        assert_eq!(still_modified, modified);
+    }
+
+    #[test]
+    fn test_optimize() {
+        let path = temp_png("optimize");
+        let mut png = read_png(&path).unwrap();
+        png.add_before_iend(Chunk::new_watermark("pngme", "0.1.0"));
+        fs::write(&path, png.as_bytes()).unwrap();
+        let original_size = png.file_size();
+
+        let mut out: Vec<u8> = vec!();
+        optimize(&OptimizeArgs{file_path: path.clone()}, &mut out).unwrap();
+        let optimized = read_png(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(optimized.chunk_by_type(Chunk::WATERMARK_CHUNK_TYPE).is_none());
+        assert_eq!(String::from_utf8(out).unwrap(), format!(
+            "Optimized: {} bytes to {} bytes, 1 chunks removed, 0 chunks compressed\n", original_size, optimized.file_size()
+        ));
+    }
+
+    #[test]
+    fn test_touch() {
+        let path = temp_png("touch");
+        let mut out: Vec<u8> = vec!();
+        touch(&TouchArgs{file_path: path.clone()}, &mut out).unwrap();
+        let stamped = read_png(&path).unwrap().modification_time().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(stamped.year >= 2024);
+        assert_eq!(String::from_utf8(out).unwrap(), format!("Modification time set to {}\n", stamped));
+    }
+
+    #[test]
+    fn test_encode_invalid_chunk_type() {
+        let path = temp_png("encode-invalid-type");
+        let before = fs::read(&path).unwrap();
+        let args: Vec<String> = ["encode", path.to_str().unwrap(), "abcd", "message"].iter().map(|a| a.to_string()).collect();
+        let parsed = PngMeArgs::try_from(args.as_slice());
+        // Past the argument parser, encode still refuses to write it
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: ChunkType::from_str("abcd").unwrap(),
+            message: String::from("message"),
+            output_file: None,
+            watermark: true,
+        };
+        let result = encode(&args);
+        let after = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(parsed.is_err());
+        assert!(result.is_err());
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn test_remove() {
+        let path = temp_png("remove");
+        let original = read_png(&path).unwrap();
+        // Without a watermark removing the message gives back the original chunks
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: ChunkType::from_str("ruSt").unwrap(),
+            message: String::from("gone soon"),
+            output_file: None,
+            watermark: false,
+        };
+        encode(&args).unwrap();
+        let args = RemoveArgs {
+            file_path: path.clone(),
+            chunk_type: ChunkType::from_str("ruSt").unwrap(),
+        };
+        remove(&args, &mut std::io::sink()).unwrap();
+        let png = read_png(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(png.chunk_by_type("ruSt").is_none());
+        assert!(png.is_structurally_complete());
+        assert_eq!(png, original);
+    }
+
+    #[test]
+    fn test_count() {
+        let dir = std::env::temp_dir().join(format!("pngme-{}-count", std::process::id()));
+        let nested = dir.join("nested");
+        fs::create_dir_all(&nested).unwrap();
+        let blank = Png::new_blank(4, 4, 2, 8).unwrap().as_bytes();
+        for (i, path) in ["a.png", "b.png", "c.png", "nested/d.png", "nested/e.PNG"].iter().enumerate() {
+            let path = dir.join(path);
+            fs::write(&path, &blank).unwrap();
+            if i < 3 {
+                encode_message(&path, "ruSt", "hidden");
+            }
+        }
+        encode_message(&dir.join("a.png"), "seCr", "another");
+        fs::write(dir.join("notes.txt"), "not a png").unwrap();
+        // Damaged files are skipped like any other file that is not a PNG
+        fs::write(dir.join("truncated.png"), &blank[..blank.len() - 5]).unwrap();
+        fs::write(dir.join("garbage.png"), "not a png either").unwrap();
+
+        let all = count_directory(&dir, None).unwrap();
+        let secr_only = count_directory(&dir, Some(&ChunkType::from_str("seCr").unwrap())).unwrap();
+        let args = CountArgs{dir_path: dir.clone(), chunk_type: None, json: true};
+        let mut out: Vec<u8> = vec!();
+        count(&args, &mut out).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(all, CountSummary{scanned: 5, with_hidden_chunks: 3, hidden_chunks: 4});
+        assert_eq!(secr_only, CountSummary{scanned: 5, with_hidden_chunks: 1, hidden_chunks: 1});
+        let json = crate::json::JsonValue::parse(&String::from_utf8(out).unwrap()).unwrap();
+        assert_eq!(json.get("scanned").and_then(|v| v.as_u64()), Some(5));
+        assert_eq!(json.get("with_hidden_chunks").and_then(|v| v.as_u64()), Some(3));
+        assert_eq!(json.get("hidden_chunks").and_then(|v| v.as_u64()), Some(4));
+    }
+
+    #[test]
+    fn test_encode_watermark() {
+        let path = temp_png("watermark");
+        encode_message(&path, "ruSt", "first");
+        encode_message(&path, "ruSt", "second");
+        let png = read_png(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let watermarks: Vec<&Chunk> = png.chunks().iter().filter(|c| c.chunk_type().to_string() == "wMRK").collect();
+        assert_eq!(watermarks.len(), 1);
+        let (app_name, version) = watermarks[0].parse_watermark().unwrap();
+        assert_eq!(app_name, "pngme");
+        assert_eq!(version, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn test_encode_no_watermark() {
+        let path = temp_png("no-watermark");
+        let args = EncodeArgs {
+            file_path: path.clone(),
+            chunk_type: ChunkType::from_str("ruSt").unwrap(),
+            message: String::from("unsigned"),
+            output_file: None,
+            watermark: false,
+        };
+        encode(&args).unwrap();
+        let png = read_png(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(png.chunk_by_type("ruSt").is_some());
+        assert!(png.chunk_by_type("wMRK").is_none());
+    }
+
+    #[test]
+    fn test_strip_comments() {
+        let path = temp_png("strip-comments");
+        encode_message(&path, "tEXt", "Author\0someone");
+        encode_message(&path, "ruSt", "kept");
+        let args = StripCommentsArgs{file_path: path.clone()};
+        let mut out: Vec<u8> = vec!();
+        strip_comments(&args, &mut out).unwrap();
+        let png = read_png(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "Removed 1 text chunks\n");
+        assert!(png.chunk_by_type("tEXt").is_none());
+        assert!(png.chunk_by_type("ruSt").is_some());
+    }
+
+    #[test]
+    fn test_extract_replace_idat() {
+        let path = temp_png("idat");
+        let stream_path = path.with_extension("zlib");
+        let output_path = path.with_extension("out.png");
+        let args = ExtractIdatArgs{file_path: path.clone(), output_file: stream_path.clone()};
+        extract_idat(&args, &mut std::io::sink()).unwrap();
+        let args = ReplaceIdatArgs {
+            template_file: path.clone(),
+            stream_file: stream_path.clone(),
+            output_file: output_path.clone(),
+        };
+        replace_idat(&args, &mut std::io::sink()).unwrap();
+        let original = fs::read(&path).unwrap();
+        let replaced = fs::read(&output_path).unwrap();
+        let stream = fs::read(&stream_path).unwrap();
+        for p in [&path, &stream_path, &output_path] {
+            fs::remove_file(p).unwrap();
+        }
+
+        assert_eq!(stream, Png::try_from(original.as_ref()).unwrap().image_data());
+        assert_eq!(replaced, original);
+    }
+
+    #[test]
+    fn test_info() {
+        let path = temp_png("info");
+        let mut out: Vec<u8> = vec!();
+        info(&InfoArgs{file_path: path.clone()}, &mut out).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Size: 4x4\nBit depth: 8\nColor type: 2\nInterlacing: none\n"
+        );
+    }
+
+    #[test]
+    fn test_grep() {
+        let path = temp_png("grep");
+        encode_message(&path, "tEXt", "Author\0someone");
+        encode_message(&path, "tEXt", "Software\0pngme");
+        let grep_to_string = |pattern: &str| {
+            let mut out: Vec<u8> = vec!();
+            grep(&GrepArgs{file_path: path.clone(), pattern: pattern.to_string()}, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let found = grep_to_string("some");
+        let missing = grep_to_string("nobody");
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(found, "tEXt: Author: someone\n");
+        assert_eq!(missing, "");
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let path = temp_png("hexdump");
+        let mut out: Vec<u8> = vec!();
+        hexdump(&HexdumpArgs{file_path: path.clone()}, &mut out).unwrap();
+        let png = read_png(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), png.to_hexdump());
+    }
+
+    #[test]
+    fn test_metadata() {
+        let path = temp_png("metadata");
+        encode_message(&path, "tEXt", "Author\0someone");
+        let metadata_to_string = |json: bool| {
+            let mut out: Vec<u8> = vec!();
+            metadata(&MetadataArgs{file_path: path.clone(), json}, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let text = metadata_to_string(false);
+        let json = metadata_to_string(true);
+        let png = read_png(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(text, "Size: 4x4\nBit depth: 8\nColor type: 2\nAuthor: someone\nProgressive: false\nsRGB: false\n");
+        assert_eq!(PngMetadata::from_json_str(&json).unwrap(), png.extract_metadata().unwrap());
+    }
+
+    #[test]
+    fn test_report() {
+        let path = temp_png("report");
+        let csv_path = path.with_extension("csv");
+        encode_message(&path, "ruSt", "hello");
+        let mut printed: Vec<u8> = vec!();
+        report(&ReportArgs{file_path: path.clone(), output_file: None}, &mut printed).unwrap();
+        let mut out: Vec<u8> = vec!();
+        report(&ReportArgs{file_path: path.clone(), output_file: Some(csv_path.clone())}, &mut out).unwrap();
+        let written = fs::read_to_string(&csv_path).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&csv_path).unwrap();
+
+        let printed = String::from_utf8(printed).unwrap();
+        assert_eq!(printed, written);
+        // Header, IHDR, IDAT, ruSt, wMRK and IEND
+        assert_eq!(printed.lines().count(), 6);
+        assert!(printed.lines().nth(3).unwrap().starts_with("2,ruSt,5,"));
+        assert!(String::from_utf8(out).unwrap().starts_with("Wrote a report of 5 chunks to "));
+    }
+
+    #[test]
+    fn test_stats() {
+        let path = temp_png("stats");
+        let stats_to_string = |detail: bool| {
+            let mut out: Vec<u8> = vec!();
+            stats(&StatsArgs{file_path: path.clone(), detail}, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let summary = stats_to_string(false);
+        let detail = stats_to_string(true);
+        let png = read_png(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(summary, format!("All: {}\n", png.chunk_statistics()));
+        let lines: Vec<&str> = detail.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1], "IHDR: 1 chunks, 13 bytes, min 13, max 13, mean 13.00, std dev 0.00");
+        assert!(lines[2].starts_with("IDAT: 1 chunks"));
+        assert_eq!(lines[3], "IEND: 1 chunks, 0 bytes, min 0, max 0, mean 0.00, std dev 0.00");
+    }
+
+    #[test]
+    fn test_benchmark() {
+        let dir = std::env::temp_dir().join(format!("pngme-{}-benchmark", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let bytes = Png::new_blank(16, 16, 6, 8).unwrap().as_bytes();
+        fs::write(dir.join("blank.png"), &bytes).unwrap();
+        fs::write(dir.join("truncated.png"), &bytes[..bytes.len() - 20]).unwrap();
+        let args = |baseline: Option<PathBuf>, save_baseline: Option<PathBuf>| {
+            BenchmarkArgs{dir_path: dir.clone(), baseline, save_baseline}
+        };
+        let saved = dir.join("baseline.json");
+        let slow = dir.join("slow.json");
+        fs::write(&slow, "{ \"encode\": { \"relative_mean\": 0.000001 }, \"decode\": { \"relative_mean\": 0.000001 } }").unwrap();
+        let mut out: Vec<u8> = vec!();
+        let result = benchmark(&args(None, Some(saved.clone())), &mut out);
+        let saved_json = fs::read_to_string(&saved).unwrap();
+        let regression = benchmark(&args(Some(slow), None), &mut std::io::sink());
+        let missing_baseline = benchmark(&args(Some(dir.join("missing.json")), None), &mut std::io::sink());
+        let empty = benchmark(&BenchmarkArgs{dir_path: dir.join("missing"), baseline: None, save_baseline: None}, &mut std::io::sink());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_ok());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("1 files, 10 runs after 2 warmup runs\n"));
+        assert!(out.contains("\nencode "));
+        assert!(out.contains("\ndecode "));
+        assert!(out.contains("\nReference 512x512 image: "));
+        let saved_json = crate::json::JsonValue::parse(&saved_json).unwrap();
+        assert!(saved_json.get("encode").and_then(|e| e.get("relative_mean")).and_then(|m| m.as_f64()).is_some_and(|m| m > 0.0));
+        assert!(saved_json.get("decode").and_then(|d| d.get("relative_mean")).and_then(|m| m.as_f64()).is_some_and(|m| m > 0.0));
+        assert!(regression.unwrap_err().to_string().contains("slower than the baseline"));
+        assert!(missing_baseline.is_err());
+        assert!(empty.is_err());
+    }
+
+    #[test]
+    fn test_compare_with_baseline() {
+        let result = |mean_ns: u64| BenchmarkResult{
+            mean: Duration::from_nanos(mean_ns),
+            std_dev: Duration::ZERO,
+            bytes_per_second: 0.0,
+        };
+        let results = [("encode", result(1500)), ("decode", result(500))];
+        let baseline = baseline_to_json(&[("encode", result(1000)), ("decode", result(1000))], &result(4000));
+        assert_eq!(baseline, "{ \"encode\": { \"relative_mean\": 0.25 }, \"decode\": { \"relative_mean\": 0.25 } }\n");
+        let mut out: Vec<u8> = vec!();
+        assert!(compare_with_baseline(&results, &result(4000), &baseline, &mut out).is_ok());
+        assert_eq!(String::from_utf8(out).unwrap(), "encode: 1.50x the baseline\ndecode: 0.50x the baseline\n");
+
+        // A machine twice as fast runs everything, the reference included, in half the time
+        let halved = [("encode", result(750)), ("decode", result(250))];
+        let mut out: Vec<u8> = vec!();
+        assert!(compare_with_baseline(&halved, &result(2000), &baseline, &mut out).is_ok());
+        assert_eq!(String::from_utf8(out).unwrap(), "encode: 1.50x the baseline\ndecode: 0.50x the baseline\n");
+
+        let baseline = baseline_to_json(&[("encode", result(500)), ("decode", result(1000))], &result(4000));
+        let error = compare_with_baseline(&results, &result(4000), &baseline, &mut std::io::sink()).unwrap_err();
+        assert_eq!(error.to_string(), "encode is 3.00x slower than the baseline");
+        let missing = "{ \"encode\": { \"relative_mean\": 0.25 } }";
+        assert!(compare_with_baseline(&results, &result(4000), missing, &mut std::io::sink()).is_err());
+        assert!(compare_with_baseline(&results, &result(4000), "not json", &mut std::io::sink()).is_err());
+    }
+}
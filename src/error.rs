@@ -0,0 +1,164 @@
+use std::fmt;
+
+/// Errors returned when reading, building or modifying a `Png` and its `Chunk`s
+#[derive(Debug)]
+pub enum PngMeError {
+    /// An argument given to a function is not acceptable
+    InvalidInput(String),
+    /// The data of a chunk does not follow the layout its type requires
+    InvalidChunkData(String),
+    /// No chunk of the given type was found
+    ChunkNotFound(String),
+    /// A zlib stream could not be decompressed
+    DecompressionFailed(String),
+    /// The given text is not valid JSON or does not describe a `Png`
+    InvalidJson(String),
+    /// The given bytes are not valid CBOR or do not describe a `Png`
+    InvalidCbor(String),
+    /// The given text is not valid hexadecimal
+    InvalidHex(String),
+    /// The given text is not valid base64
+    InvalidBase64(String),
+    /// A chunk type byte is not an ASCII letter, or it is the third byte and it is lowercase
+    /// (the reserved bit is set)
+    InvalidChunkType {
+        byte_index: usize,
+        byte_value: u8,
+    },
+    /// A sequence of bytes does not have the length required
+    InvalidLength {
+        expected: usize,
+        actual: usize,
+    },
+    /// The data given for a chunk is longer than its 4 byte length field can describe
+    DataTooLarge(usize),
+    /// The CRC stored for a chunk does not match the one computed from its type and data
+    CrcMismatch {
+        index: usize,
+        chunk_type: String,
+        expected: u32,
+        actual: u32,
+    },
+    /// A file is already larger than the size it was asked to grow to
+    AlreadyTooLarge {
+        size: u64,
+        target: u64,
+    },
+    /// A signed message does not match its tag: it was modified or the key is wrong
+    AuthenticationFailed,
+    /// Reading or writing failed
+    Io(std::io::Error),
+    /// Another error with a description of what was being done when it happened,
+    /// such as the file being read. See `PngMeError::with_context`. Only the description is
+    /// displayed, the error itself is the `source`.
+    Context {
+        message: String,
+        source: Box<PngMeError>,
+    },
+}
+
+impl PngMeError {
+    /// Wraps this error in `PngMeError::Context` so it tells where it happened
+    pub fn with_context(self, ctx: String) -> PngMeError {
+        return PngMeError::Context{message: ctx, source: Box::new(self)}
+    }
+}
+
+impl fmt::Display for PngMeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PngMeError::InvalidInput(m) => write!(f, "InvalidInput: {}", m),
+            PngMeError::InvalidChunkData(m) => write!(f, "InvalidChunkData: {}", m),
+            PngMeError::ChunkNotFound(t) => write!(f, "ChunkNotFound: no chunk of type \"{}\"", t),
+            PngMeError::DecompressionFailed(m) => write!(f, "DecompressionFailed: {}", m),
+            PngMeError::InvalidJson(m) => write!(f, "InvalidJson: {}", m),
+            PngMeError::InvalidCbor(m) => write!(f, "InvalidCbor: {}", m),
+            PngMeError::InvalidHex(m) => write!(f, "InvalidHex: {}", m),
+            PngMeError::InvalidBase64(m) => write!(f, "InvalidBase64: {}", m),
+            PngMeError::InvalidChunkType { byte_index, byte_value } => {
+                let reason = if byte_value.is_ascii_alphabetic() { "sets the reserved bit" } else { "is not an ASCII letter" };
+                write!(f, "InvalidChunkType: byte {} ({:#04x}) {}", byte_index, byte_value, reason)
+            }
+            PngMeError::InvalidLength { expected, actual } => {
+                write!(f, "InvalidLength: expected {} bytes, got {}", expected, actual)
+            }
+            PngMeError::DataTooLarge(len) => write!(f, "DataTooLarge: {} bytes do not fit in a single chunk", len),
+            PngMeError::CrcMismatch { index, chunk_type, expected, actual } => write!(
+                f,
+                "CrcMismatch: expected {:#010X}, got {:#010X} in chunk at index {} (type \"{}\")",
+                expected, actual, index, chunk_type
+            ),
+            PngMeError::AlreadyTooLarge { size, target } => {
+                write!(f, "AlreadyTooLarge: {} bytes is already larger than the target of {} bytes", size, target)
+            }
+            PngMeError::AuthenticationFailed => write!(f, "AuthenticationFailed: the message does not match its signature"),
+            PngMeError::Io(e) => write!(f, "Io: {}", e),
+            // The wrapped error is returned by `source` instead, so it is not repeated here
+            PngMeError::Context { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for PngMeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            PngMeError::Context { source, .. } => Some(source.as_ref()),
+            PngMeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PngMeError {
+    fn from(e: std::io::Error) -> Self {
+        return PngMeError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    fn crc_mismatch() -> PngMeError {
+        return PngMeError::CrcMismatch {
+            index: 3,
+            chunk_type: String::from("tEXt"),
+            expected: 0xDEADBEEF,
+            actual: 0x12345678,
+        }
+    }
+
+    #[test]
+    fn test_display_crc_mismatch() {
+        assert_eq!(
+            crc_mismatch().to_string(),
+            "CrcMismatch: expected 0xDEADBEEF, got 0x12345678 in chunk at index 3 (type \"tEXt\")"
+        );
+    }
+
+    #[test]
+    fn test_display_chunk_not_found() {
+        let message = PngMeError::ChunkNotFound(String::from("ruSt")).to_string();
+        assert!(message.starts_with("ChunkNotFound"));
+        assert!(message.contains("\"ruSt\""));
+    }
+
+    #[test]
+    fn test_display_invalid_chunk_type() {
+        let error = PngMeError::InvalidChunkType{byte_index: 1, byte_value: b'5'};
+        assert_eq!(error.to_string(), "InvalidChunkType: byte 1 (0x35) is not an ASCII letter");
+        let error = PngMeError::InvalidChunkType{byte_index: 2, byte_value: b's'};
+        assert_eq!(error.to_string(), "InvalidChunkType: byte 2 (0x73) sets the reserved bit");
+    }
+
+    #[test]
+    fn test_with_context() {
+        let error = crc_mismatch().with_context(String::from("reading dice.png"));
+        assert_eq!(error.to_string(), "reading dice.png");
+
+        let source = error.source().unwrap();
+        assert_eq!(source.to_string(), crc_mismatch().to_string());
+        assert!(source.source().is_none());
+    }
+}
@@ -0,0 +1,42 @@
+/// Formats the bytes like `xxd` does: 16 bytes per row, each row made of the offset, the bytes
+/// in hex in groups of two and the bytes as ASCII, with `.` for anything that is not printable.
+/// `00000000: 8950 4e47 0d0a 1a0a 0000 000d 4948 4452  .PNG........IHDR`
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut s = String::new();
+
+    for (row, line) in bytes.chunks(16).enumerate() {
+        let mut hex = String::with_capacity(39);
+        for (i, b) in line.iter().enumerate() {
+            if i > 0 && i % 2 == 0 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x}", b));
+        }
+        let ascii: String = line
+            .iter()
+            .map(|b| if (0x20..=0x7e).contains(b) { *b as char } else { '.' })
+            .collect();
+        s.push_str(&format!("{:08x}: {:<39}  {}\n", row * 16, hex, ascii));
+    }
+    return s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hexdump() {
+        let bytes: Vec<u8> = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDRabc".to_vec();
+        assert_eq!(
+            hexdump(&bytes),
+            "00000000: 8950 4e47 0d0a 1a0a 0000 000d 4948 4452  .PNG........IHDR\n\
+             00000010: 6162 63                                  abc\n"
+        );
+    }
+
+    #[test]
+    fn test_hexdump_empty() {
+        assert_eq!(hexdump(b""), "");
+    }
+}
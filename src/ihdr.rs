@@ -0,0 +1,114 @@
+use std::convert::TryFrom;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+
+/// The decoded contents of an IHDR chunk. See the PNG spec for more details.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.IHDR
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IhdrData {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub compression_method: u8,
+    pub filter_method: u8,
+    pub interlace_method: u8,
+}
+
+impl IhdrData {
+    /// Length of the data of every IHDR chunk
+    pub const LENGTH: usize = 13;
+
+    /// Returns true if `bit_depth` is allowed for `color_type` by the table in the PNG spec
+    pub fn is_valid_combination(color_type: u8, bit_depth: u8) -> bool {
+        let allowed: &[u8] = match color_type {
+            0 => &[1, 2, 4, 8, 16], // Grayscale
+            2 => &[8, 16],          // Truecolor
+            3 => &[1, 2, 4, 8],     // Indexed
+            4 => &[8, 16],          // Grayscale with alpha
+            6 => &[8, 16],          // Truecolor with alpha
+            _ => &[],
+        };
+        return allowed.contains(&bit_depth)
+    }
+
+    /// Returns the 13 bytes of data of the IHDR chunk described by this struct
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut v: Vec<u8> = Vec::with_capacity(IhdrData::LENGTH);
+        v.extend(self.width.to_be_bytes());
+        v.extend(self.height.to_be_bytes());
+        v.extend([
+            self.bit_depth,
+            self.color_type,
+            self.compression_method,
+            self.filter_method,
+            self.interlace_method,
+        ]);
+        return v
+    }
+}
+
+impl TryFrom<&Chunk> for IhdrData {
+    type Error = PngMeError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if *chunk.chunk_type() != ChunkType::IHDR {
+            return Err(PngMeError::InvalidChunkData(format!("expected an IHDR chunk, got {}", chunk.chunk_type())))
+        }
+        let d = chunk.data();
+        if d.len() != IhdrData::LENGTH {
+            return Err(PngMeError::InvalidChunkData(format!("IHDR data is {} bytes long instead of 13", d.len())))
+        }
+
+        return Ok(IhdrData {
+            width: u32::from_be_bytes([d[0], d[1], d[2], d[3]]),
+            height: u32::from_be_bytes([d[4], d[5], d[6], d[7]]),
+            bit_depth: d[8],
+            color_type: d[9],
+            compression_method: d[10],
+            filter_method: d[11],
+            interlace_method: d[12],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_combinations() {
+        assert!(IhdrData::is_valid_combination(0, 1));
+        assert!(IhdrData::is_valid_combination(0, 16));
+        assert!(IhdrData::is_valid_combination(3, 8));
+        assert!(IhdrData::is_valid_combination(6, 16));
+        assert!(!IhdrData::is_valid_combination(2, 4));
+        assert!(!IhdrData::is_valid_combination(3, 16));
+        assert!(!IhdrData::is_valid_combination(1, 8));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ihdr = IhdrData {
+            width: 640,
+            height: 480,
+            bit_depth: 8,
+            color_type: 6,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: 1,
+        };
+        let chunk = Chunk::new(ChunkType::IHDR, ihdr.to_bytes()).unwrap();
+        assert_eq!(IhdrData::try_from(&chunk).unwrap(), ihdr);
+    }
+
+    #[test]
+    fn test_invalid_chunk() {
+        let chunk = Chunk::new(ChunkType::IHDR, vec![0; 12]).unwrap();
+        assert!(IhdrData::try_from(&chunk).is_err());
+        let chunk = Chunk::new(ChunkType::IDAT, vec![0; 13]).unwrap();
+        assert!(IhdrData::try_from(&chunk).is_err());
+    }
+}
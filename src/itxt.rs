@@ -0,0 +1,138 @@
+use std::convert::TryFrom;
+
+use crate::chunk::Chunk;
+use crate::error::PngMeError;
+use crate::zlib;
+
+/// The decoded contents of an iTXt chunk, international text in UTF-8. See the PNG spec for more details.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.iTXt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItxtData {
+    /// 1 to 79 Latin-1 characters, like the keywords of tEXt
+    pub keyword: String,
+    /// Whether the text is stored compressed with zlib
+    pub compressed: bool,
+    /// Language of the text as an RFC 1766 tag such as `zh-cn`, or empty if unknown
+    pub language_tag: String,
+    /// The keyword translated into the language of the text
+    pub translated_keyword: String,
+    pub text: String,
+}
+
+impl ItxtData {
+    /// Returns the data of the iTXt chunk described by this struct:
+    /// `keyword\0`, compression flag and method, `language_tag\0translated_keyword\0` and the text
+    pub fn to_bytes(&self) -> Result<Vec<u8>, PngMeError> {
+        let keyword: Vec<u8> = self.keyword.chars()
+            .map(|ch| u8::try_from(ch).ok().filter(|b| *b != 0))
+            .collect::<Option<Vec<u8>>>()
+            .filter(|k| (1..=79).contains(&k.len()))
+            .ok_or_else(|| PngMeError::InvalidInput(format!("invalid iTXt keyword \"{}\"", self.keyword)))?;
+        if !self.language_tag.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+            return Err(PngMeError::InvalidInput(format!("invalid language tag \"{}\"", self.language_tag)))
+        }
+        if self.translated_keyword.contains('\0') {
+            return Err(PngMeError::InvalidInput(String::from("the translated keyword can not contain a null character")))
+        }
+
+        let mut data = keyword;
+        data.push(0);
+        // Compression method 0, zlib, is the only one
+        data.extend([self.compressed as u8, 0]);
+        data.extend(self.language_tag.as_bytes());
+        data.push(0);
+        data.extend(self.translated_keyword.as_bytes());
+        data.push(0);
+        match self.compressed {
+            true => data.extend(zlib::compress(self.text.as_bytes(), 9)?),
+            false => data.extend(self.text.as_bytes()),
+        }
+        return Ok(data)
+    }
+}
+
+impl TryFrom<&Chunk> for ItxtData {
+    type Error = PngMeError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if chunk.chunk_type().to_string() != "iTXt" {
+            return Err(PngMeError::InvalidChunkData(format!("expected an iTXt chunk, got {}", chunk.chunk_type())))
+        }
+        let invalid = |message: &str| PngMeError::InvalidChunkData(format!("iTXt {}", message));
+
+        let (keyword, rest) = chunk.data().split_at(chunk.data().iter().position(|b| *b == 0).ok_or_else(|| invalid("has no keyword separator"))?);
+        let (compressed, rest) = match rest.get(1..3) {
+            Some([flag, 0]) if *flag <= 1 => (*flag == 1, &rest[3..]),
+            Some(_) => return Err(invalid("has an unknown compression flag or method")),
+            None => return Err(invalid("is too short")),
+        };
+        let mut fields = rest.splitn(3, |b| *b == 0);
+        let (language_tag, translated_keyword, text) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(l), Some(t), Some(text)) => (l, t, text),
+            _ => return Err(invalid("is missing the language tag or translated keyword")),
+        };
+        let text = match compressed {
+            true => zlib::decompress(text)?,
+            false => text.to_vec(),
+        };
+        let utf8 = |bytes: Vec<u8>, field: &str| String::from_utf8(bytes).map_err(|_| invalid(&format!("{} is not valid UTF-8", field)));
+
+        return Ok(ItxtData {
+            keyword: keyword.iter().map(|b| *b as char).collect(),
+            compressed,
+            language_tag: utf8(language_tag.to_vec(), "language tag")?,
+            translated_keyword: utf8(translated_keyword.to_vec(), "translated keyword")?,
+            text: utf8(text, "text")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn itxt(data: &[u8]) -> Chunk {
+        return Chunk::new(ChunkType::from_str("iTXt").unwrap(), data.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_uncompressed_layout() {
+        let data = ItxtData {
+            keyword: String::from("Title"),
+            compressed: false,
+            language_tag: String::from("fr"),
+            translated_keyword: String::from("Titre"),
+            text: String::from("caf\u{e9}"),
+        };
+        assert_eq!(data.to_bytes().unwrap(), b"Title\0\0\0fr\0Titre\0caf\xC3\xA9".to_vec());
+        assert_eq!(ItxtData::try_from(&itxt(&data.to_bytes().unwrap())).unwrap(), data);
+    }
+
+    #[test]
+    fn test_invalid_fields() {
+        let mut data = ItxtData {
+            keyword: String::new(),
+            compressed: false,
+            language_tag: String::new(),
+            translated_keyword: String::new(),
+            text: String::new(),
+        };
+        assert!(data.to_bytes().is_err());
+        data.keyword = String::from("Title");
+        assert!(data.to_bytes().is_ok());
+        data.language_tag = String::from("fr\0");
+        assert!(data.to_bytes().is_err());
+    }
+
+    #[test]
+    fn test_invalid_chunk() {
+        assert!(ItxtData::try_from(&itxt(b"Title")).is_err());
+        assert!(ItxtData::try_from(&itxt(b"Title\0\x02\0\0\0text")).is_err());
+        assert!(ItxtData::try_from(&itxt(b"Title\0\0\0fr")).is_err());
+        assert!(ItxtData::try_from(&itxt(b"Title\0\x01\0\0\0not zlib")).is_err());
+        let text = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Title\0\0\0\0\0text".to_vec()).unwrap();
+        assert!(ItxtData::try_from(&text).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use crate::error::PngMeError;
+
+/// A parsed JSON value. Only what is needed to read back the documents this crate writes.
+/// https://www.json.org/json-en.html
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    /// Keys are kept in the order they were found
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Parses a whole JSON document
+    pub fn parse(s: &str) -> Result<JsonValue, PngMeError> {
+        let mut parser = Parser{bytes: s.as_bytes(), pos: 0};
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != parser.bytes.len() {
+            return Err(parser.error("trailing characters"))
+        }
+        return Ok(value)
+    }
+
+    /// Returns the value stored under `key` if this is an object
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns the number if it is a non negative integer
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            JsonValue::Number(n) if *n >= 0.0 && n.fract() == 0.0 && *n <= u64::MAX as f64 => Some(*n as u64),
+            _ => None,
+        }
+    }
+}
+
+/// Escapes a string and surrounds it with quotes so it can be written as a JSON string
+pub fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    return quoted
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> PngMeError {
+        return PngMeError::InvalidJson(format!("{} at byte {}", message, self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        return self.bytes.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), PngMeError> {
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+        self.pos += 1;
+        return Ok(())
+    }
+
+    fn literal(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, PngMeError> {
+        if !self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            return Err(self.error("unexpected character"))
+        }
+        self.pos += word.len();
+        return Ok(value)
+    }
+
+    fn value(&mut self) -> Result<JsonValue, PngMeError> {
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(JsonValue::String(self.string()?)),
+            Some(b't') => self.literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.literal("false", JsonValue::Bool(false)),
+            Some(b'n') => self.literal("null", JsonValue::Null),
+            Some(b) if b == b'-' || b.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<JsonValue, PngMeError> {
+        self.expect(b'{')?;
+        let mut members: Vec<(String, JsonValue)> = vec!();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(members))
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a key"))
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            members.push((key, self.value()?));
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(members))
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<JsonValue, PngMeError> {
+        self.expect(b'[')?;
+        let mut values: Vec<JsonValue> = vec!();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(values))
+        }
+        loop {
+            values.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(values))
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, PngMeError> {
+        self.expect(b'"')?;
+        let mut s: Vec<u8> = vec!();
+        loop {
+            let b = match self.bytes.get(self.pos) {
+                Some(b) => *b,
+                None => return Err(self.error("unterminated string")),
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.bytes.get(self.pos) {
+                        Some(e) => *e,
+                        None => return Err(self.error("unterminated string")),
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        b'"' | b'\\' | b'/' => s.push(escaped),
+                        b'b' => s.push(8),
+                        b'f' => s.push(12),
+                        b'n' => s.push(b'\n'),
+                        b'r' => s.push(b'\r'),
+                        b't' => s.push(b'\t'),
+                        b'u' => {
+                            let c = self.unicode_escape()?;
+                            s.extend(c.to_string().bytes());
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                _ => s.push(b),
+            }
+        }
+        return String::from_utf8(s).map_err(|_| self.error("string is not valid UTF-8"))
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, PngMeError> {
+        let first = self.hex4()?;
+        // Characters outside the basic plane are written as a surrogate pair
+        if (0xD800..0xDC00).contains(&first) && self.bytes[self.pos..].starts_with(b"\\u") {
+            self.pos += 2;
+            let second = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&second) {
+                return Err(self.error("high surrogate not followed by a low surrogate"))
+            }
+            let code = 0x10000 + ((first - 0xD800) << 10) + (second - 0xDC00);
+            return char::from_u32(code).ok_or_else(|| self.error("invalid surrogate pair"))
+        }
+        if (0xDC00..0xE000).contains(&first) {
+            return Err(self.error("low surrogate without a high surrogate"))
+        }
+        return char::from_u32(first).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, PngMeError> {
+        let digits = self.bytes.get(self.pos..self.pos + 4).ok_or_else(|| self.error("unterminated escape"))?;
+        let digits = std::str::from_utf8(digits).map_err(|_| self.error("invalid unicode escape"))?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        return Ok(value)
+    }
+
+    fn number(&mut self) -> Result<JsonValue, PngMeError> {
+        let start = self.pos;
+        while self.pos < self.bytes.len() && matches!(self.bytes[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+            self.pos += 1;
+        }
+        // The slice only contains ASCII so it is valid UTF-8
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap();
+        return text.parse::<f64>().map(JsonValue::Number).map_err(|_| self.error("invalid number"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_document() {
+        let value = JsonValue::parse(r#"{ "a": [1, 2.5, -3], "b": "x\"y", "c": true, "d": null }"#).unwrap();
+        assert_eq!(value.get("a").unwrap().as_array().unwrap().len(), 3);
+        assert_eq!(value.get("a").unwrap().as_array().unwrap()[0].as_u64(), Some(1));
+        assert_eq!(value.get("b").unwrap().as_str(), Some("x\"y"));
+        assert_eq!(value.get("c"), Some(&JsonValue::Bool(true)));
+        assert_eq!(value.get("d"), Some(&JsonValue::Null));
+        assert!(value.get("e").is_none());
+    }
+
+    #[test]
+    fn test_parse_unicode_escape() {
+        let value = JsonValue::parse(r#""\u00e9\ud83d\ude00""#).unwrap();
+        assert_eq!(value.as_str(), Some("é😀"));
+    }
+
+    #[test]
+    fn test_parse_invalid_surrogates() {
+        // A high surrogate followed by an escape that is not a low surrogate
+        let error = JsonValue::parse(r#""\ud83d\u0041""#).unwrap_err();
+        assert!(error.to_string().contains("high surrogate not followed by a low surrogate"));
+        assert!(JsonValue::parse(r#""\ud83d\ud83d""#).is_err());
+        // A lone high surrogate and a lone low surrogate
+        assert!(JsonValue::parse(r#""\ud83d""#).is_err());
+        assert!(JsonValue::parse(r#""\ude00""#).unwrap_err().to_string().contains("low surrogate without a high surrogate"));
+        assert!(JsonValue::parse(r#""\ude00\ud83d""#).is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(JsonValue::parse("{ \"a\": }").is_err());
+        assert!(JsonValue::parse("[1, 2").is_err());
+        assert!(JsonValue::parse("\"abc").is_err());
+        assert!(JsonValue::parse("{} x").is_err());
+    }
+
+    #[test]
+    fn test_quote_round_trip() {
+        let s = "line\n\"quoted\"\t\\ \u{1}";
+        let value = JsonValue::parse(&quote(s)).unwrap();
+        assert_eq!(value.as_str(), Some(s));
+    }
+}
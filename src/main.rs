@@ -1,21 +1,44 @@
+// The library surface is larger than what the binary uses and the code base
+// prefers explicit returns.
+#![allow(clippy::needless_return)]
+
 mod args;
+mod background;
+mod base64;
 mod chunk;
 mod chunk_type;
 mod commands;
+mod error;
+mod hexdump;
+mod ihdr;
+mod itxt;
+mod json;
+mod metadata;
 mod png;
+mod png_time;
+mod transparency;
+mod zlib;
 
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
+use std::convert::TryFrom;
 use std::env; // https://doc.rust-lang.org/book/ch12-01-accepting-command-line-arguments.html
+use std::io;
+use std::process;
 
+use args::PngMeArgs;
 
-// https://jrdngr.github.io/pngme_book/chapter_4.html#chapter-4-command-line-argumentss
-
-
-
-
-
+// https://jrdngr.github.io/pngme_book/chapter_4.html#chapter-4-command-line-arguments
 fn main() -> Result<()> {
-    todo!()
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = match PngMeArgs::try_from(args.as_slice()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    return commands::run(args, &mut io::stdout())
 }
@@ -0,0 +1,146 @@
+use std::str::FromStr;
+
+use crate::error::PngMeError;
+use crate::json::{self, JsonValue};
+use crate::png_time::PngTime;
+
+/// Everything `Png::extract_metadata` gathers about an image in one place
+#[derive(Debug, Clone, PartialEq)]
+pub struct PngMetadata {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    /// Gamma stored in gAMA, already divided by 100000
+    pub gamma: Option<f64>,
+    pub modification_time: Option<PngTime>,
+    /// Keyword and text of every tEXt, zTXt and iTXt chunk in the order they appear
+    pub text_entries: Vec<(String, String)>,
+    pub is_progressive: bool,
+    pub has_srgb: bool,
+}
+
+impl PngMetadata {
+    /// Returns this metadata as a JSON object. Missing gamma and time are written as `null`
+    /// and the time uses the RFC 3339 format.
+    pub fn to_json_string(&self) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| String::from("null"));
+        let text_entries: Vec<String> = self
+            .text_entries
+            .iter()
+            .map(|(keyword, text)| format!("\n    {{ \"keyword\": {}, \"text\": {} }}", json::quote(keyword), json::quote(text)))
+            .collect();
+
+        let mut s = String::from("{\n");
+        s.push_str(&format!("  \"width\": {},\n", self.width));
+        s.push_str(&format!("  \"height\": {},\n", self.height));
+        s.push_str(&format!("  \"bit_depth\": {},\n", self.bit_depth));
+        s.push_str(&format!("  \"color_type\": {},\n", self.color_type));
+        s.push_str(&format!("  \"gamma\": {},\n", optional(self.gamma.map(|g| g.to_string()))));
+        s.push_str(&format!(
+            "  \"modification_time\": {},\n",
+            optional(self.modification_time.map(|t| json::quote(&t.to_string())))
+        ));
+        s.push_str(&format!("  \"text_entries\": [{}\n  ],\n", text_entries.join(",")));
+        s.push_str(&format!("  \"is_progressive\": {},\n", self.is_progressive));
+        s.push_str(&format!("  \"has_srgb\": {}\n", self.has_srgb));
+        s.push_str("}\n");
+        return s
+    }
+    /// Reads back the JSON object written by `to_json_string`
+    pub fn from_json_str(s: &str) -> Result<PngMetadata, PngMeError> {
+        let document = JsonValue::parse(s)?;
+        let field = |name: &str| {
+            document.get(name).ok_or_else(|| PngMeError::InvalidJson(format!("missing \"{}\"", name)))
+        };
+        let invalid = |name: &str| PngMeError::InvalidJson(format!("\"{}\" has an invalid value", name));
+        let integer = |name: &str, max: u64| match field(name)?.as_u64() {
+            Some(n) if n <= max => Ok(n),
+            _ => Err(invalid(name)),
+        };
+        let boolean = |name: &str| field(name)?.as_bool().ok_or_else(|| invalid(name));
+
+        let gamma = match field("gamma")? {
+            JsonValue::Null => None,
+            value => Some(value.as_f64().ok_or_else(|| invalid("gamma"))?),
+        };
+        let modification_time = match field("modification_time")? {
+            JsonValue::Null => None,
+            value => {
+                let text = value.as_str().ok_or_else(|| invalid("modification_time"))?;
+                Some(PngTime::from_str(text).map_err(|_| invalid("modification_time"))?)
+            }
+        };
+        let mut text_entries: Vec<(String, String)> = vec!();
+        for entry in field("text_entries")?.as_array().ok_or_else(|| invalid("text_entries"))? {
+            let keyword = entry.get("keyword").and_then(|k| k.as_str());
+            let text = entry.get("text").and_then(|t| t.as_str());
+            match (keyword, text) {
+                (Some(keyword), Some(text)) => text_entries.push((keyword.to_string(), text.to_string())),
+                _ => return Err(invalid("text_entries")),
+            }
+        }
+
+        return Ok(PngMetadata {
+            width: integer("width", u32::MAX as u64)? as u32,
+            height: integer("height", u32::MAX as u64)? as u32,
+            bit_depth: integer("bit_depth", u8::MAX as u64)? as u8,
+            color_type: integer("color_type", u8::MAX as u64)? as u8,
+            gamma,
+            modification_time,
+            text_entries,
+            is_progressive: boolean("is_progressive")?,
+            has_srgb: boolean("has_srgb")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_metadata() -> PngMetadata {
+        PngMetadata {
+            width: 640,
+            height: 480,
+            bit_depth: 8,
+            color_type: 6,
+            gamma: Some(0.45455),
+            modification_time: Some(PngTime::from_str("2024-01-02T03:04:05Z").unwrap()),
+            text_entries: vec![
+                (String::from("Author"), String::from("Jane \"JD\" Doe")),
+                (String::from("Comment"), String::from("two\nlines")),
+            ],
+            is_progressive: true,
+            has_srgb: false,
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let metadata = testing_metadata();
+        assert_eq!(PngMetadata::from_json_str(&metadata.to_json_string()).unwrap(), metadata);
+    }
+
+    #[test]
+    fn test_json_round_trip_empty() {
+        let metadata = PngMetadata {
+            gamma: None,
+            modification_time: None,
+            text_entries: vec!(),
+            ..testing_metadata()
+        };
+        let json = metadata.to_json_string();
+        assert!(json.contains("\"gamma\": null"));
+        assert_eq!(PngMetadata::from_json_str(&json).unwrap(), metadata);
+    }
+
+    #[test]
+    fn test_json_invalid() {
+        let json = testing_metadata().to_json_string();
+        assert!(PngMetadata::from_json_str(&json.replace("\"width\": 640", "\"width\": -1")).is_err());
+        assert!(PngMetadata::from_json_str(&json.replace("\"bit_depth\": 8", "\"bit_depth\": 300")).is_err());
+        assert!(PngMetadata::from_json_str(&json.replace("\"has_srgb\": false", "\"has_srgb\": 0")).is_err());
+        assert!(PngMetadata::from_json_str("{}").is_err());
+    }
+}
@@ -1,293 +1,4409 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
 use std::fmt;
-use std::io::{BufReader, Read};
+use std::fs;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
+use crate::background::BackgroundColor;
+use crate::base64;
 use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use crate::hexdump;
+use crate::ihdr::IhdrData;
+#[cfg(feature = "cbor")]
+use ciborium::Value as CborValue;
+use crate::json::{self, JsonValue};
+use crate::metadata::PngMetadata;
+use crate::png_time::PngTime;
+use crate::transparency::Transparency;
+use crate::zlib;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 
 /// A PNG container as described by the PNG spec
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Contents.html
+/// Two `Png`s are equal when they have the same chunks in the same order.
 #[derive(Debug)]
 pub struct Png {
-    chunk_list: Vec<Chunk>
+    chunk_list: Vec<Chunk>,
+    // Incremented by every method that changes the chunks
+    version: u64,
+    // Version written by the last `save_to_file`, a `Cell` because saving only needs `&self`
+    last_written_version: Cell<u64>,
 }
 
 impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    /// First byte of the data of chunks written by `encode_compressed`, followed by a zlib stream
+    pub const COMPRESSED_MESSAGE_MARKER: u8 = 0xC7;
+    /// Type of the marker chunk added by `to_compressed`. Its data is the big endian u32 index
+    /// of every chunk `to_compressed` changed, so `from_compressed` knows what to undo.
+    pub const COMPRESSION_MARKER_CHUNK_TYPE: &'static str = "cPRS";
+    /// Type of the chunk of zero bytes added by `pad_to_size`
+    pub const PADDING_CHUNK_TYPE: &'static str = "pADD";
+    /// Ancillary chunk types that already hold compressed data, left alone by `to_compressed`
+    pub const COMPRESSED_ANCILLARY_TYPES: [&'static str; 3] = ["zTXt", "iTXt", "iCCP"];
+    /// Type of the chunk where `annotate_chunk` stores its `key=value` lines
+    pub const ANNOTATION_CHUNK_TYPE: &'static str = "aNNO";
+    /// Buffer size used by `to_writer`. Writing a 64 MB file with many small chunks
+    /// (`bench_to_writer_buffered`), 4 KB buffers were about 5-10% slower than 64 KB and
+    /// 1 MB buffers were no faster, so 64 KB is the sweet spot.
+    pub const WRITER_BUFFER_SIZE: usize = 64 * 1024;
+    /// Ancillary chunk types the spec allows at most once per file
+    pub const SINGLE_OCCURRENCE_TYPES: [&'static str; 9] = [
+        "gAMA", "cHRM", "sRGB", "bKGD", "hIST", "pHYs", "sBIT", "tIME", "iCCP",
+    ];
+    /// Standard text chunk types, removed by `remove_comment_chunks`
+    pub const TEXT_CHUNK_TYPES: [&'static str; 3] = ChunkType::TEXT_TYPES;
+    /// Ancillary chunk types the spec requires before PLTE (and IDAT)
+    pub const BEFORE_PLTE_TYPES: [&'static str; 5] = ["cHRM", "gAMA", "iCCP", "sBIT", "sRGB"];
+    /// Chunk types describing the color space, removed by `remove_color_management_chunks`
+    pub const COLOR_MANAGEMENT_TYPES: [&'static str; 4] = ["sRGB", "iCCP", "cHRM", "gAMA"];
+    /// Entropy, in bits per byte, above which `contains_hidden_payload` finds the data of an
+    /// ancillary chunk suspicious. Text is around 4.5 and encrypted data around 7.9.
+    pub const SUSPICIOUS_ENTROPY: f64 = 7.5;
+    /// Largest IDAT chunk written by `replace_image_data`
+    pub const MAX_IDAT_CHUNK_SIZE: usize = 65535;
+    /// How deep `from_cbor` lets arrays nest, so a malicious document can not overflow the stack
+    #[cfg(feature = "cbor")]
+    pub const CBOR_RECURSION_LIMIT: usize = 16;
+    /// First bytes of the patches written by `write_diff_patch`
+    pub const PATCH_MAGIC: [u8; 4] = *b"pPAT";
+    /// Largest table, in cells, `write_diff_patch` builds to find the longest common subsequence.
+    /// It has a cell per pair of chunks, so this is around 1000 changed chunks on each side.
+    pub const MAX_DIFF_TABLE_CELLS: usize = 1 << 20;
+    /// Start of the data URIs written by `to_base64_data_uri`
+    pub const DATA_URI_PREFIX: &'static str = "data:image/png;base64,";
+    /// Length of the HMAC-SHA256 tag `embed_and_sign` stores before the message
+    pub const SIGNATURE_TAG_LEN: usize = 32;
+
+    // A new `Png` counts as written: it is at version 0 whether it was read from a file or built
+    fn with_chunk_list(chunks: Vec<Chunk>) -> Png {
+        return Png{chunk_list: chunks, version: 0, last_written_version: Cell::new(0)}
+    }
+    // Every change to the chunks goes through here so it is counted in `version`. It is called
+    // once per change, and only when something does change, so `is_dirty` stays accurate.
+    fn chunk_list_mut(&mut self) -> &mut Vec<Chunk> {
+        self.version += 1;
+        return &mut self.chunk_list
+    }
+    // Where `add_after_ihdr` inserts: right after IHDR, or first without an IHDR at the start
+    fn after_ihdr_position(chunks: &[Chunk]) -> usize {
+        return match chunks.first() {
+            Some(c) if *c.chunk_type() == ChunkType::IHDR => 1,
+            _ => 0,
+        }
+    }
+    // Where `add_before_iend` inserts: right before IEND, or at the end without an IEND there
+    fn before_iend_position(chunks: &[Chunk]) -> usize {
+        return match chunks.last() {
+            Some(c) if *c.chunk_type() == ChunkType::IEND => chunks.len() - 1,
+            _ => chunks.len(),
+        }
+    }
+    // Removes the chunks at `indices`, which must be in increasing order, and returns them in order
+    fn remove_chunks_at(&mut self, indices: &[usize]) -> Vec<Chunk> {
+        if indices.is_empty() {
+            return vec!()
+        }
+        let list = self.chunk_list_mut();
+        let mut removed: Vec<Chunk> = vec!();
+        let mut kept: Vec<Chunk> = vec!();
+        for (i, c) in std::mem::take(list).into_iter().enumerate() {
+            match indices.binary_search(&i) {
+                Ok(_) => removed.push(c),
+                Err(_) => kept.push(c),
+            }
+        }
+        *list = kept;
+        return removed
+    }
+    /// Creates a `Png` from a list of chunks using the correct header
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
+        return Png::with_chunk_list(chunks)
+    }
+    /// Creates a `Png` from a list of chunks without looking at their order or framing, to build
+    /// intentionally broken files for tests and repair tools. `repair` can fix them afterwards.
+    /// The result may lack IHDR, IDAT or IEND, or have them in any position, so methods that
+    /// rely on the framing (`validate`, `ihdr_or_err`...) can fail.
+    pub fn from_chunks_unchecked(chunks: Vec<Chunk>) -> Png {
+        return Png::with_chunk_list(chunks)
+    }
+    /// Creates a `Png` with the critical chunks of `critical_source` (in their order) and the
+    /// given ancillary chunks instead of its own. The ancillary chunks that must come before PLTE
+    /// go right after IHDR, every other one goes right before the first IDAT.
+    /// Returns an error if any of the given chunks is critical.
+    pub fn from_critical_and_ancillary(critical_source: &Png, ancillary_chunks: Vec<Chunk>) -> Result<Png, PngMeError> {
+        if let Some(c) = ancillary_chunks.iter().find(|c| c.chunk_type().is_critical()) {
+            return Err(PngMeError::InvalidInput(format!("{} is a critical chunk", c.chunk_type())))
+        }
+
+        let (before_plte, others): (Vec<Chunk>, Vec<Chunk>) = ancillary_chunks
+            .into_iter()
+            .partition(|c| Png::BEFORE_PLTE_TYPES.contains(&c.chunk_type().to_string().as_str()));
+        let mut chunks: Vec<Chunk> = critical_source.partition_chunks().0.into_iter().cloned().collect();
+
+        let first_idat = chunks
+            .iter()
+            .position(|c| *c.chunk_type() == ChunkType::IDAT)
+            .or_else(|| chunks.iter().position(|c| *c.chunk_type() == ChunkType::IEND))
+            .unwrap_or(chunks.len());
+        chunks.splice(first_idat..first_idat, others);
+        let after_ihdr = Png::after_ihdr_position(&chunks);
+        chunks.splice(after_ihdr..after_ihdr, before_plte);
+
+        return Ok(Png::from_chunks(chunks))
+    }
+    /// Creates a valid `Png` of the given size where every pixel is zero (black or fully transparent).
+    /// It contains IHDR, a single IDAT and IEND, plus a one color PLTE for indexed images.
+    pub fn new_blank(width: u32, height: u32, color_type: u8, bit_depth: u8) -> Result<Png, PngMeError> {
+        // Also validates the dimensions and the color type and bit depth combination
+        let ihdr = Chunk::new_ihdr(width, height, bit_depth, color_type)?;
+
+        let channels: u64 = match color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        };
+        // Every scanline is a filter type byte (0, none) followed by the packed pixels
+        let row_length = (width as u64 * channels * bit_depth as u64).div_ceil(8) + 1;
+        // The image data goes in a single IDAT chunk, checked before allocating it
+        let image_length = row_length
+            .checked_mul(height as u64)
+            .and_then(|n| usize::try_from(n).ok())
+            .unwrap_or(usize::MAX);
+        if !Chunk::data_len_fits_in_u32(image_length) {
+            return Err(PngMeError::DataTooLarge(image_length))
+        }
+        let image_data = zlib::compress_stored(&vec![0; image_length]);
+
+        let mut chunks: Vec<Chunk> = vec![ihdr];
+        if color_type == 3 {
+            // Indexed images need a palette, a single black entry is enough
+            chunks.push(Chunk::new(ChunkType::PLTE, vec![0, 0, 0])?);
+        }
+        chunks.push(Chunk::new(ChunkType::IDAT, image_data)?);
+        chunks.push(Chunk::new_iend());
+
+        return Ok(Png::from_chunks(chunks))
+    }
+    /// Same as `add_before_iend`: despite the name the chunk goes before IEND, never after it
+    #[deprecated(note = "use add_before_iend")]
+    pub fn append_chunk(&mut self, chunk: Chunk) {
+        self.add_before_iend(chunk);
+    }
+    /// Inserts a chunk right before IEND, the safe place for chunks that can go anywhere
+    /// after the header. Without an IEND at the end the chunk is appended.
+    pub fn add_before_iend(&mut self, chunk: Chunk) {
+        let position = Png::before_iend_position(self.chunks());
+        self.chunk_list_mut().insert(position, chunk);
+    }
+    /// Returns this `Png` with the chunk added before IEND, so additions can be chained:
+    /// `Png::from_file(a)?.with_chunk(text)?.with_chunk(gamma)?.save_to_file(b)?`.
+    /// Fails for an IHDR or IEND chunk, and for a second chunk of a type in `SINGLE_OCCURRENCE_TYPES`.
+    pub fn with_chunk(mut self, chunk: Chunk) -> Result<Png, PngMeError> {
+        let chunk_type = chunk.chunk_type();
+        if *chunk_type == ChunkType::IHDR || *chunk_type == ChunkType::IEND {
+            return Err(PngMeError::InvalidInput(format!("a {} chunk can not be added", chunk_type)))
+        }
+        if Png::SINGLE_OCCURRENCE_TYPES.contains(&chunk_type.to_string().as_str())
+            && self.chunks().iter().any(|c| c.chunk_type() == chunk_type)
+        {
+            return Err(PngMeError::InvalidInput(format!("there is already a {} chunk", chunk_type)))
+        }
+        self.add_before_iend(chunk);
+        return Ok(self)
+    }
+    /// Adds a copy of the first chunk of the given type in `source` before IEND
+    pub fn copy_chunk_from(&mut self, source: &Png, chunk_type: &str) -> Result<(), PngMeError> {
+        let chunk = source.chunk_at_type_or_err(chunk_type)?.clone();
+        self.add_before_iend(chunk);
+        return Ok(())
+    }
+    /// Adds a copy of every chunk of the given type in `source` before IEND, in order, and
+    /// returns how many were copied. Like `copy_chunk_from`, it fails if there are none.
+    pub fn copy_all_chunks_of_type_from(&mut self, source: &Png, chunk_type: &str) -> Result<usize, PngMeError> {
+        let chunks: Vec<Chunk> = source
+            .find_chunks(|c| c.chunk_type().to_string() == chunk_type)
+            .into_iter()
+            .cloned()
+            .collect();
+        if chunks.is_empty() {
+            return Err(PngMeError::ChunkNotFound(chunk_type.to_string()))
+        }
+        let count = chunks.len();
+        self.append_chunks(chunks);
+        return Ok(count)
+    }
+    /// Returns the first chunk of the same type as `default`, or adds `default` before IEND and
+    /// returns it when there is none, like `HashMap::entry(..).or_insert(..)`
+    pub fn get_or_insert_chunk(&mut self, default: Chunk) -> &mut Chunk {
+        let chunk_type = default.chunk_type().clone();
+        if let Some(index) = self.position_of(|c| *c.chunk_type() == chunk_type) {
+            return &mut self.chunk_list_mut()[index]
+        }
+        self.add_before_iend(default);
+        // It was just added and is the only chunk of its type so this can not fail
+        let index = self.position_of(|c| *c.chunk_type() == chunk_type).unwrap();
+        // Already counted as a change by `add_before_iend`
+        return &mut self.chunk_list[index]
+    }
+    /// Inserts all the chunks right before IEND, in order, in a single pass.
+    /// Like `add_before_iend`, IEND stays the last chunk.
+    pub fn append_chunks(&mut self, chunks: Vec<Chunk>) {
+        if chunks.is_empty() {
+            return
+        }
+        let position = Png::before_iend_position(self.chunks());
+        self.chunk_list_mut().splice(position..position, chunks);
+    }
+    /// Inserts a chunk right after IHDR, for chunks the spec requires before PLTE and IDAT
+    /// (cHRM, gAMA, sRGB...). Without an IHDR at the start the chunk becomes the first one.
+    pub fn add_after_ihdr(&mut self, chunk: Chunk) {
+        let position = Png::after_ihdr_position(self.chunks());
+        self.chunk_list_mut().insert(position, chunk);
+    }
+    /// Inserts a new IDAT chunk with the given data right after the last IDAT, so the IDAT
+    /// chunks stay contiguous as the spec requires. Without any IDAT it goes after PLTE, or
+    /// after IHDR when there is no palette, and the PNG must have an IHDR.
+    pub fn insert_idat(&mut self, data: Vec<u8>) -> Result<(), PngMeError> {
+        let chunk = Chunk::new(ChunkType::IDAT, data)?;
+        let position = match self.chunks().iter().rposition(|c| *c.chunk_type() == ChunkType::IDAT) {
+            Some(i) => i + 1,
+            None => match self.position_of(|c| *c.chunk_type() == ChunkType::PLTE) {
+                Some(i) => i + 1,
+                None => self.position_of(|c| *c.chunk_type() == ChunkType::IHDR)
+                    .ok_or_else(|| PngMeError::ChunkNotFound(String::from("IHDR")))? + 1,
+            },
+        };
+        self.chunk_list_mut().insert(position, chunk);
+        return Ok(())
+    }
+    /// Searches for a `Chunk` with the specified `chunk_type` and removes the first
+    /// matching `Chunk` from this `Png` list of chunks.
+    pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk, &str> {
+        for (i, c) in self.chunks().iter().enumerate() {
+            if c.chunk_type().to_string() == chunk_type {
+                return Ok(self.chunk_list_mut().remove(i))
+            }
+        }
+        return Err("No chunks of that type where found")
+    }
+    /// The header of this PNG.
+    pub fn header(&self) -> &[u8; 8] {
+        return &Png::STANDARD_HEADER
+    }
+    /// Lists the `Chunk`s stored in this `Png`
+    pub fn chunks(&self) -> &[Chunk] {
+        return &self.chunk_list
+    }
+    /// Searches for a `Chunk` with the specified `chunk_type` and returns the first
+    /// matching `Chunk` from this `Png`.
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+        for c in self.chunks().iter() {
+            if c.chunk_type().to_string() == chunk_type {    
+                return Option::Some(c)
+            }
+        }
+        return Option::None
+    }
+    /// Returns the data of the first chunk of the given type. It is borrowed for now, the `Cow`
+    /// leaves room for types whose data has to be decoded first without changing callers.
+    pub fn get_chunk_data<'a>(&'a self, chunk_type: &str) -> Option<Cow<'a, [u8]>> {
+        return self.chunk_by_type(chunk_type).map(|c| Cow::Borrowed(c.data()))
+    }
+    /// Returns the index and the first chunk whose CRC, computed from its type and data, is `crc`.
+    /// Useful to identify a chunk of a damaged file from a CRC that survived.
+    pub fn find_chunk_by_crc(&self, crc: u32) -> Option<(usize, &Chunk)> {
+        return self.chunks().iter().enumerate().find(|(_, c)| c.crc() == crc)
+    }
+    /// Same as `find_chunk_by_crc` but returning every chunk with that CRC
+    pub fn find_all_chunks_by_crc(&self, crc: u32) -> Vec<(usize, &Chunk)> {
+        return self.chunks().iter().enumerate().filter(|(_, c)| c.crc() == crc).collect()
+    }
+    /// Returns the first chunk for which `predicate` returns true
+    pub fn find_chunk<F: Fn(&Chunk) -> bool>(&self, predicate: F) -> Option<&Chunk> {
+        return self.chunks().iter().find(|c| predicate(c))
+    }
+    /// Returns every chunk for which `predicate` returns true, in file order
+    pub fn find_chunks<F: Fn(&Chunk) -> bool>(&self, predicate: F) -> Vec<&Chunk> {
+        return self.chunks().iter().filter(|c| predicate(c)).collect()
+    }
+    /// Returns the index of the first chunk for which `predicate` returns true
+    pub fn position_of<F: Fn(&Chunk) -> bool>(&self, predicate: F) -> Option<usize> {
+        return self.chunks().iter().position(predicate)
+    }
+    // Indices of every chunk for which `predicate` returns true, in increasing order
+    fn positions_of<F: Fn(&Chunk) -> bool>(&self, predicate: F) -> Vec<usize> {
+        return self.chunks().iter().enumerate().filter(|(_, c)| predicate(c)).map(|(i, _)| i).collect()
+    }
+    /// Same as `chunk_by_type` but a missing chunk is a `PngMeError::ChunkNotFound`
+    pub fn chunk_at_type_or_err(&self, chunk_type: &str) -> Result<&Chunk, PngMeError> {
+        return self.chunk_by_type(chunk_type).ok_or_else(|| PngMeError::ChunkNotFound(chunk_type.to_string()))
+    }
+    /// Returns the first IDAT chunk
+    pub fn first_idat(&self) -> Result<&Chunk, PngMeError> {
+        return self.chunk_at_type_or_err("IDAT")
+    }
+    /// Returns the IHDR chunk
+    pub fn ihdr_or_err(&self) -> Result<&Chunk, PngMeError> {
+        return self.chunk_at_type_or_err("IHDR")
+    }
+    /// Returns the data of every chunk of the given type joined together, in order. This is how
+    /// data split across many chunks, like the image data in IDAT, is put back together.
+    pub fn chunk_data_combined(&self, chunk_type: &str) -> Vec<u8> {
+        return self
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type().to_string() == chunk_type)
+            .flat_map(|c| c.data().iter().copied())
+            .collect()
+    }
+    /// Returns the zlib stream of the image, the data of every IDAT chunk joined together
+    pub fn image_data(&self) -> Vec<u8> {
+        return self.chunk_data_combined("IDAT")
+    }
+    /// Joins the data of every IDAT chunk into a single IDAT chunk placed where the first one
+    /// was. Unlike `replace_image_data` the data is never split, for decoders that want one IDAT.
+    /// Nothing changes when there is a single IDAT already.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_single_idat(&mut self) -> Result<(), PngMeError> {
+        let position = self.position_of(|c| *c.chunk_type() == ChunkType::IDAT)
+            .ok_or_else(|| PngMeError::ChunkNotFound(String::from("IDAT")))?;
+        if self.find_chunks(|c| *c.chunk_type() == ChunkType::IDAT).len() == 1 {
+            return Ok(())
+        }
+        let chunk = Chunk::new(ChunkType::IDAT, self.chunk_data_combined("IDAT"))?;
+        let list = self.chunk_list_mut();
+        list.retain(|c| *c.chunk_type() != ChunkType::IDAT);
+        list.insert(position, chunk);
+        return Ok(())
+    }
+    /// Returns a reader over the data of every IDAT chunk, in order, as one continuous stream,
+    /// without joining them into a single buffer like `image_data` does
+    pub fn idat_reader(&self) -> IdatReader<'_> {
+        return IdatReader{
+            chunks: self.find_chunks(|c| *c.chunk_type() == ChunkType::IDAT),
+            chunk_index: 0,
+            offset: 0,
+        }
+    }
+    /// Replaces the IDAT chunks with the given zlib stream, split into chunks of at most
+    /// `MAX_IDAT_CHUNK_SIZE` bytes. The new chunks go where the first IDAT was, or before IEND
+    /// if there was none. The stream is not checked.
+    pub fn replace_image_data(&mut self, stream: &[u8]) {
+        let position = self.chunks().iter().position(|c| *c.chunk_type() == ChunkType::IDAT)
+            .unwrap_or_else(|| Png::before_iend_position(self.chunks()));
+
+        // An empty stream still gets an (empty) IDAT chunk
+        let idats: Vec<Chunk> = match stream.is_empty() {
+            true => vec![Chunk::new(ChunkType::IDAT, vec!()).unwrap()],
+            // Every piece is at most 65535 bytes, so creating the chunks can not fail
+            false => stream
+                .chunks(Png::MAX_IDAT_CHUNK_SIZE)
+                .map(|piece| Chunk::new(ChunkType::IDAT, piece.to_vec()).unwrap())
+                .collect(),
+        };
+        let list = self.chunk_list_mut();
+        list.retain(|c| *c.chunk_type() != ChunkType::IDAT);
+        list.splice(position..position, idats);
+    }
+    /// Returns the interlace method stored in IHDR: 0 for none, 1 for Adam7
+    pub fn interlace_method(&self) -> Result<u8, PngMeError> {
+        return Ok(IhdrData::try_from(self.ihdr_or_err()?)?.interlace_method)
+    }
+    /// Returns the width and height of the image in pixels, from a single parse of IHDR
+    pub fn pixel_dimensions(&self) -> Result<(u32, u32), PngMeError> {
+        let ihdr = IhdrData::try_from(self.ihdr_or_err()?)?;
+        return Ok((ihdr.width, ihdr.height))
+    }
+    /// Returns the number of pixels in the image. This is a u64 because width times height
+    /// overflows a u32 for large images, like 65535 by 65535.
+    pub fn pixel_count(&self) -> Result<u64, PngMeError> {
+        let (width, height) = self.pixel_dimensions()?;
+        return Ok(width as u64 * height as u64)
+    }
+    /// Returns the size the image is meant to be printed at, from the pixel density in pHYs.
+    /// None if there is no pHYs, its unit is unknown (only the aspect ratio is given), its
+    /// density is zero or it is malformed, or if there is no valid IHDR.
+    pub fn print_size(&self) -> Option<PrintSize> {
+        let phys = self.chunk_by_type("pHYs")?;
+        // Pixels per unit on each axis and the unit, 1 for the metre
+        if phys.data().len() != 9 || phys.data()[8] != 1 {
+            return None
+        }
+        let x_per_metre = phys.data_read_u32_be_at(0).ok()? as f64;
+        let y_per_metre = phys.data_read_u32_be_at(4).ok()? as f64;
+        if x_per_metre == 0.0 || y_per_metre == 0.0 {
+            return None
+        }
+        let (width, height) = self.pixel_dimensions().ok()?;
+        return Some(PrintSize {
+            width_cm: width as f64 / x_per_metre * 100.0,
+            height_cm: height as f64 / y_per_metre * 100.0,
+            dpi: x_per_metre * 0.0254,
+        })
+    }
+    /// Returns true if the image is Adam7 interlaced, so it can be shown progressively while loading
+    pub fn is_progressive(&self) -> bool {
+        return matches!(self.interlace_method(), Ok(1))
+    }
+    /// Returns true if the image is not interlaced
+    pub fn is_non_interlaced(&self) -> bool {
+        return matches!(self.interlace_method(), Ok(0))
+    }
+    /// Returns the color type stored in IHDR
+    pub fn color_type(&self) -> Result<u8, PngMeError> {
+        return Ok(IhdrData::try_from(self.ihdr_or_err()?)?.color_type)
+    }
+    /// Returns the name of the color type stored in IHDR, such as `"RGBA"`
+    pub fn image_type_string(&self) -> Result<&'static str, PngMeError> {
+        return match self.color_type()? {
+            0 => Ok("Grayscale"),
+            2 => Ok("RGB"),
+            3 => Ok("Indexed"),
+            4 => Ok("Grayscale+Alpha"),
+            6 => Ok("RGBA"),
+            other => Err(PngMeError::InvalidChunkData(format!("unknown color type {}", other))),
+        }
+    }
+    /// Returns true if the image has color: RGB, indexed or RGBA. False for grayscale, or if
+    /// the color type can not be read.
+    pub fn is_color(&self) -> bool {
+        return matches!(self.color_type(), Ok(2 | 3 | 6))
+    }
+    /// Returns true if every pixel has an alpha channel: grayscale with alpha or RGBA. Other
+    /// color types can still have transparency through a tRNS chunk, see `transparency`.
+    pub fn has_alpha(&self) -> bool {
+        return matches!(self.color_type(), Ok(4 | 6))
+    }
+    /// Returns every chunk type present, once, in the order they first appear
+    pub fn all_chunk_types(&self) -> Vec<ChunkType> {
+        let mut types: Vec<ChunkType> = vec!();
+        for c in self.chunks() {
+            if !types.contains(c.chunk_type()) {
+                types.push(c.chunk_type().clone());
+            }
+        }
+        return types
+    }
+    /// Returns the chunks grouped by type, each group in the order the chunks appear
+    pub fn to_chunk_map(&self) -> HashMap<ChunkType, Vec<&Chunk>> {
+        let mut map: HashMap<ChunkType, Vec<&Chunk>> = HashMap::new();
+        for c in self.chunks() {
+            map.entry(c.chunk_type().clone()).or_default().push(c);
+        }
+        return map
+    }
+    /// Same as `to_chunk_map` but taking the chunks out of this `Png` instead of borrowing them
+    pub fn into_chunk_map(self) -> HashMap<ChunkType, Vec<Chunk>> {
+        let mut map: HashMap<ChunkType, Vec<Chunk>> = HashMap::new();
+        for c in self.chunk_list {
+            map.entry(c.chunk_type().clone()).or_default().push(c);
+        }
+        return map
+    }
+    /// Returns statistics about the data lengths of every chunk
+    pub fn chunk_statistics(&self) -> ChunkStats {
+        return ChunkStats::from_chunks(self.chunks().iter().collect())
+    }
+    /// Returns statistics about the data lengths of the chunks of the given type, or None if
+    /// there are no chunks of that type
+    pub fn chunk_statistics_for_type(&self, chunk_type: &str) -> Option<ChunkStats> {
+        let chunks = self.find_chunks(|c| c.chunk_type().to_string() == chunk_type);
+        if chunks.is_empty() {
+            return None
+        }
+        return Some(ChunkStats::from_chunks(chunks))
+    }
+    /// Returns how many different chunk types are present
+    pub fn count_unique_types(&self) -> usize {
+        return self.all_chunk_types().len()
+    }
+    /// Returns true if the first chunk is an IHDR chunk with its 13 bytes of data
+    pub fn has_valid_ihdr(&self) -> bool {
+        return match self.chunks().first() {
+            Some(c) => c.chunk_type().to_string() == "IHDR" && c.length() == 13,
+            None => false,
+        }
+    }
+    /// Returns true if the last chunk is an IEND chunk with no data
+    pub fn has_valid_iend(&self) -> bool {
+        return match self.chunks().last() {
+            Some(c) => c.chunk_type().to_string() == "IEND" && c.length() == 0,
+            None => false,
+        }
+    }
+    /// Returns true if there is at least one IDAT chunk
+    pub fn has_any_idat(&self) -> bool {
+        return self.chunk_by_type("IDAT").is_some()
+    }
+    /// Returns true if the framing of this `Png` is well formed: it starts with IHDR,
+    /// ends with IEND and contains image data. This only looks at the chunk types and
+    /// lengths, so it is cheap enough to be called while parsing.
+    pub fn is_structurally_complete(&self) -> bool {
+        return self.has_valid_ihdr() && self.has_valid_iend() && self.has_any_idat()
+    }
+    /// Marks the image as sRGB with the given rendering intent (0 perceptual, 1 relative
+    /// colorimetric, 2 saturation, 3 absolute colorimetric). Any sRGB, iCCP or cHRM chunk is
+    /// replaced since they conflict with it. gAMA is kept, the spec recommends it alongside sRGB
+    /// for decoders that do not understand sRGB.
+    pub fn add_srgb_chunk(&mut self, rendering_intent: u8) -> Result<(), PngMeError> {
+        if rendering_intent > 3 {
+            return Err(PngMeError::InvalidInput(format!("invalid sRGB rendering intent {}", rendering_intent)))
+        }
+        // sRGB is made of valid letters so this can not fail
+        let chunk = Chunk::new(ChunkType::from_str("sRGB").unwrap(), vec![rendering_intent])?;
+        let list = self.chunk_list_mut();
+        // Every color management chunk but gAMA
+        list.retain(|c| {
+            let chunk_type = c.chunk_type().to_string();
+            chunk_type == "gAMA" || !Png::COLOR_MANAGEMENT_TYPES.contains(&chunk_type.as_str())
+        });
+        // sRGB must come before PLTE and IDAT
+        list.insert(Png::after_ihdr_position(list), chunk);
+        return Ok(())
+    }
+    /// Removes every sRGB, iCCP, cHRM and gAMA chunk, leaving the color space unspecified
+    pub fn remove_color_management_chunks(&mut self) {
+        let indices = self.positions_of(|c| Png::COLOR_MANAGEMENT_TYPES.contains(&c.chunk_type().to_string().as_str()));
+        self.remove_chunks_at(&indices);
+    }
+    /// Returns the keyword and text of a tEXt, zTXt or iTXt chunk, decompressing them if needed.
+    /// tEXt and zTXt are Latin-1, iTXt is read by `ItxtData`. Other or malformed chunks give None.
+    fn decode_text_chunk(chunk: &Chunk) -> Option<(String, String)> {
+        if chunk.chunk_type().to_string() == "iTXt" {
+            return chunk.parse_itxt().ok().map(|itxt| (itxt.keyword, itxt.text))
+        }
+        let latin1 = |bytes: &[u8]| -> String { bytes.iter().map(|b| *b as char).collect() };
+        let (keyword, rest) = chunk.data().split_at(chunk.data().iter().position(|b| *b == 0)?);
+        let rest = &rest[1..];
+
+        return match chunk.chunk_type().to_string().as_str() {
+            "tEXt" => Some((latin1(keyword), latin1(rest))),
+            // Compression method (always 0, zlib) and the compressed text
+            "zTXt" => Some((latin1(keyword), latin1(&zlib::decompress(rest.get(1..)?).ok()?))),
+            _ => None,
+        }
+    }
+    /// Returns the type, keyword and text of every tEXt, zTXt and iTXt chunk in order,
+    /// decompressing them if needed. A malformed text chunk is an error rather than skipped.
+    pub fn all_text_data(&self) -> Result<Vec<(ChunkType, String, String)>, PngMeError> {
+        let mut entries: Vec<(ChunkType, String, String)> = vec!();
+        for c in self.chunks().iter().filter(|c| c.chunk_type().is_text_type()) {
+            let (keyword, text) = Png::decode_text_chunk(c).ok_or_else(|| {
+                PngMeError::InvalidChunkData(format!("malformed {} chunk", c.chunk_type()))
+            })?;
+            entries.push((c.chunk_type().clone(), keyword, text));
+        }
+        return Ok(entries)
+    }
+    /// Returns true if the keyword or text of any tEXt, zTXt or iTXt chunk contains `needle`
+    pub fn contains_text(&self, needle: &str) -> bool {
+        return !self.find_text(needle).is_empty()
+    }
+    /// Returns the type and content, as `keyword: text`, of every tEXt, zTXt or iTXt chunk
+    /// whose keyword or text contains `needle`
+    pub fn find_text(&self, needle: &str) -> Vec<(ChunkType, String)> {
+        return self
+            .chunks()
+            .iter()
+            .filter_map(|c| Png::decode_text_chunk(c).map(|text| (c.chunk_type().clone(), text)))
+            .filter(|(_, (keyword, text))| keyword.contains(needle) || text.contains(needle))
+            .map(|(chunk_type, (keyword, text))| (chunk_type, format!("{}: {}", keyword, text)))
+            .collect()
+    }
+    /// Replaces every occurrence of `from` with `to` in the text of the tEXt chunks (the keywords
+    /// are left alone) and returns how many were replaced. tEXt is Latin-1, characters of `to`
+    /// outside of it are written as `?`.
+    pub fn find_and_replace_in_text_chunks(&mut self, from: &str, to: &str) -> usize {
+        if from.is_empty() {
+            return 0
+        }
+        let mut replaced = 0;
+        let mut replacements: Vec<(usize, Chunk)> = vec!();
+        for (i, c) in self.chunks().iter().enumerate().filter(|(_, c)| c.chunk_type().to_string() == "tEXt") {
+            let (keyword, text) = match Png::decode_text_chunk(c) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let count = text.matches(from).count();
+            if count == 0 {
+                continue
+            }
+
+            let mut data: Vec<u8> = keyword.chars().map(|ch| ch as u8).collect();
+            data.push(0);
+            data.extend(text.replace(from, to).chars().map(|ch| u8::try_from(ch).unwrap_or(b'?')));
+            // The chunk only grows by the replacements, which are tiny compared to a u32
+            replacements.push((i, Chunk::new(c.chunk_type().clone(), data).unwrap()));
+            replaced += count;
+        }
+        if !replacements.is_empty() {
+            let list = self.chunk_list_mut();
+            for (i, chunk) in replacements {
+                list[i] = chunk;
+            }
+        }
+        return replaced
+    }
+    /// Stores `text` under `keyword` in a tEXt chunk. Any tEXt, zTXt or iTXt chunk with the same
+    /// keyword is replaced, the new chunk taking the place of the first one; otherwise it goes
+    /// before IEND. tEXt is Latin-1: the keyword must be 1 to 79 characters long and neither
+    /// may contain a null character or characters outside of Latin-1.
+    pub fn set_text_chunk(&mut self, keyword: &str, text: &str) -> Result<(), PngMeError> {
+        let latin1 = |s: &str| -> Option<Vec<u8>> {
+            s.chars().map(|ch| u8::try_from(ch).ok().filter(|b| *b != 0)).collect()
+        };
+        let keyword_bytes = latin1(keyword).filter(|k| (1..=79).contains(&k.len()))
+            .ok_or_else(|| PngMeError::InvalidInput(format!("invalid tEXt keyword \"{}\"", keyword)))?;
+        let text_bytes = latin1(text)
+            .ok_or_else(|| PngMeError::InvalidInput(String::from("tEXt text must be Latin-1 without null characters")))?;
+
+        let mut data = keyword_bytes;
+        data.push(0);
+        data.extend(text_bytes);
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), data)?;
+
+        let has_keyword = |c: &Chunk| matches!(Png::decode_text_chunk(c), Some((k, _)) if k == keyword);
+        match self.position_of(has_keyword) {
+            Some(i) => {
+                let list = self.chunk_list_mut();
+                list[i] = chunk;
+                // The new chunk takes the place of the first one, the later ones are dropped
+                let mut index = 0;
+                list.retain(|c| {
+                    index += 1;
+                    index <= i + 1 || !has_keyword(c)
+                });
+            }
+            None => self.add_before_iend(chunk),
+        }
+        return Ok(())
+    }
+    /// Returns the text of the first tEXt, zTXt or iTXt chunk with the given keyword
+    pub fn get_text_chunk(&self, keyword: &str) -> Option<String> {
+        return self
+            .chunks()
+            .iter()
+            .filter_map(Png::decode_text_chunk)
+            .find(|(k, _)| k == keyword)
+            .map(|(_, text)| text)
+    }
+    /// Sets the text stored under the `Description` keyword, see `set_text_chunk`
+    pub fn set_description(&mut self, text: &str) -> Result<(), PngMeError> {
+        return self.set_text_chunk("Description", text)
+    }
+    /// Returns the text stored under the `Description` keyword
+    pub fn get_description(&self) -> Option<String> {
+        return self.get_text_chunk("Description")
+    }
+    /// Sets the text stored under the `Author` keyword, see `set_text_chunk`
+    pub fn set_author(&mut self, text: &str) -> Result<(), PngMeError> {
+        return self.set_text_chunk("Author", text)
+    }
+    /// Returns the text stored under the `Author` keyword
+    pub fn get_author(&self) -> Option<String> {
+        return self.get_text_chunk("Author")
+    }
+    /// Sets the text stored under the `Copyright` keyword, see `set_text_chunk`
+    pub fn set_copyright(&mut self, text: &str) -> Result<(), PngMeError> {
+        return self.set_text_chunk("Copyright", text)
+    }
+    /// Returns the text stored under the `Copyright` keyword
+    pub fn get_copyright(&self) -> Option<String> {
+        return self.get_text_chunk("Copyright")
+    }
+    /// Sets the text stored under the `Software` keyword, see `set_text_chunk`
+    pub fn set_software(&mut self, text: &str) -> Result<(), PngMeError> {
+        return self.set_text_chunk("Software", text)
+    }
+    /// Returns the text stored under the `Software` keyword
+    pub fn get_software(&self) -> Option<String> {
+        return self.get_text_chunk("Software")
+    }
+    /// Sets the text stored under the `Comment` keyword, see `set_text_chunk`
+    pub fn set_comment(&mut self, text: &str) -> Result<(), PngMeError> {
+        return self.set_text_chunk("Comment", text)
+    }
+    /// Returns the text stored under the `Comment` keyword
+    pub fn get_comment(&self) -> Option<String> {
+        return self.get_text_chunk("Comment")
+    }
+    /// Gathers the image properties from IHDR together with the gamma, the modification time,
+    /// the text chunks and whether the image is interlaced or sRGB
+    pub fn extract_metadata(&self) -> Result<PngMetadata, PngMeError> {
+        let ihdr = IhdrData::try_from(self.ihdr_or_err()?)?;
+        let gamma = match self.chunk_by_type("gAMA").map(|c| c.data()) {
+            Some(&[a, b, c, d]) => Some(u32::from_be_bytes([a, b, c, d]) as f64 / 100000.0),
+            Some(_) => return Err(PngMeError::InvalidChunkData(String::from("gAMA data is not 4 bytes long"))),
+            None => None,
+        };
+
+        return Ok(PngMetadata {
+            width: ihdr.width,
+            height: ihdr.height,
+            bit_depth: ihdr.bit_depth,
+            color_type: ihdr.color_type,
+            gamma,
+            modification_time: self.modification_time()?,
+            text_entries: self.chunks().iter().filter_map(Png::decode_text_chunk).collect(),
+            is_progressive: ihdr.interlace_method == 1,
+            has_srgb: self.chunk_by_type("sRGB").is_some(),
+        })
+    }
+    /// Removes every tEXt, zTXt and iTXt chunk, which may leak metadata such as the author or the
+    /// software used. Returns how many were removed.
+    pub fn remove_comment_chunks(&mut self) -> usize {
+        let indices = self.positions_of(|c| c.chunk_type().is_text_type());
+        return self.remove_chunks_at(&indices).len()
+    }
+    /// Returns a copy where every chunk is passed through `transform`, in order. `Some` keeps the
+    /// returned chunk, which can have another type or data, `None` drops it, and the first error
+    /// stops the transformation.
+    pub fn apply_transform<F>(&self, transform: F) -> Result<Png, PngMeError>
+    where
+        F: Fn(Chunk) -> Result<Option<Chunk>, PngMeError>,
+    {
+        let mut chunks: Vec<Chunk> = Vec::new();
+        for c in self.chunks() {
+            if let Some(transformed) = transform(c.clone())? {
+                chunks.push(transformed);
+            }
+        }
+        return Ok(Png::from_chunks(chunks))
+    }
+    /// Removes every chunk for which `predicate` returns true and returns them, in order.
+    /// IHDR and IEND are never removed, whatever the predicate says.
+    pub fn remove_chunks_where<F: Fn(&Chunk) -> bool>(&mut self, predicate: F) -> Vec<Chunk> {
+        let indices = self.positions_of(|c| *c.chunk_type() != ChunkType::IHDR && *c.chunk_type() != ChunkType::IEND && predicate(c));
+        return self.remove_chunks_at(&indices)
+    }
+    /// Keeps only the first chunk of every type in `SINGLE_OCCURRENCE_TYPES` and returns the
+    /// ones removed, in the order they were found. Other chunks, such as IDAT, are left alone.
+    pub fn deduplicate_chunks(&mut self) -> Vec<Chunk> {
+        let mut seen: Vec<&ChunkType> = vec!();
+        let mut duplicates: Vec<usize> = vec!();
+
+        for (i, c) in self.chunks().iter().enumerate() {
+            if !Png::SINGLE_OCCURRENCE_TYPES.contains(&c.chunk_type().to_string().as_str()) {
+                continue
+            }
+            if seen.contains(&c.chunk_type()) {
+                duplicates.push(i);
+            } else {
+                seen.push(c.chunk_type());
+            }
+        }
+
+        return self.remove_chunks_at(&duplicates)
+    }
+    /// Checks that this `Png` is structurally complete and that every chunk is valid for the
+    /// color type in IHDR. The error describes the first problem found.
+    pub fn validate(&self) -> Result<(), PngMeError> {
+        if !self.has_valid_ihdr() {
+            return Err(PngMeError::InvalidChunkData(String::from("the first chunk is not a valid IHDR")))
+        }
+        if !self.has_any_idat() {
+            return Err(PngMeError::InvalidChunkData(String::from("there is no IDAT chunk")))
+        }
+        if !self.has_valid_iend() {
+            return Err(PngMeError::InvalidChunkData(String::from("the last chunk is not a valid IEND")))
+        }
+
+        let color_type = IhdrData::try_from(&self.chunks()[0])?.color_type;
+        for (i, c) in self.chunks().iter().enumerate() {
+            if !c.is_valid_for_color_type(color_type) {
+                return Err(PngMeError::InvalidChunkData(format!(
+                    "{} chunk at index {} is not valid for color type {}", c.chunk_type(), i, color_type
+                )))
+            }
+        }
+        return Ok(())
+    }
+    /// Position in the file of a chunk type, used by `repair`. Chunks with the same rank keep
+    /// their relative order.
+    fn chunk_order_rank(chunk_type: &ChunkType) -> u8 {
+        let name = chunk_type.to_string();
+        return match name.as_str() {
+            "IHDR" => 0,
+            _ if Png::BEFORE_PLTE_TYPES.contains(&name.as_str()) => 1,
+            "PLTE" => 2,
+            "IDAT" => 4,
+            "IEND" => 5,
+            _ => 3,
+        }
+    }
+    /// Puts the chunks in an order the spec accepts: IHDR first, then the chunks required before
+    /// PLTE, PLTE, every other chunk, the IDAT chunks and IEND last. IEND is added if missing.
+    /// Fails if there is no IHDR or IDAT, as those can not be made up.
+    pub fn repair(&mut self) -> Result<(), PngMeError> {
+        self.ihdr_or_err()?;
+        self.first_idat()?;
+        let has_iend = self.chunk_by_type("IEND").is_some();
+        let in_order = self.chunks()
+            .windows(2)
+            .all(|w| Png::chunk_order_rank(w[0].chunk_type()) <= Png::chunk_order_rank(w[1].chunk_type()));
+        if has_iend && in_order {
+            return Ok(())
+        }
+        let list = self.chunk_list_mut();
+        if !has_iend {
+            list.push(Chunk::new_iend());
+        }
+        // sort_by_key is stable
+        list.sort_by_key(|c| Png::chunk_order_rank(c.chunk_type()));
+        return Ok(())
+    }
+    /// Splits the chunks into `(critical, ancillary)` keeping their relative order
+    pub fn partition_chunks(&self) -> (Vec<&Chunk>, Vec<&Chunk>) {
+        return self.chunks().iter().partition(|c| c.chunk_type().is_critical())
+    }
+    /// Consumes this `Png` splitting its chunks into `(critical, ancillary)` keeping their relative order
+    pub fn partition_into_chunks(self) -> (Vec<Chunk>, Vec<Chunk>) {
+        return self.chunk_list.into_iter().partition(|c| c.chunk_type().is_critical())
+    }
+    /// Returns how many bytes of the file are taken by the chunks of the given type
+    pub fn byte_size_of_type(&self, chunk_type: &str) -> u64 {
+        return self
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type().to_string() == chunk_type)
+            .map(|c| c.size_in_file())
+            .sum()
+    }
+    /// Returns how many bytes of the file are taken by each chunk type present.
+    /// Together with the 8 bytes of the header they add up to the size of the file.
+    pub fn byte_size_breakdown(&self) -> BTreeMap<ChunkType, u64> {
+        let mut sizes: BTreeMap<ChunkType, u64> = BTreeMap::new();
+        for c in self.chunks() {
+            *sizes.entry(c.chunk_type().clone()).or_insert(0) += c.size_in_file();
+        }
+        return sizes
+    }
+    /// Returns how many bytes of the file are taken by each category of chunk. The data of every
+    /// chunk is counted in its category, and the header and the 12 bytes of length, type and
+    /// CRC of every chunk are counted as overhead, so the categories add up to `file_size`.
+    pub fn count_bytes_by_category(&self) -> ByteBreakdown {
+        let mut breakdown = ByteBreakdown {
+            overhead_bytes: Png::STANDARD_HEADER.len() as u64,
+            ..ByteBreakdown::default()
+        };
+        for c in self.chunks() {
+            let chunk_type = c.chunk_type().to_string();
+            let category = match chunk_type.as_str() {
+                "IHDR" | "PLTE" | "IDAT" | "IEND" => &mut breakdown.critical_bytes,
+                t if Png::TEXT_CHUNK_TYPES.contains(&t) => &mut breakdown.text_bytes,
+                t if Png::COLOR_MANAGEMENT_TYPES.contains(&t) => &mut breakdown.color_management_bytes,
+                "tIME" => &mut breakdown.time_bytes,
+                _ => &mut breakdown.custom_bytes,
+            };
+            *category += c.length() as u64;
+            breakdown.overhead_bytes += c.size_in_file() - c.length() as u64;
+        }
+        return breakdown
+    }
+    /// Looks for chunks that may hide a message: chunk types the spec does not define, and ancillary
+    /// chunks whose data looks random, with an entropy above `SUSPICIOUS_ENTROPY`. Encrypted data
+    /// looks random while text does not. The chunk types that hold compressed data
+    /// (`COMPRESSED_ANCILLARY_TYPES`) are always close to 8 bits per byte, so their entropy is not checked.
+    pub fn contains_hidden_payload(&self) -> HiddenPayloadAnalysis {
+        let mut analysis = HiddenPayloadAnalysis {
+            has_nonstandard_chunks: false,
+            suspicious_chunk_types: vec!(),
+            unusual_data_entropy: false,
+        };
+        for c in self.chunks() {
+            let chunk_type = c.chunk_type();
+            let nonstandard = !chunk_type.is_standard();
+            let high_entropy = !chunk_type.is_critical()
+                && !Png::COMPRESSED_ANCILLARY_TYPES.contains(&chunk_type.to_string().as_str())
+                && c.data_entropy() > Png::SUSPICIOUS_ENTROPY;
+
+            analysis.has_nonstandard_chunks |= nonstandard;
+            analysis.unusual_data_entropy |= high_entropy;
+            if (nonstandard || high_entropy) && !analysis.suspicious_chunk_types.contains(chunk_type) {
+                analysis.suspicious_chunk_types.push(chunk_type.clone());
+            }
+        }
+        return analysis
+    }
+    /// Splits the chunks into `(before_idat, idat, after_idat)` keeping their relative order.
+    /// The first group has IHDR and the chunks before the image data, the last one has the
+    /// chunks after it and IEND. Without IDAT every chunk is in the first group.
+    pub fn split_at_idat(&self) -> (Vec<&Chunk>, Vec<&Chunk>, Vec<&Chunk>) {
+        let first_idat = self
+            .chunks()
+            .iter()
+            .position(|c| *c.chunk_type() == ChunkType::IDAT)
+            .unwrap_or(self.chunk_list.len());
+        let (before, rest) = self.chunks().split_at(first_idat);
+        let (idat, after) = rest.iter().partition(|c| *c.chunk_type() == ChunkType::IDAT);
+        return (before.iter().collect(), idat, after)
+    }
+    /// Returns the chunks ordered by type, keeping the original order of chunks of the same type.
+    /// This is meant for inspecting or exporting (hashing, diffing) the chunks in a canonical order:
+    /// a `Png` written in this order does not follow the PNG spec.
+    pub fn chunks_sorted_by_type(&self) -> Vec<&Chunk> {
+        let mut sorted: Vec<&Chunk> = self.chunks().iter().collect();
+        // sort_by is stable
+        sorted.sort_by(|a, b| a.chunk_type().cmp(b.chunk_type()));
+        return sorted
+    }
+    /// Returns every chunk with its index, largest data first. Chunks of the same length stay in
+    /// file order.
+    pub fn chunks_sorted_by_size_desc(&self) -> Vec<(usize, &Chunk)> {
+        let mut sorted: Vec<(usize, &Chunk)> = self.chunks().iter().enumerate().collect();
+        sorted.sort_by_key(|(_, c)| std::cmp::Reverse(c.length()));
+        return sorted
+    }
+    /// Returns every chunk with its index, smallest data first. Chunks of the same length stay
+    /// in file order.
+    pub fn chunks_sorted_by_size_asc(&self) -> Vec<(usize, &Chunk)> {
+        let mut sorted: Vec<(usize, &Chunk)> = self.chunks().iter().enumerate().collect();
+        sorted.sort_by_key(|(_, c)| c.length());
+        return sorted
+    }
+    /// Returns the `n` chunks with the largest data, with their index, largest first
+    pub fn top_n_chunks_by_size(&self, n: usize) -> Vec<(usize, &Chunk)> {
+        let mut sorted = self.chunks_sorted_by_size_desc();
+        sorted.truncate(n);
+        return sorted
+    }
+    /// Returns the size in bytes of the file this `Png` is written as, without writing it
+    pub fn file_size(&self) -> u64 {
+        return Png::STANDARD_HEADER.len() as u64 + self.chunks().iter().map(|c| c.size_in_file()).sum::<u64>()
+    }
+    /// Returns where the chunk at `index` is in the bytes returned by `as_bytes`, header included,
+    /// so it can be patched in place in a file. None if there is no chunk at that index.
+    pub fn byte_range_of_chunk(&self, index: usize) -> Option<std::ops::Range<usize>> {
+        let chunk = self.chunks().get(index)?;
+        let start = Png::STANDARD_HEADER.len() + self.chunks()[..index].iter().map(|c| c.size_in_file() as usize).sum::<usize>();
+        return Some(start..start + chunk.size_in_file() as usize)
+    }
+    /// Adds a `pADD` chunk of zero bytes before IEND so the file is exactly `target_bytes` long.
+    /// A chunk takes at least 12 bytes, so a file 1 to 11 bytes short of the target can not be
+    /// padded. Nothing is added when the file already has the target size.
+    pub fn pad_to_size(&mut self, target_bytes: u64) -> Result<(), PngMeError> {
+        let size = self.file_size();
+        if size > target_bytes {
+            return Err(PngMeError::AlreadyTooLarge{size, target: target_bytes})
+        }
+        let missing = target_bytes - size;
+        if missing == 0 {
+            return Ok(())
+        }
+        if missing < 12 {
+            return Err(PngMeError::InvalidInput(format!(
+                "{} bytes are missing, less than the 12 bytes a padding chunk takes", missing
+            )))
+        }
+        // Checked before allocating the zeros
+        let padding = usize::try_from(missing - 12).unwrap_or(usize::MAX);
+        if !Chunk::data_len_fits_in_u32(padding) {
+            return Err(PngMeError::DataTooLarge(padding))
+        }
+        self.add_before_iend(Chunk::new(ChunkType::from_str(Png::PADDING_CHUNK_TYPE).unwrap(), vec![0; padding])?);
+        return Ok(())
+    }
+    /// Returns this `Png` as a byte sequence.
+    /// These bytes will contain the header followed by the bytes of all of the chunks.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut png_as_bytes: Vec<u8> = vec!();
+        png_as_bytes.extend(self.header());
+
+        for c in self.chunks() {
+            png_as_bytes.extend(c.as_bytes());
+        }
+
+        return png_as_bytes
+    }
+    /// Returns the bytes of this `Png`, as written by `as_bytes`, formatted like `xxd` does
+    pub fn to_hexdump(&self) -> String {
+        return hexdump::hexdump(&self.as_bytes())
+    }
+    /// Writes this `Png` to `writer` through a 64 KB buffer. See `to_writer_buffered`.
+    pub fn to_writer<W: Write>(&self, writer: W) -> Result<(), PngMeError> {
+        return self.to_writer_buffered(writer, Png::WRITER_BUFFER_SIZE)
+    }
+    /// Writes the header and then every chunk to `writer`, one chunk at a time, through a
+    /// buffer of `buffer_size` bytes so small chunks do not each cost a system call.
+    /// The buffer is flushed before returning.
+    pub fn to_writer_buffered<W: Write>(&self, writer: W, buffer_size: usize) -> Result<(), PngMeError> {
+        let mut writer = BufWriter::with_capacity(buffer_size, writer);
+        writer.write_all(self.header())?;
+        for c in self.chunks() {
+            writer.write_all(&c.as_bytes())?;
+        }
+        writer.flush()?;
+        return Ok(())
+    }
+    /// Writes the data of every chunk to its own file in `dir`, named after its index and type
+    /// (`00-IHDR.bin`, `01-IDAT.bin`, ...), and returns the paths in chunk order.
+    /// The directory is created if it does not exist.
+    pub fn export_chunks_to_directory(&self, dir: &Path) -> Result<Vec<PathBuf>, PngMeError> {
+        fs::create_dir_all(dir)?;
+        // Pad the index so the files sort in chunk order even with more than 100 chunks
+        let width = self.chunks().len().saturating_sub(1).to_string().len().max(2);
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for (i, c) in self.chunks().iter().enumerate() {
+            let path = dir.join(format!("{:0width$}-{}.bin", i, c.chunk_type(), width = width));
+            fs::write(&path, c.data())?;
+            paths.push(path);
+        }
+        return Ok(paths)
+    }
+    /// Reads back the chunks written by `export_chunks_to_directory`. Every `.bin` file in `dir`
+    /// must be named `<index>-<type>.bin`; the chunks are returned sorted by index.
+    pub fn import_chunks_from_directory(dir: &Path) -> Result<Vec<Chunk>, PngMeError> {
+        let mut indexed: Vec<(usize, Chunk)> = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if !path.is_file() || path.extension() != Some("bin".as_ref()) {
+                continue
+            }
+            let invalid_name = || PngMeError::InvalidInput(format!("{} is not named <index>-<type>.bin", path.display()));
+            let stem = path.file_stem().and_then(|s| s.to_str()).ok_or_else(invalid_name)?;
+            let (index, chunk_type) = stem.split_once('-').ok_or_else(invalid_name)?;
+            let index: usize = index.parse().map_err(|_| invalid_name())?;
+            let chunk_type = ChunkType::try_from_slice(chunk_type.as_bytes())
+                .map_err(|e| e.with_context(format!("reading {}", path.display())))?;
+            indexed.push((index, Chunk::new(chunk_type, fs::read(&path)?)?));
+        }
+        indexed.sort_by_key(|(index, _)| *index);
+        return Ok(indexed.into_iter().map(|(_, c)| c).collect())
+    }
+    /// Reads and parses the PNG file at `path`
+    pub fn from_file(path: &Path) -> Result<Png, PngMeError> {
+        let bytes = fs::read(path)?;
+        return Png::try_from(bytes.as_ref()).map_err(|e| e.with_context(format!("reading {}", path.display())))
+    }
+    /// Same as `from_file` but a missing file is `Ok(None)` instead of an error, for batch
+    /// processing where some files may be gone. Any other I/O error is still an error.
+    pub fn from_file_if_exists(path: &Path) -> Result<Option<Png>, PngMeError> {
+        return match Png::from_file(path) {
+            Ok(png) => Ok(Some(png)),
+            Err(PngMeError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    /// Reads the chunk at the start of `bytes`, returning it with the CRC stored after it (which
+    /// is not checked) and the bytes that follow it. Fails if the chunk is cut short or its type is invalid.
+    fn read_chunk(bytes: &[u8]) -> Result<(Chunk, u32, &[u8]), PngMeError> {
+        if bytes.len() < 12 {
+            return Err(PngMeError::InvalidLength{expected: 12, actual: bytes.len()})
+        }
+        let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        let chunk_type = ChunkType::try_from_slice(&bytes[4..8])?;
+        if bytes.len() - 12 < length {
+            return Err(PngMeError::InvalidLength{expected: 12 + length, actual: bytes.len()})
+        }
+        let (chunk_bytes, rest) = bytes.split_at(12 + length);
+        let stored_crc = u32::from_be_bytes([chunk_bytes[8 + length], chunk_bytes[9 + length], chunk_bytes[10 + length], chunk_bytes[11 + length]]);
+        // The length came from a u32 so the data always fits
+        let chunk = Chunk::new(chunk_type, chunk_bytes[8..8 + length].to_vec())?;
+        return Ok((chunk, stored_crc, rest))
+    }
+    /// Parses as much of a damaged file as possible, for recovering data. A chunk with a wrong
+    /// CRC is kept, and the mismatch is returned as a warning with the index of the chunk.
+    /// A wrong header is reported at index 0 and parsing goes on after it. Parsing stops at
+    /// the first chunk that is cut short or has an invalid type, since nothing after it can be
+    /// trusted to start where a chunk does.
+    pub fn from_bytes_lenient(bytes: &[u8]) -> (Png, Vec<(usize, PngMeError)>) {
+        let mut warnings: Vec<(usize, PngMeError)> = vec!();
+        if !bytes.starts_with(&Png::STANDARD_HEADER) {
+            warnings.push((0, PngMeError::InvalidInput(String::from("the file does not start with the PNG header"))));
+        }
+        let mut rest = bytes.get(Png::STANDARD_HEADER.len()..).unwrap_or(&[]);
+        let mut chunks: Vec<Chunk> = vec!();
+
+        while !rest.is_empty() {
+            let index = chunks.len();
+            let (chunk, stored_crc, tail) = match Png::read_chunk(rest) {
+                Ok(read) => read,
+                Err(e) => {
+                    warnings.push((index, e));
+                    break
+                }
+            };
+            if chunk.crc() != stored_crc {
+                warnings.push((index, PngMeError::CrcMismatch {
+                    index,
+                    chunk_type: chunk.chunk_type().to_string(),
+                    expected: stored_crc,
+                    actual: chunk.crc(),
+                }));
+            }
+            chunks.push(chunk);
+            rest = tail;
+        }
+        return (Png::from_chunks(chunks), warnings)
+    }
+    /// Returns the number of changes made to the chunks since this `Png` was created
+    pub fn version(&self) -> u64 {
+        return self.version
+    }
+    /// Returns true if the chunks changed since the last `save_to_file`, or since this `Png`
+    /// was created if it was never saved
+    pub fn is_dirty(&self) -> bool {
+        return self.version != self.last_written_version.get()
+    }
+    /// Writes this `Png` to the file at `path`, replacing it, and marks it as not dirty.
+    ///
+    /// The file is first written to a temporary file in the same directory, which is then
+    /// renamed over `path`. A rename within a file system is atomic, so `path` is never left
+    /// half written: readers see either the old file or the new one. Only if the rename fails
+    /// because it would cross devices is the temporary file copied over `path` instead. The
+    /// temporary file is removed whenever saving fails.
+    pub fn save_to_file(&self, path: &Path) -> Result<(), PngMeError> {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        // Deleted when dropped, so every early return cleans it up
+        let mut temp = tempfile::NamedTempFile::new_in(parent)?;
+        self.to_writer(temp.as_file_mut())?;
+        if let Err(e) = temp.persist(path) {
+            if e.error.kind() != std::io::ErrorKind::CrossesDevices {
+                return Err(e.error.into())
+            }
+            fs::copy(e.file.path(), path)?;
+        }
+
+        self.last_written_version.set(self.version);
+        return Ok(())
+    }
+    /// Writes one CSV line per chunk, after a header line, with the columns `index`,
+    /// `chunk_type`, `length`, `crc_hex`, `critical`, `public`, `safe_to_copy`, `is_standard`
+    /// and `data_is_utf8`.
+    pub fn write_chunks_report<W: Write>(&self, writer: &mut W) -> Result<(), PngMeError> {
+        let header = ["index", "chunk_type", "length", "crc_hex", "critical", "public", "safe_to_copy", "is_standard", "data_is_utf8"];
+        Png::write_csv_row(writer, &header.map(String::from))?;
+        for (i, c) in self.chunks().iter().enumerate() {
+            let chunk_type = c.chunk_type();
+            let row = [
+                i.to_string(),
+                chunk_type.to_string(),
+                c.length().to_string(),
+                format!("{:08x}", c.crc()),
+                chunk_type.is_critical().to_string(),
+                chunk_type.is_public().to_string(),
+                chunk_type.is_safe_to_copy().to_string(),
+                chunk_type.is_standard().to_string(),
+                std::str::from_utf8(c.data()).is_ok().to_string(),
+            ];
+            Png::write_csv_row(writer, &row)?;
+        }
+        return Ok(())
+    }
+    // Writes the fields as one CSV line, quoting those with a comma, a quote or a line break
+    // as RFC 4180 asks: https://datatracker.ietf.org/doc/html/rfc4180#section-2
+    fn write_csv_row<W: Write>(writer: &mut W, fields: &[String]) -> Result<(), PngMeError> {
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|f| match f.contains([',', '"', '\r', '\n']) {
+                true => format!("\"{}\"", f.replace('"', "\"\"")),
+                false => f.clone(),
+            })
+            .collect();
+        writeln!(writer, "{}", fields.join(","))?;
+        return Ok(())
+    }
+    /// Returns this `Png` as a JSON document that can be edited by hand.
+    /// Every chunk is written as its type, its data encoded as base64 and its CRC:
+    /// `{ "chunks": [ { "type": "IHDR", "data_base64": "...", "crc": 12345 }, ... ] }`
+    pub fn to_json_string(&self) -> String {
+        let mut s = String::from("{\n  \"chunks\": [");
+
+        for (i, c) in self.chunks().iter().enumerate() {
+            if i > 0 {
+                s.push(',');
+            }
+            s.push_str(&format!(
+                "\n    {{ \"type\": {}, \"data_base64\": {}, \"crc\": {} }}",
+                json::quote(&c.chunk_type().to_string()),
+                json::quote(&base64::encode(c.data())),
+                c.crc()
+            ));
+        }
+
+        s.push_str("\n  ]\n}\n");
+        return s
+    }
+    /// Creates a `Png` from a JSON document written by `to_json_string`.
+    /// The stored CRC of every chunk is checked against the one computed from its type and data.
+    pub fn from_json_str(s: &str) -> Result<Png, PngMeError> {
+        let document = JsonValue::parse(s)?;
+        let entries = match document.get("chunks").and_then(|c| c.as_array()) {
+            Some(entries) => entries,
+            None => return Err(PngMeError::InvalidJson(String::from("missing \"chunks\" array"))),
+        };
+
+        let mut chunks: Vec<Chunk> = vec!();
+        for (i, entry) in entries.iter().enumerate() {
+            let field = |name: &str| entry.get(name).ok_or_else(|| {
+                PngMeError::InvalidJson(format!("chunk {} is missing \"{}\"", i, name))
+            });
+
+            let type_string = field("type")?.as_str().ok_or_else(|| {
+                PngMeError::InvalidJson(format!("chunk {} has a type that is not a string", i))
+            })?;
+            let chunk_type = ChunkType::from_str(type_string).map_err(|e| {
+                PngMeError::InvalidJson(format!("chunk {} has an invalid type: {}", i, e))
+            })?;
+            let data = base64::decode(field("data_base64")?.as_str().ok_or_else(|| {
+                PngMeError::InvalidJson(format!("chunk {} has data that is not a string", i))
+            })?)?;
+            let crc = match field("crc")?.as_u64() {
+                Some(crc) if crc <= u32::MAX as u64 => crc as u32,
+                _ => return Err(PngMeError::InvalidJson(format!("chunk {} has an invalid crc", i))),
+            };
+
+            let chunk = Chunk::new(chunk_type, data)?;
+            if chunk.crc() != crc {
+                return Err(PngMeError::CrcMismatch {
+                    index: i,
+                    chunk_type: type_string.to_string(),
+                    expected: crc,
+                    actual: chunk.crc(),
+                })
+            }
+            chunks.push(chunk);
+        }
+
+        return Ok(Png::from_chunks(chunks))
+    }
+    /// Returns this `Png` as CBOR, an array with a `[type, data]` pair for every chunk.
+    /// The data is stored as a byte string so it is much smaller than the base64 in `to_json_string`.
+    /// The CRCs are left out, they are computed again from the type and data by `from_cbor`.
+    #[cfg(feature = "cbor")]
+    pub fn to_cbor(&self) -> Result<Vec<u8>, PngMeError> {
+        let chunks = self.chunks().iter().map(|c| {
+            return CborValue::Array(vec![
+                CborValue::Text(c.chunk_type().to_string()),
+                CborValue::Bytes(c.data().to_vec()),
+            ])
+        }).collect();
+        let mut out: Vec<u8> = vec!();
+        ciborium::into_writer(&CborValue::Array(chunks), &mut out).map_err(|e| PngMeError::InvalidCbor(e.to_string()))?;
+        return Ok(out)
+    }
+    /// Creates a `Png` from the CBOR written by `to_cbor`. The document must take up all of the
+    /// bytes and nest at most `CBOR_RECURSION_LIMIT` deep, a `Png` only needs 2 levels.
+    #[cfg(feature = "cbor")]
+    pub fn from_cbor(bytes: &[u8]) -> Result<Png, PngMeError> {
+        let mut reader = bytes;
+        let document: CborValue = ciborium::de::from_reader_with_recursion_limit(&mut reader, Png::CBOR_RECURSION_LIMIT)
+            .map_err(|e| PngMeError::InvalidCbor(e.to_string()))?;
+        if !reader.is_empty() {
+            return Err(PngMeError::InvalidCbor(format!("{} trailing bytes", reader.len())))
+        }
+        let entries = document.as_array().ok_or_else(|| {
+            PngMeError::InvalidCbor(String::from("expected an array of chunks"))
+        })?;
+
+        let mut chunks: Vec<Chunk> = vec!();
+        for (i, entry) in entries.iter().enumerate() {
+            let (type_string, data) = match entry.as_array().map(|pair| pair.as_slice()) {
+                Some([t, d]) => match (t.as_text(), d.as_bytes()) {
+                    (Some(t), Some(d)) => (t, d),
+                    _ => return Err(PngMeError::InvalidCbor(format!("chunk {} is not a text type and byte data", i))),
+                },
+                _ => return Err(PngMeError::InvalidCbor(format!("chunk {} is not a [type, data] pair", i))),
+            };
+            let chunk_type = ChunkType::from_str(type_string).map_err(|e| {
+                PngMeError::InvalidCbor(format!("chunk {} has an invalid type: {}", i, e))
+            })?;
+            chunks.push(Chunk::new(chunk_type, data.to_vec())?);
+        }
+
+        return Ok(Png::from_chunks(chunks))
+    }
+    /// Writes this `Png` to bytes and parses them back. The result should always equal `self`,
+    /// an error or a different `Png` means a bug left the chunks in an inconsistent state.
+    pub fn reparse(&self) -> Result<Png, PngMeError> {
+        let bytes = self.as_bytes();
+        return Png::try_from(bytes.as_slice())
+    }
+    /// Returns this `Png` as a base64 data URI that can be embedded in HTML or CSS:
+    /// `data:image/png;base64,iVBORw0K...`
+    pub fn to_base64_data_uri(&self) -> String {
+        return format!("{}{}", Png::DATA_URI_PREFIX, base64::encode(&self.as_bytes()))
+    }
+    /// Creates a `Png` from a data URI written by `to_base64_data_uri`.
+    /// URIs of any other media type or without base64 encoding are rejected.
+    pub fn from_base64_data_uri(uri: &str) -> Result<Png, PngMeError> {
+        let encoded = match uri.trim().strip_prefix(Png::DATA_URI_PREFIX) {
+            Some(e) => e,
+            None => return Err(PngMeError::InvalidInput(String::from("not a base64 PNG data URI"))),
+        };
+        let bytes = base64::decode(encoded)?;
+        return Png::try_from(bytes.as_ref())
+    }
+    /// Returns the bytes of this `Png` as lowercase hexadecimal without separators, `89504e47...`
+    pub fn to_hex_string(&self) -> String {
+        return self.as_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+    /// Creates a `Png` from its bytes written in hexadecimal, in either case. Whitespace,
+    /// including newlines, is ignored so test vectors can be split over several lines.
+    pub fn from_hex_string(hex: &str) -> Result<Png, PngMeError> {
+        let digits: Vec<u8> = hex
+            .chars()
+            .filter(|ch| !ch.is_whitespace())
+            .map(|ch| match ch.to_digit(16) {
+                Some(d) => Ok(d as u8),
+                None => Err(PngMeError::InvalidHex(format!("{:?} is not a hexadecimal digit", ch))),
+            })
+            .collect::<Result<Vec<u8>, PngMeError>>()?;
+        if !digits.len().is_multiple_of(2) {
+            return Err(PngMeError::InvalidHex(String::from("odd number of hexadecimal digits")))
+        }
+        let bytes: Vec<u8> = digits.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect();
+        return Png::try_from(bytes.as_ref())
+    }
+    /// Compresses the message with zlib at the given level (0 to 9) and appends it as a chunk.
+    /// The data starts with `COMPRESSED_MESSAGE_MARKER` so `decode_compressed` knows to decompress it.
+    pub fn encode_compressed(&mut self, chunk_type: ChunkType, message: &[u8], level: u32) -> Result<(), PngMeError> {
+        let mut data: Vec<u8> = vec![Png::COMPRESSED_MESSAGE_MARKER];
+        data.extend(zlib::compress(message, level)?);
+        self.add_before_iend(Chunk::new(chunk_type, data)?);
+        return Ok(())
+    }
+    /// Decompresses the message stored by `encode_compressed` in the first chunk of the given type
+    pub fn decode_compressed(&self, chunk_type: &str) -> Result<Vec<u8>, PngMeError> {
+        let chunk = self.chunk_at_type_or_err(chunk_type)?;
+        return match chunk.data().split_first() {
+            Some((&Png::COMPRESSED_MESSAGE_MARKER, stream)) => zlib::decompress(stream),
+            _ => Err(PngMeError::InvalidChunkData(format!("chunk {} does not hold a compressed message", chunk_type))),
+        }
+    }
+    /// Returns the zTXt chunk holding the same keyword and text as a tEXt chunk
+    fn text_to_ztxt(chunk: &Chunk) -> Result<Chunk, PngMeError> {
+        let separator = chunk.data().iter().position(|b| *b == 0)
+            .ok_or_else(|| PngMeError::InvalidChunkData(String::from("tEXt chunk without a keyword separator")))?;
+        // Keyword and separator, compression method 0 (zlib), then the compressed text
+        let mut data = chunk.data()[..=separator].to_vec();
+        data.push(0);
+        data.extend(zlib::compress(&chunk.data()[separator + 1..], 9)?);
+        return Chunk::new(ChunkType::from_ascii_bytes(*b"zTXt")?, data)
+    }
+    /// Shrinks the file without touching the image or the text it holds, in order:
+    /// 1. `deduplicate_chunks` removes repeated chunks of the types allowed only once
+    /// 2. tEXt chunks become zTXt where that makes them smaller
+    /// 3. wMRK watermarks and the `pADD` chunks of `pad_to_size` are removed
+    /// 4. `to_single_idat` joins the image data, saving 12 bytes per IDAT chunk
+    ///
+    /// The IDAT chunks merged into the first one count as removed. Malformed tEXt chunks are left as they are.
+    pub fn optimize(&mut self) -> Result<OptimizationReport, PngMeError> {
+        let original_size = self.file_size();
+        let mut chunks_removed = self.deduplicate_chunks().len();
+
+        let compressed: Vec<(usize, Chunk)> = self
+            .chunks()
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.chunk_type().to_string() == "tEXt")
+            .filter_map(|(i, c)| Png::text_to_ztxt(c).ok().filter(|ztxt| ztxt.length() < c.length()).map(|ztxt| (i, ztxt)))
+            .collect();
+        let chunks_compressed = compressed.len();
+        if !compressed.is_empty() {
+            let list = self.chunk_list_mut();
+            for (i, ztxt) in compressed {
+                list[i] = ztxt;
+            }
+        }
+
+        chunks_removed += self.remove_chunks_where(|c| {
+            let chunk_type = c.chunk_type().to_string();
+            return chunk_type == Chunk::WATERMARK_CHUNK_TYPE || chunk_type == Png::PADDING_CHUNK_TYPE
+        }).len();
+
+        let idat_count = self.find_chunks(|c| *c.chunk_type() == ChunkType::IDAT).len();
+        if idat_count > 1 {
+            self.to_single_idat()?;
+            chunks_removed += idat_count - 1;
+        }
+
+        return Ok(OptimizationReport {
+            original_size,
+            final_size: self.file_size(),
+            chunks_removed,
+            chunks_compressed,
+        })
+    }
+    /// Returns a copy where every tEXt chunk is converted to zTXt and every non standard
+    /// ancillary chunk has its data replaced by a zlib stream, plus a `cPRS` marker chunk before
+    /// IEND listing the changed chunks. Chunks that would not get smaller are left as they are.
+    /// Critical and other standard chunks are never touched, so decoders still read them.
+    pub fn to_compressed(&self) -> Result<Png, PngMeError> {
+        if self.chunk_by_type(Png::COMPRESSION_MARKER_CHUNK_TYPE).is_some() {
+            return Err(PngMeError::InvalidInput(String::from("the PNG is already compressed")))
+        }
+        let mut chunks: Vec<Chunk> = Vec::new();
+        let mut changed: Vec<u8> = Vec::new();
+        for (index, chunk) in self.chunks().iter().enumerate() {
+            let chunk_type = chunk.chunk_type().to_string();
+            if chunk.chunk_type().is_critical() || (chunk.chunk_type().is_standard() && chunk_type != "tEXt") {
+                chunks.push(chunk.clone());
+                continue
+            }
+            let compressed = match chunk_type.as_str() {
+                "tEXt" => Png::text_to_ztxt(chunk)?,
+                _ => Chunk::new(chunk.chunk_type().clone(), zlib::compress(chunk.data(), 9)?)?,
+            };
+            if compressed.length() < chunk.length() {
+                changed.extend((index as u32).to_be_bytes());
+                chunks.push(compressed);
+            } else {
+                chunks.push(chunk.clone());
+            }
+        }
+        let mut png = Png::from_chunks(chunks);
+        png.add_before_iend(Chunk::new(ChunkType::from_str(Png::COMPRESSION_MARKER_CHUNK_TYPE).unwrap(), changed)?);
+        return Ok(png)
+    }
+    /// Undoes `to_compressed` using the chunk indices stored in the `cPRS` marker chunk
+    pub fn from_compressed(png: &Png) -> Result<Png, PngMeError> {
+        let marker = png.chunk_at_type_or_err(Png::COMPRESSION_MARKER_CHUNK_TYPE)?;
+        if marker.data().len() % 4 != 0 {
+            return Err(PngMeError::InvalidChunkData(String::from("cPRS chunk length is not a multiple of 4")))
+        }
+        let changed: Vec<usize> = marker.data()
+            .chunks(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .collect();
+
+        let mut chunks: Vec<Chunk> = Vec::new();
+        for (index, chunk) in png.chunks().iter().enumerate() {
+            if chunk.chunk_type().to_string() == Png::COMPRESSION_MARKER_CHUNK_TYPE {
+                continue
+            }
+            if !changed.contains(&index) {
+                chunks.push(chunk.clone());
+                continue
+            }
+            let restored = match chunk.chunk_type().to_string().as_str() {
+                "zTXt" => {
+                    let separator = chunk.data().iter().position(|b| *b == 0)
+                        .ok_or_else(|| PngMeError::InvalidChunkData(String::from("zTXt chunk without a keyword separator")))?;
+                    let stream = chunk.data().get(separator + 2..)
+                        .ok_or_else(|| PngMeError::InvalidChunkData(String::from("zTXt chunk without a compression method")))?;
+                    let mut data = chunk.data()[..=separator].to_vec();
+                    data.extend(zlib::decompress(stream)?);
+                    Chunk::new(ChunkType::from_ascii_bytes(*b"tEXt")?, data)?
+                }
+                _ => Chunk::new(chunk.chunk_type().clone(), zlib::decompress(chunk.data())?)?,
+            };
+            chunks.push(restored);
+        }
+        return Ok(Png::from_chunks(chunks))
+    }
+    /// Stores the message in a chunk of the given type, before IEND, after its HMAC-SHA256 tag
+    /// computed with `key`. `extract_and_verify` with the same key gets it back and detects any
+    /// change to the message or the tag.
+    pub fn embed_and_sign(&mut self, chunk_type: ChunkType, message: &[u8], key: &[u8]) -> Result<(), PngMeError> {
+        let mut mac = Png::hmac_sha256(key);
+        mac.update(message);
+        let mut data: Vec<u8> = mac.finalize().into_bytes().to_vec();
+        data.extend(message);
+        self.add_before_iend(Chunk::new(chunk_type, data)?);
+        return Ok(())
+    }
+    /// Returns the message stored by `embed_and_sign` in the first chunk of the given type, or
+    /// `PngMeError::AuthenticationFailed` if it does not match its tag for this key
+    pub fn extract_and_verify(&self, chunk_type: &str, key: &[u8]) -> Result<Vec<u8>, PngMeError> {
+        let chunk = self.chunk_at_type_or_err(chunk_type)?;
+        if chunk.data().len() < Png::SIGNATURE_TAG_LEN {
+            return Err(PngMeError::InvalidLength{expected: Png::SIGNATURE_TAG_LEN, actual: chunk.data().len()})
+        }
+        let (tag, message) = chunk.data().split_at(Png::SIGNATURE_TAG_LEN);
+        let mut mac = Png::hmac_sha256(key);
+        mac.update(message);
+        // verify_slice compares in constant time
+        mac.verify_slice(tag).map_err(|_| PngMeError::AuthenticationFailed)?;
+        return Ok(message.to_vec())
+    }
+    // HMAC-SHA256 keyed with `key`
+    fn hmac_sha256(key: &[u8]) -> Hmac<Sha256> {
+        // HMAC hashes long keys and pads short ones, so a key of any length is accepted
+        return Hmac::<Sha256>::new_from_slice(key).unwrap()
+    }
+    /// Stores each part in its own chunk of the given type, before IEND. The data of every chunk
+    /// starts with the big endian u32 index of the part and the u32 number of parts, so
+    /// `read_chunk_sequence` can put them back together whatever order the chunks end up in.
+    pub fn add_chunk_sequence(&mut self, chunk_type: ChunkType, parts: Vec<Vec<u8>>) -> Result<(), PngMeError> {
+        let total = u32::try_from(parts.len())
+            .map_err(|_| PngMeError::InvalidInput(format!("{} parts do not fit in a sequence header", parts.len())))?;
+        // Build every chunk first so a part that is too large does not leave half a sequence behind
+        let mut chunks: Vec<Chunk> = Vec::new();
+        for (index, part) in parts.into_iter().enumerate() {
+            let mut data: Vec<u8> = Vec::with_capacity(part.len() + 8);
+            data.extend((index as u32).to_be_bytes());
+            data.extend(total.to_be_bytes());
+            data.extend(part);
+            chunks.push(Chunk::new(chunk_type.clone(), data)?);
+        }
+        self.append_chunks(chunks);
+        return Ok(())
+    }
+    /// Reads back the parts stored by `add_chunk_sequence` in chunks of the given type, sorted by
+    /// their index. Every chunk must agree on the number of parts and every part must be present once.
+    pub fn read_chunk_sequence(&self, chunk_type: &str) -> Result<Vec<Vec<u8>>, PngMeError> {
+        let chunks = self.find_chunks(|c| c.chunk_type().to_string() == chunk_type);
+        if chunks.is_empty() {
+            return Err(PngMeError::ChunkNotFound(chunk_type.to_string()))
+        }
+        let mut total: Option<u32> = None;
+        let mut parts: Vec<(u32, Vec<u8>)> = Vec::new();
+        for c in chunks {
+            if c.data().len() < 8 {
+                return Err(PngMeError::InvalidLength{expected: 8, actual: c.data().len()})
+            }
+            let index = u32::from_be_bytes([c.data()[0], c.data()[1], c.data()[2], c.data()[3]]);
+            let count = u32::from_be_bytes([c.data()[4], c.data()[5], c.data()[6], c.data()[7]]);
+            match total {
+                Some(t) if t != count => {
+                    return Err(PngMeError::InvalidChunkData(format!("sequence chunks disagree on the number of parts: {} and {}", t, count)))
+                }
+                _ => total = Some(count),
+            }
+            parts.push((index, c.data()[8..].to_vec()));
+        }
+        parts.sort_by_key(|(index, _)| *index);
+
+        let total = total.unwrap_or(0);
+        let indices_match = parts.iter().enumerate().all(|(i, (index, _))| i as u32 == *index);
+        if parts.len() != total as usize || !indices_match {
+            return Err(PngMeError::InvalidChunkData(format!("sequence of {} parts is incomplete or has duplicates", total)))
+        }
+        return Ok(parts.into_iter().map(|(_, part)| part).collect())
+    }
+    /// Returns the differences between this `Png` and `other`. Chunks are matched by type and
+    /// by their position among the chunks of that type: the second tEXt here is compared with the
+    /// second tEXt there. The differences are in the order their types first appear.
+    pub fn diff(&self, other: &Png) -> Vec<ChunkDiff> {
+        return Png::diff_chunks(self.chunks().iter().collect(), other.chunks().iter().collect())
+    }
+    /// Same as `diff` but only looking at ancillary chunks, to compare the metadata of two
+    /// versions of the same image without the image data getting in the way
+    pub fn compare_metadata(&self, other: &Png) -> Vec<ChunkDiff> {
+        return Png::diff_chunks(
+            self.find_chunks(|c| !c.chunk_type().is_critical()),
+            other.find_chunks(|c| !c.chunk_type().is_critical()),
+        )
+    }
+    /// Returns true if both have the same ancillary chunks in the same order
+    pub fn ancillary_chunks_equal(&self, other: &Png) -> bool {
+        return self.find_chunks(|c| !c.chunk_type().is_critical()) == other.find_chunks(|c| !c.chunk_type().is_critical())
+    }
+    fn diff_chunks(old: Vec<&Chunk>, new: Vec<&Chunk>) -> Vec<ChunkDiff> {
+        let mut types: Vec<&ChunkType> = vec!();
+        for c in old.iter().chain(new.iter()) {
+            if !types.contains(&c.chunk_type()) {
+                types.push(c.chunk_type());
+            }
+        }
+        let mut diffs: Vec<ChunkDiff> = vec!();
+        for t in types {
+            let old_of_type: Vec<&Chunk> = old.iter().copied().filter(|c| c.chunk_type() == t).collect();
+            let new_of_type: Vec<&Chunk> = new.iter().copied().filter(|c| c.chunk_type() == t).collect();
+            for i in 0..old_of_type.len().max(new_of_type.len()) {
+                match (old_of_type.get(i), new_of_type.get(i)) {
+                    (Some(o), Some(n)) if o != n => diffs.push(ChunkDiff::Modified{old: (*o).clone(), new: (*n).clone()}),
+                    (Some(o), None) => diffs.push(ChunkDiff::Removed((*o).clone())),
+                    (None, Some(n)) => diffs.push(ChunkDiff::Added((*n).clone())),
+                    _ => {}
+                }
+            }
+        }
+        return diffs
+    }
+    /// Returns a patch that turns this `Png` into `other`, to send changes such as new metadata
+    /// without sending the chunks both already have. The patch is a list of splices, each
+    /// replacing a run of chunks of this `Png` with chunks of `other`, found with a longest common
+    /// subsequence of the chunks (after skipping the ones they start and end with in common).
+    /// When that would take a table larger than `MAX_DIFF_TABLE_CELLS`, the patch is a single
+    /// splice replacing every chunk in between instead.
+    ///
+    /// Layout, every number a big endian u32:
+    /// `PATCH_MAGIC`, CRC of the bytes of this `Png`, its number of chunks, number of splices,
+    /// then for every splice its first index, how many chunks it removes, how many it inserts
+    /// and the inserted chunks as written in a file.
+    pub fn write_diff_patch(&self, other: &Png) -> Result<Vec<u8>, PngMeError> {
+        let old = self.chunks();
+        let new = other.chunks();
+        let prefix = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+        let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(o, n)| o == n).count();
+        let old_middle = &old[prefix..old.len() - suffix];
+        let new_middle = &new[prefix..new.len() - suffix];
+
+        // (first index, removed count, inserted chunks)
+        let mut splices: Vec<(usize, usize, Vec<&Chunk>)> = vec!();
+        let table_cells = (old_middle.len() + 1).saturating_mul(new_middle.len() + 1);
+        if table_cells > Png::MAX_DIFF_TABLE_CELLS {
+            splices.push((prefix, old_middle.len(), new_middle.iter().collect()));
+        } else {
+            // lcs[i][j] is the length of the longest common subsequence of old_middle[i..] and new_middle[j..]
+            let mut lcs = vec![vec![0u32; new_middle.len() + 1]; old_middle.len() + 1];
+            for i in (0..old_middle.len()).rev() {
+                for j in (0..new_middle.len()).rev() {
+                    lcs[i][j] = match old_middle[i] == new_middle[j] {
+                        true => lcs[i + 1][j + 1] + 1,
+                        false => lcs[i + 1][j].max(lcs[i][j + 1]),
+                    };
+                }
+            }
+
+            let (mut i, mut j) = (0, 0);
+            while i < old_middle.len() || j < new_middle.len() {
+                if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+                    i += 1;
+                    j += 1;
+                    continue
+                }
+                let start = i;
+                let mut inserted: Vec<&Chunk> = vec!();
+                while i < old_middle.len() || j < new_middle.len() {
+                    if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+                        break
+                    }
+                    if j == new_middle.len() || (i < old_middle.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+                        i += 1;
+                    } else {
+                        inserted.push(&new_middle[j]);
+                        j += 1;
+                    }
+                }
+                splices.push((prefix + start, i - start, inserted));
+            }
+        }
+
+        const ISO: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+        let count = |n: usize| u32::try_from(n).map_err(|_| PngMeError::DataTooLarge(n));
+        let mut patch: Vec<u8> = Png::PATCH_MAGIC.to_vec();
+        patch.extend(ISO.checksum(&self.as_bytes()).to_be_bytes());
+        patch.extend(count(old.len())?.to_be_bytes());
+        patch.extend(count(splices.len())?.to_be_bytes());
+        for (start, removed, inserted) in splices {
+            patch.extend(count(start)?.to_be_bytes());
+            patch.extend(count(removed)?.to_be_bytes());
+            patch.extend(count(inserted.len())?.to_be_bytes());
+            for c in inserted {
+                patch.extend(c.as_bytes());
+            }
+        }
+        return Ok(patch)
+    }
+    /// Applies a patch written by `write_diff_patch`. The patch only applies to the exact `Png` it
+    /// was made from, so applying it a second time fails, and on error nothing is changed.
+    pub fn apply_patch(&mut self, patch: &[u8]) -> Result<(), PngMeError> {
+        let invalid = |message: &str| PngMeError::InvalidInput(format!("invalid patch: {}", message));
+        let mut rest = patch.strip_prefix(&Png::PATCH_MAGIC[..]).ok_or_else(|| invalid("missing magic bytes"))?;
+        let next_u32 = |rest: &mut &[u8]| -> Result<usize, PngMeError> {
+            let (number, tail) = rest.split_first_chunk::<4>().ok_or_else(|| invalid("unexpected end"))?;
+            *rest = tail;
+            return Ok(u32::from_be_bytes(*number) as usize)
+        };
+
+        const ISO: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+        let base_crc = next_u32(&mut rest)? as u32;
+        let base_count = next_u32(&mut rest)?;
+        if base_count != self.chunk_list.len() || base_crc != ISO.checksum(&self.as_bytes()) {
+            return Err(PngMeError::InvalidInput(String::from(
+                "the patch was made for another PNG, it may already have been applied"
+            )))
+        }
+
+        let mut chunks = self.chunks().to_vec();
+        // Indices refer to the chunks before patching, so the splices are applied from the last
+        let mut splices: Vec<(usize, usize, Vec<Chunk>)> = vec!();
+        let mut end_of_previous = 0;
+        for _ in 0..next_u32(&mut rest)? {
+            let start = next_u32(&mut rest)?;
+            let removed = next_u32(&mut rest)?;
+            if start < end_of_previous || start.checked_add(removed).is_none_or(|end| end > base_count) {
+                return Err(invalid("a splice is out of order or out of range"))
+            }
+            end_of_previous = start + removed;
+
+            let mut inserted: Vec<Chunk> = vec!();
+            for _ in 0..next_u32(&mut rest)? {
+                let length = u32::from_be_bytes(*rest.first_chunk::<4>().ok_or_else(|| invalid("unexpected end"))?) as usize;
+                if rest.len() < 12 + length {
+                    return Err(invalid("unexpected end"))
+                }
+                let (bytes, tail) = rest.split_at(12 + length);
+                inserted.push(Chunk::try_from(bytes).map_err(invalid)?);
+                rest = tail;
+            }
+            splices.push((start, removed, inserted));
+        }
+        if !rest.is_empty() {
+            return Err(invalid("trailing bytes"))
+        }
+
+        for (start, removed, inserted) in splices.into_iter().rev() {
+            chunks.splice(start..start + removed, inserted);
+        }
+        *self.chunk_list_mut() = chunks;
+        return Ok(())
+    }
+    /// Returns the time of the last modification stored in the tIME chunk, if there is one
+    pub fn modification_time(&self) -> Result<Option<PngTime>, PngMeError> {
+        return match self.chunk_by_type("tIME") {
+            Some(c) => Ok(Some(PngTime::try_from(c)?)),
+            None => Ok(None),
+        }
+    }
+    /// Stores the time of the last modification, replacing the tIME chunk if there is one
+    pub fn set_modification_time(&mut self, time: PngTime) {
+        // tIME is made of valid letters and its 7 bytes always fit so this can not fail
+        let chunk = Chunk::new(ChunkType::from_str("tIME").unwrap(), time.to_bytes().to_vec()).unwrap();
+        match self.chunks().iter().position(|c| c.chunk_type().to_string() == "tIME") {
+            Some(i) => self.chunk_list_mut()[i] = chunk,
+            None => self.add_before_iend(chunk),
+        }
+    }
+    /// Stores the current time of the system clock as the time of the last modification
+    pub fn touch(&mut self) -> Result<(), PngMeError> {
+        self.set_modification_time(PngTime::now());
+        return Ok(())
+    }
+    /// Removes the tIME chunk. Returns false if there was none.
+    pub fn remove_modification_time(&mut self) -> bool {
+        return self.remove_first_chunk("tIME").is_ok()
+    }
+    /// Returns the background color stored in the bKGD chunk, read according to the color type
+    /// in IHDR, if there is one
+    pub fn background_color(&self) -> Result<Option<BackgroundColor>, PngMeError> {
+        let chunk = match self.chunk_by_type("bKGD") {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let color_type = IhdrData::try_from(self.ihdr_or_err()?)?.color_type;
+        return Ok(Some(BackgroundColor::from_bytes(chunk.data(), color_type)?))
+    }
+    /// Stores the background color, replacing the bKGD chunk if there is one. Otherwise the
+    /// chunk goes before the first IDAT, as the spec requires. The color must be of the form
+    /// used by the color type in IHDR.
+    pub fn set_background_color(&mut self, color: BackgroundColor) -> Result<(), PngMeError> {
+        let color_type = IhdrData::try_from(self.ihdr_or_err()?)?.color_type;
+        if !color.is_valid_for_color_type(color_type) {
+            return Err(PngMeError::InvalidInput(format!(
+                "{:?} is not a background color for color type {}", color, color_type
+            )))
+        }
+        let chunk = Chunk::new(ChunkType::from_str("bKGD").unwrap(), color.to_bytes())?;
+        match self.position_of(|c| c.chunk_type().to_string() == "bKGD") {
+            Some(i) => self.chunk_list_mut()[i] = chunk,
+            None => match self.position_of(|c| *c.chunk_type() == ChunkType::IDAT) {
+                Some(i) => self.chunk_list_mut().insert(i, chunk),
+                None => self.add_before_iend(chunk),
+            },
+        }
+        return Ok(())
+    }
+    /// Returns the transparency stored in the tRNS chunk, read according to the color type in
+    /// IHDR, if there is one
+    pub fn transparency(&self) -> Result<Option<Transparency>, PngMeError> {
+        let chunk = match self.chunk_by_type("tRNS") {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let color_type = IhdrData::try_from(self.ihdr_or_err()?)?.color_type;
+        return Ok(Some(Transparency::from_bytes(chunk.data(), color_type)?))
+    }
+    /// Stores the transparency, replacing the tRNS chunk if there is one. Otherwise the chunk
+    /// goes before the first IDAT, as the spec requires. The transparency must be of the form
+    /// used by the color type in IHDR, and an indexed one can not have more alpha values than
+    /// there are palette entries.
+    pub fn set_transparency(&mut self, t: Transparency) -> Result<(), PngMeError> {
+        let color_type = IhdrData::try_from(self.ihdr_or_err()?)?.color_type;
+        t.validate_for_color_type(color_type)?;
+        if let (Transparency::Indexed(alphas), Some(plte)) = (&t, self.chunk_by_type("PLTE")) {
+            if alphas.len() > plte.data().len() / 3 {
+                return Err(PngMeError::InvalidInput(format!(
+                    "{} alpha values for a palette of {} entries", alphas.len(), plte.data().len() / 3
+                )))
+            }
+        }
+        let chunk = Chunk::new(ChunkType::from_str("tRNS").unwrap(), t.to_bytes())?;
+        match self.position_of(|c| c.chunk_type().to_string() == "tRNS") {
+            Some(i) => self.chunk_list_mut()[i] = chunk,
+            None => match self.position_of(|c| *c.chunk_type() == ChunkType::IDAT) {
+                Some(i) => self.chunk_list_mut().insert(i, chunk),
+                None => self.add_before_iend(chunk),
+            },
+        }
+        return Ok(())
+    }
+    /// Stores `key=value` in the annotation chunk, creating the chunk if there is none.
+    /// Every call adds a new line, so a key that is annotated twice keeps both values
+    /// and `get_annotation` returns the latest one.
+    pub fn annotate_chunk(&mut self, key: &str, value: &str) -> Result<(), PngMeError> {
+        if key.is_empty() || key.contains('=') || key.contains('\n') {
+            return Err(PngMeError::InvalidInput(format!("invalid annotation key \"{}\"", key)))
+        }
+        if value.contains('\n') {
+            return Err(PngMeError::InvalidInput(String::from("annotation values can not contain new lines")))
+        }
+
+        let line = format!("{}={}", key, value);
+        let position = self.chunks().iter().position(|c| c.chunk_type().to_string() == Png::ANNOTATION_CHUNK_TYPE);
+        match position {
+            Some(i) => {
+                let mut data = self.chunk_list[i].data().to_vec();
+                if !data.is_empty() {
+                    data.push(b'\n');
+                }
+                data.extend(line.bytes());
+                self.chunk_list_mut()[i] = Chunk::new(self.chunk_list[i].chunk_type().clone(), data)?;
+            }
+            None => {
+                // The annotation type is made of valid letters so this can not fail
+                let chunk_type = ChunkType::from_str(Png::ANNOTATION_CHUNK_TYPE).unwrap();
+                self.add_before_iend(Chunk::new(chunk_type, line.into_bytes())?);
+            }
+        }
+        return Ok(())
+    }
+    /// Returns the latest value annotated with `key`
+    pub fn get_annotation(&self, key: &str) -> Option<String> {
+        return self
+            .all_annotations()
+            .into_iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+    /// Lists every `key=value` pair stored in the annotation chunk in the order they were added.
+    /// Lines that are not valid UTF-8 or have no `=` are skipped.
+    pub fn all_annotations(&self) -> Vec<(String, String)> {
+        let text = match self.chunk_by_type(Png::ANNOTATION_CHUNK_TYPE).map(|c| c.data_as_string()) {
+            Some(Ok(text)) => text,
+            _ => return vec!(),
+        };
+
+        return text
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+}
+
+// https://doc.rust-lang.org/std/convert/trait.TryFrom.html
+impl TryFrom<&[u8]> for Png {
+    type Error = PngMeError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let mut rest = match bytes.strip_prefix(&Png::STANDARD_HEADER[..]) {
+            Some(rest) => rest,
+            None => return Err(PngMeError::InvalidInput(String::from("the file does not start with the PNG header"))),
+        };
+
+        let mut chunks: Vec<Chunk> = vec!();
+        while !rest.is_empty() {
+            let index = chunks.len();
+            let (chunk, stored_crc, tail) = Png::read_chunk(rest)?;
+            if chunk.crc() != stored_crc {
+                return Err(PngMeError::CrcMismatch {
+                    index,
+                    chunk_type: chunk.chunk_type().to_string(),
+                    expected: stored_crc,
+                    actual: chunk.crc(),
+                })
+            }
+            chunks.push(chunk);
+            rest = tail;
+        }
+        return Ok(Png::with_chunk_list(chunks))
+    }
+}
+
+impl TryFrom<Vec<u8>> for Png {
+    type Error = PngMeError;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        return Png::try_from(bytes.as_slice())
+    }
+}
+
+// https://doc.rust-lang.org/std/cmp/trait.PartialEq.html
+// Only the chunks are compared, not how many changes it took to get them
+impl PartialEq for Png {
+    fn eq(&self, other: &Self) -> bool {
+        return self.chunk_list == other.chunk_list
+    }
+}
+
+impl Eq for Png {}
+
+// https://doc.rust-lang.org/std/convert/trait.From.html
+impl From<Png> for Vec<u8> {
+    fn from(png: Png) -> Self {
+        return png.as_bytes()
+    }
+}
+
+// https://doc.rust-lang.org/std/iter/trait.FromIterator.html
+/// Collects chunks into a `Png` that passes `Png::validate`, or the first problem found
+impl FromIterator<Chunk> for Result<Png, PngMeError> {
+    fn from_iter<I: IntoIterator<Item = Chunk>>(iter: I) -> Self {
+        let png = Png::from_chunks(iter.into_iter().collect());
+        png.validate()?;
+        return Ok(png)
+    }
+}
+
+// https://doc.rust-lang.org/std/iter/trait.Extend.html
+/// Inserts the chunks before IEND, like `append_chunks`
+impl Extend<Chunk> for Png {
+    fn extend<I: IntoIterator<Item = Chunk>>(&mut self, iter: I) {
+        self.append_chunks(iter.into_iter().collect());
+    }
+}
+
+// https://doc.rust-lang.org/std/fmt/trait.Display.html
+impl fmt::Display for Png {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Png {{")?;
+        let h = self.header();
+        writeln!(f, "  Header: {} {} {} {} {} {} {} {}", h[0], h[1], h[2], h[3], h[4], h[5], h[6], h[7])?;
+
+        for (i, c) in self.chunks().iter().enumerate() {
+            writeln!(f, "  Chunk {}: {}", i, c)?;
+        }
+
+        writeln!(f, "}}",)?;
+        Ok(())
+    }
+}
+
+/// Physical size of an image, as returned by `Png::print_size`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrintSize {
+    pub width_cm: f64,
+    pub height_cm: f64,
+    /// Dots (pixels) per inch along the horizontal axis
+    pub dpi: f64,
+}
+
+/// Statistics about the data lengths of a group of chunks, as returned by `Png::chunk_statistics`.
+/// The lengths do not include the 12 bytes of length, type and CRC of every chunk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChunkStats {
+    pub count: usize,
+    pub total_bytes: u64,
+    pub min_length: u32,
+    pub max_length: u32,
+    pub mean_length: f64,
+    /// Population standard deviation of the lengths
+    pub std_dev: f64,
+}
+
+impl ChunkStats {
+    fn from_chunks(chunks: Vec<&Chunk>) -> ChunkStats {
+        let lengths: Vec<u32> = chunks.iter().map(|c| c.length()).collect();
+        let count = lengths.len();
+        let total_bytes: u64 = lengths.iter().map(|l| *l as u64).sum();
+        let mean_length = match count {
+            0 => 0.0,
+            _ => total_bytes as f64 / count as f64,
+        };
+        let variance = match count {
+            0 => 0.0,
+            _ => lengths.iter().map(|l| (*l as f64 - mean_length).powi(2)).sum::<f64>() / count as f64,
+        };
+        return ChunkStats {
+            count,
+            total_bytes,
+            min_length: lengths.iter().copied().min().unwrap_or(0),
+            max_length: lengths.iter().copied().max().unwrap_or(0),
+            mean_length,
+            std_dev: variance.sqrt(),
+        }
+    }
+}
+
+// https://doc.rust-lang.org/std/fmt/trait.Display.html
+impl fmt::Display for ChunkStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} chunks, {} bytes, min {}, max {}, mean {:.2}, std dev {:.2}",
+            self.count, self.total_bytes, self.min_length, self.max_length, self.mean_length, self.std_dev
+        )
+    }
+}
+
+/// Bytes of a file taken by each category of chunk, as returned by `Png::count_bytes_by_category`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ByteBreakdown {
+    /// Data of IHDR, PLTE, IDAT and IEND
+    pub critical_bytes: u64,
+    /// Data of tEXt, zTXt and iTXt
+    pub text_bytes: u64,
+    /// Data of gAMA, cHRM, sRGB and iCCP
+    pub color_management_bytes: u64,
+    /// Data of tIME
+    pub time_bytes: u64,
+    /// Data of every other chunk
+    pub custom_bytes: u64,
+    /// The header, and the length, type and CRC of every chunk
+    pub overhead_bytes: u64,
+}
+
+impl ByteBreakdown {
+    /// Returns the sum of every category, the size of the file
+    pub fn total(&self) -> u64 {
+        return self.critical_bytes
+            + self.text_bytes
+            + self.color_management_bytes
+            + self.time_bytes
+            + self.custom_bytes
+            + self.overhead_bytes
+    }
+}
+
+/// Result of `Png::contains_hidden_payload`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HiddenPayloadAnalysis {
+    /// Some chunk has a type the PNG spec does not define
+    pub has_nonstandard_chunks: bool,
+    /// Types of the nonstandard and high entropy chunks, each once, in the order they were found
+    pub suspicious_chunk_types: Vec<ChunkType>,
+    /// Some ancillary chunk has data that looks random
+    pub unusual_data_entropy: bool,
+}
+
+/// What `Png::optimize` did, with the sizes of the file before and after
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptimizationReport {
+    pub original_size: u64,
+    pub final_size: u64,
+    pub chunks_removed: usize,
+    /// tEXt chunks converted to zTXt
+    pub chunks_compressed: usize,
+}
+
+// https://doc.rust-lang.org/std/fmt/trait.Display.html
+impl fmt::Display for OptimizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes to {} bytes, {} chunks removed, {} chunks compressed",
+            self.original_size, self.final_size, self.chunks_removed, self.chunks_compressed
+        )
+    }
+}
+
+/// A difference between two `Png`s, as returned by `Png::diff`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkDiff {
+    /// A chunk only the second `Png` has
+    Added(Chunk),
+    /// A chunk only the first `Png` has
+    Removed(Chunk),
+    /// A chunk whose data changed
+    Modified { old: Chunk, new: Chunk },
+}
+
+/// Reads the data of the IDAT chunks of a `Png` one chunk after the other, created by
+/// `Png::idat_reader`. Each `read` copies from at most one chunk.
+#[derive(Debug)]
+pub struct IdatReader<'a> {
+    chunks: Vec<&'a Chunk>,
+    chunk_index: usize,
+    // Position in the data of the current chunk
+    offset: usize,
+}
+
+// https://doc.rust-lang.org/std/io/trait.Read.html
+impl Read for IdatReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0)
+        }
+        // Skip the chunks that are already read, and the empty ones
+        while let Some(c) = self.chunks.get(self.chunk_index) {
+            let remaining = &c.data()[self.offset..];
+            if remaining.is_empty() {
+                self.chunk_index += 1;
+                self.offset = 0;
+                continue
+            }
+            let n = remaining.len().min(buf.len());
+            buf[..n].copy_from_slice(&remaining[..n]);
+            self.offset += n;
+            return Ok(n)
+        }
+        return Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::chunk::Chunk;
+    use crate::ihdr::IhdrData;
+    use std::str::FromStr;
+    use std::convert::TryFrom;
+
+    fn testing_chunks() -> Vec<Chunk> {
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
+    }
+
+    fn testing_png() -> Png {
+        let chunks = testing_chunks();
+        Png::from_chunks(chunks)
+    }
+    // I modified this function because the result needs a second type for the error
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk, String> {
+        // I commented this import
+        //use std::str::FromStr;
+
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+        let data: Vec<u8> = data.bytes().collect();
+
+        Ok(Chunk::new(chunk_type, data).unwrap())
+    }
+
+    #[test]
+    fn test_from_chunks() {
+        let chunks = testing_chunks();
+        let png = Png::from_chunks(chunks);
+
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_valid_from_bytes() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(chunk_bytes.iter())
+            .copied()
+            .collect();
+
+        let png = Png::try_from(bytes.as_ref());
+
+        assert!(png.is_ok());
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        let bytes: Vec<u8> = [13, 80, 78, 71, 13, 10, 26, 10]
+            .iter()
+            .chain(chunk_bytes.iter())
+            .copied()
+            .collect();
+
+        let png = Png::try_from(bytes.as_ref());
+
+        assert!(png.is_err());
+    }
+
+    #[test]
+    fn test_invalid_chunk() {
+        let mut chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        #[rustfmt::skip]
+        let mut bad_chunk = vec![
+            0, 0, 0, 5,         // length
+            32, 117, 83, 116,   // Chunk Type (bad)
+            65, 64, 65, 66, 67, // Data
+            1, 2, 3, 4, 5       // CRC (bad)
+        ];
+
+        chunk_bytes.append(&mut bad_chunk);
+
+        let png = Png::try_from(chunk_bytes.as_ref());
+
+        assert!(png.is_err());
+    }
+
+
+    #[test]
+    fn test_list_chunks() {
+        let png = testing_png();
+        let chunks = png.chunks();
+        assert_eq!(chunks.len(), 3);
+    }
+
+    #[test]
+    fn test_all_chunk_types() {
+        let mut png = framed_png();
+        png.add_after_ihdr(chunk_from_strings("tEXt", "a\0b").unwrap());
+        png.add_after_ihdr(chunk_from_strings("tEXt", "c\0d").unwrap());
+
+        let types: Vec<String> = png.all_chunk_types().iter().map(|t| t.to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "tEXt", "IDAT", "IEND"]);
+        assert_eq!(png.count_unique_types(), 4);
+        assert_eq!(Png::from_chunks(vec!()).count_unique_types(), 0);
+    }
+
+    #[test]
+    fn test_image_type_string() {
+        let expected = [
+            (0, "Grayscale", false, false),
+            (2, "RGB", true, false),
+            (3, "Indexed", true, false),
+            (4, "Grayscale+Alpha", false, true),
+            (6, "RGBA", true, true),
+        ];
+        for (color_type, name, color, alpha) in expected {
+            let png = Png::new_blank(2, 2, color_type, 8).unwrap();
+            assert_eq!(png.image_type_string().unwrap(), name);
+            assert_eq!(png.is_color(), color);
+            assert_eq!(png.has_alpha(), alpha);
+        }
+
+        let mut ihdr = IhdrData::try_from(&framed_png().chunks()[0]).unwrap();
+        ihdr.color_type = 5;
+        let png = Png::from_chunks(vec![Chunk::new(ChunkType::IHDR, ihdr.to_bytes()).unwrap()]);
+        assert!(matches!(png.image_type_string(), Err(PngMeError::InvalidChunkData(_))));
+        assert!(!png.is_color());
+        assert!(!png.has_alpha());
+        assert!(matches!(testing_png().image_type_string(), Err(PngMeError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_get_chunk_data() {
+        let png = testing_png();
+        let data = png.get_chunk_data("miDl").unwrap();
+        match data {
+            Cow::Borrowed(d) => assert!(std::ptr::eq(d, png.chunk_by_type("miDl").unwrap().data())),
+            Cow::Owned(_) => panic!("expected borrowed data"),
+        }
+        assert_eq!(&*data, b"I am another chunk");
+        assert!(png.get_chunk_data("gAMA").is_none());
+    }
+
+    #[test]
+    fn test_find_chunk_by_crc() {
+        let mut png = testing_png();
+        const ISO: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+        let crc = ISO.checksum(b"miDlI am another chunk");
+
+        let (index, chunk) = png.find_chunk_by_crc(crc).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(chunk, &png.chunks()[1]);
+        assert!(png.find_chunk_by_crc(crc ^ 1).is_none());
+
+        png.add_before_iend(chunk_from_strings("miDl", "I am another chunk").unwrap());
+        let indices: Vec<usize> = png.find_all_chunks_by_crc(crc).iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, vec![1, 3]);
+        assert!(png.find_all_chunks_by_crc(crc ^ 1).is_empty());
+    }
+
+    #[test]
+    fn test_chunk_maps() {
+        let mut png = framed_png();
+        png.add_after_ihdr(chunk_from_strings("gAMA", "gama").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "a\0first").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "b\0second").unwrap());
+        png.insert_idat(b"more".to_vec()).unwrap();
+        png.insert_idat(b"last".to_vec()).unwrap();
+
+        let text_type = ChunkType::from_str("tEXt").unwrap();
+        let map = png.to_chunk_map();
+        assert_eq!(map.len(), 5);
+        assert_eq!(map[&ChunkType::from_str("gAMA").unwrap()].len(), 1);
+        let texts: Vec<&[u8]> = map[&text_type].iter().map(|c| c.data()).collect();
+        assert_eq!(texts, vec![&b"a\0first"[..], b"b\0second"]);
+        let idats: Vec<&[u8]> = map[&ChunkType::IDAT].iter().map(|c| c.data()).collect();
+        assert_eq!(idats, vec![&b"pixels"[..], b"more", b"last"]);
+
+        let expected_texts: Vec<Chunk> = map[&text_type].iter().map(|c| (*c).clone()).collect();
+        let map = png.into_chunk_map();
+        assert_eq!(map[&text_type], expected_texts);
+        assert_eq!(map[&ChunkType::IDAT].len(), 3);
+        assert_eq!(map[&ChunkType::IEND].len(), 1);
+    }
+
+    #[test]
+    fn test_chunk_by_type() {
+        let png = testing_png();
+        let chunk = png.chunk_by_type("FrSt").unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "FrSt");
+        assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
+
+    }
+
+    #[test]
+    fn test_find_chunk_by_predicate() {
+        let mut png = testing_png();
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0first").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "Author\0someone").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0second").unwrap());
+
+        let by_type = png.find_chunk(|c| c.chunk_type().to_string() == "miDl").unwrap();
+        assert_eq!(by_type.data_as_string().unwrap(), "I am another chunk");
+
+        let is_comment = |c: &Chunk| c.chunk_type().to_string() == "tEXt" && c.data().starts_with(b"Comment\0");
+        let comment = png.find_chunk(is_comment).unwrap();
+        assert_eq!(comment.data_as_string().unwrap(), "Comment\0first");
+
+        let comments = png.find_chunks(is_comment);
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[1].data_as_string().unwrap(), "Comment\0second");
+
+        assert_eq!(png.position_of(|c| c.data().starts_with(b"Author")), Some(4));
+        assert_eq!(png.position_of(|c| c.chunk_type().to_string() == "zTXt"), None);
+        assert!(png.find_chunk(|c| c.length() > 1000).is_none());
+        assert!(png.find_chunks(|c| c.length() > 1000).is_empty());
+    }
+
+    #[test]
+    fn test_chunk_at_type_or_err() {
+        let png = testing_png();
+        assert_eq!(png.chunk_at_type_or_err("miDl").unwrap().data_as_string().unwrap(), "I am another chunk");
+
+        let error = png.chunk_at_type_or_err("ruSt").unwrap_err();
+        assert!(matches!(&error, PngMeError::ChunkNotFound(t) if t == "ruSt"));
+        assert_eq!(error.to_string(), "ChunkNotFound: no chunk of type \"ruSt\"");
+    }
+
+    #[test]
+    fn test_first_idat_and_ihdr_or_err() {
+        let png = framed_png();
+        assert_eq!(png.first_idat().unwrap().chunk_type(), &ChunkType::IDAT);
+        assert_eq!(png.ihdr_or_err().unwrap().chunk_type(), &ChunkType::IHDR);
+
+        let png = testing_png();
+        let error = png.first_idat().unwrap_err();
+        assert!(matches!(&error, PngMeError::ChunkNotFound(t) if t == "IDAT"));
+        assert_eq!(error.to_string(), "ChunkNotFound: no chunk of type \"IDAT\"");
+        let error = png.ihdr_or_err().unwrap_err();
+        assert!(matches!(&error, PngMeError::ChunkNotFound(t) if t == "IHDR"));
+        assert_eq!(error.to_string(), "ChunkNotFound: no chunk of type \"IHDR\"");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_append_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        let chunk = png.chunk_by_type("TeSt").unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "TeSt");
+        assert_eq!(&chunk.data_as_string().unwrap(), "Message");
+
+        let mut png = framed_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(types_of(png.chunks()), vec!["IHDR", "IDAT", "TeSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_add_before_iend() {
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("ruSt", "first").unwrap());
+        png.add_before_iend(chunk_from_strings("seCr", "second").unwrap());
+        assert_eq!(types_of(png.chunks()), vec!["IHDR", "IDAT", "ruSt", "seCr", "IEND"]);
+        assert!(png.is_structurally_complete());
+
+        // Without IEND the chunk goes at the end
+        let mut png = testing_png();
+        png.add_before_iend(chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(types_of(png.chunks()), vec!["FrSt", "miDl", "LASt", "TeSt"]);
+    }
+
+    #[test]
+    fn test_append_chunks() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let before = png.chunks().len();
+        let chunks: Vec<Chunk> = (0..100).map(|i| chunk_from_strings("ruSt", &i.to_string()).unwrap()).collect();
+        png.append_chunks(chunks);
+
+        assert_eq!(png.chunks().len(), before + 100);
+        assert!(png.is_structurally_complete());
+        let added = &png.chunks()[before - 1..before + 99];
+        for (i, c) in added.iter().enumerate() {
+            assert_eq!(c.data_as_string().unwrap(), i.to_string());
+        }
+    }
+
+    // Run with `cargo test --release bench_append_chunks -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_append_chunks() {
+        let chunks: Vec<Chunk> = (0..100).map(|i| chunk_from_strings("ruSt", &i.to_string()).unwrap()).collect();
+        // Both loops pay the same for copying the starting chunks. Every insertion before IEND
+        // only has to move IEND, so both take about the same time: append_chunks is mostly a
+        // convenience for adding many chunks at once.
+        let mut base: Vec<Chunk> = (0..1000).map(|_| chunk_from_strings("tEXt", "a\0b").unwrap()).collect();
+        base.push(Chunk::new_iend());
+        let runs = 10000;
+
+        let start = std::time::Instant::now();
+        for _ in 0..runs {
+            let mut png = Png::from_chunks(base.clone());
+            for c in chunks.iter().cloned() {
+                png.add_before_iend(c);
+            }
+        }
+        println!("100 add_before_iend calls: {:?} per run", start.elapsed() / runs);
+
+        let start = std::time::Instant::now();
+        for _ in 0..runs {
+            let mut png = Png::from_chunks(base.clone());
+            png.append_chunks(chunks.clone());
+        }
+        println!("1 append_chunks call:      {:?} per run", start.elapsed() / runs);
+    }
+
+    #[test]
+    fn test_add_after_ihdr() {
+        let mut png = framed_png();
+        png.add_after_ihdr(chunk_from_strings("gAMA", "gama").unwrap());
+        png.add_after_ihdr(chunk_from_strings("cHRM", "chrm").unwrap());
+        assert_eq!(types_of(png.chunks()), vec!["IHDR", "cHRM", "gAMA", "IDAT", "IEND"]);
+        assert!(png.is_structurally_complete());
+
+        // Without IHDR the chunk goes at the start
+        let mut png = testing_png();
+        png.add_after_ihdr(chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(types_of(png.chunks()), vec!["TeSt", "FrSt", "miDl", "LASt"]);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_remove_first_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        png.remove_first_chunk("TeSt").unwrap();
+        let chunk = png.chunk_by_type("TeSt");
+        assert!(chunk.is_none());
+    }
+
+    #[test]
+    fn test_remove_first_chunk_keeps_later_chunks() {
+        let mut png = testing_png();
+        let removed = png.remove_first_chunk("miDl").unwrap();
+        assert_eq!(&removed.data_as_string().unwrap(), "I am another chunk");
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["FrSt", "LASt"]);
+    }
+
+    #[test]
+    fn test_png_from_image_file() {
+        let png = Png::try_from(&PNG_FILE[..]);
+        assert!(png.is_ok());
+    }
+
+    #[test]
+    fn test_as_bytes() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let actual = png.as_bytes();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_png_trait_impls() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(chunk_bytes.iter())
+            .copied()
+            .collect();
+
+        let png: Png = TryFrom::try_from(bytes.as_ref()).unwrap();
+
+        let _png_string = format!("{}", png);
+    }
+
+    #[test]
+    fn test_reparse() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.reparse().unwrap(), png);
+
+        png.add_before_iend(chunk_from_strings("ruSt", "added").unwrap());
+        assert_eq!(png.reparse().unwrap(), png);
+        png.remove_first_chunk("gAMA").unwrap();
+        assert_eq!(png.reparse().unwrap(), png);
+        png.annotate_chunk("key", "value").unwrap();
+        png.replace_image_data(&[1; 70000]);
+        assert_eq!(png.reparse().unwrap(), png);
+    }
+
+    #[test]
+    fn test_truncated_file() {
+        let bytes = Png::try_from(&PNG_FILE[..]).unwrap().as_bytes();
+        // Cut inside the length of a chunk, inside its data and inside its CRC
+        for len in [0, 5, 8 + 2, 8 + 20, bytes.len() - 2] {
+            assert!(Png::try_from(&bytes[..len]).is_err());
+        }
+        // A length far larger than the file
+        let mut huge = bytes.clone();
+        huge[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(Png::try_from(huge.as_ref()), Err(PngMeError::InvalidLength { .. })));
+    }
+
+    #[test]
+    fn test_bad_crc() {
+        let mut bytes = testing_png().as_bytes();
+        *bytes.last_mut().unwrap() ^= 1;
+        let error = Png::try_from(bytes.as_ref()).unwrap_err();
+        assert!(matches!(error, PngMeError::CrcMismatch { index: 2, .. }));
+    }
+
+    #[test]
+    fn test_vec_conversions() {
+        let png = Png::try_from(PNG_FILE.to_vec()).unwrap();
+        assert_eq!(Vec::<u8>::from(png), PNG_FILE.to_vec());
+        assert!(Png::try_from(vec![0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_eq() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let reparsed = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(reparsed, png);
+
+        let mut changed = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        changed.add_before_iend(chunk_from_strings("ruSt", "extra").unwrap());
+        assert_ne!(changed, png);
+    }
+
+    #[test]
+    fn test_to_hexdump() {
+        let png = Png::from_chunks(vec![Chunk::new_iend()]);
+        assert_eq!(
+            png.to_hexdump(),
+            "00000000: 8950 4e47 0d0a 1a0a 0000 0000 4945 4e44  .PNG........IEND\n\
+             00000010: ae42 6082                                .B`.\n"
+        );
+    }
+
+    #[test]
+    fn test_to_writer() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut out: Vec<u8> = vec!();
+        png.to_writer(&mut out).unwrap();
+        assert_eq!(out, PNG_FILE.to_vec());
+
+        // A buffer smaller than most chunks still gives the same bytes
+        let mut out: Vec<u8> = vec!();
+        png.to_writer_buffered(&mut out, 16).unwrap();
+        assert_eq!(out, PNG_FILE.to_vec());
+    }
+
+    // Run with `cargo test --release bench_to_writer_buffered -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_to_writer_buffered() {
+        // 64 MB of 8 KB chunks, like the IDAT chunks most encoders write, plus many small chunks
+        let mut png = framed_png();
+        for i in 0..8192 {
+            png.add_before_iend(Chunk::new(ChunkType::IDAT, vec![0x55; 8192]).unwrap());
+            for _ in 0..16 {
+                png.add_before_iend(chunk_from_strings("tEXt", &format!("n\0{}", i)).unwrap());
+            }
+        }
+        let path = std::env::temp_dir().join(format!("pngme-{}-bench.png", std::process::id()));
+
+        for buffer_size in [4 * 1024, 64 * 1024, 1024 * 1024] {
+            let start = std::time::Instant::now();
+            png.to_writer_buffered(std::fs::File::create(&path).unwrap(), buffer_size).unwrap();
+            let elapsed = start.elapsed();
+            let mb = png.as_bytes().len() as f64 / (1024.0 * 1024.0);
+            println!("{:>8} byte buffer: {:?} ({:.0} MB/s)", buffer_size, elapsed, mb / elapsed.as_secs_f64());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let png = testing_png();
+        let json = png.to_json_string();
+        let parsed = Png::from_json_str(&json).unwrap();
+        assert_eq!(parsed.as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_json_round_trip_image_file() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let parsed = Png::from_json_str(&png.to_json_string()).unwrap();
+        assert_eq!(parsed.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_json_edited_data_fails_crc() {
+        let json = testing_png().to_json_string();
+        let first_data = base64::encode(b"I am the first chunk");
+        let edited = json.replace(&first_data, &base64::encode(b"I am an edited chunk"));
+        let png = Png::from_json_str(&edited);
+        assert!(matches!(png, Err(PngMeError::CrcMismatch { index: 0, .. })));
+    }
+
+    #[test]
+    fn test_json_invalid_document() {
+        assert!(Png::from_json_str("{ \"chunks\": 3 }").is_err());
+        assert!(Png::from_json_str("{ \"chunks\": [ { \"type\": \"RuSt\" } ] }").is_err());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor_round_trip() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let cbor = png.to_cbor().unwrap();
+        let parsed = Png::from_cbor(&cbor).unwrap();
+        assert_eq!(parsed, png);
+        assert_eq!(parsed.as_bytes(), PNG_FILE.to_vec());
+
+        // Binary data is stored as is rather than as base64 text
+        assert!(cbor.len() < png.to_json_string().len());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor_invalid_document() {
+        let encode = |value: CborValue| {
+            let mut out: Vec<u8> = vec!();
+            ciborium::into_writer(&value, &mut out).unwrap();
+            out
+        };
+        let pair = |t: &str, d: &[u8]| CborValue::Array(vec![CborValue::Text(t.to_string()), CborValue::Bytes(d.to_vec())]);
+        assert!(Png::from_cbor(&encode(CborValue::Integer(3.into()))).is_err());
+        assert!(Png::from_cbor(&encode(CborValue::Array(vec![pair("Ru1t", b"")]))).is_err());
+        assert!(Png::from_cbor(&encode(CborValue::Array(vec![CborValue::Array(vec![CborValue::Text(String::from("RuSt"))])]))).is_err());
+        assert!(Png::from_cbor(&encode(CborValue::Array(vec![pair("RuSt", b"data")]))).is_ok());
+
+        // Truncated and trailing bytes
+        let valid = encode(CborValue::Array(vec![pair("RuSt", b"data")]));
+        assert!(Png::from_cbor(&valid[..valid.len() - 1]).is_err());
+        assert!(Png::from_cbor(&[valid.as_slice(), &[0]].concat()).is_err());
+
+        // Deep enough to overflow the stack without the recursion limit
+        let mut nested = vec![0x81; 1_000_000];
+        nested.push(0x00);
+        assert!(matches!(Png::from_cbor(&nested), Err(PngMeError::InvalidCbor(_))));
+    }
+
+    // A 1 by 1 grayscale image with a single black pixel
+    const MINIMAL_PNG_HEX: &str = "89504e470d0a1a0a0000000d49484452000000010000000108000000003a7e9b55\
+        0000000a4944415478da6360000000020001e527defc0000000049454e44ae426082";
+
+    #[test]
+    fn test_hex_string_round_trip() {
+        let png = Png::from_hex_string(MINIMAL_PNG_HEX).unwrap();
+        assert_eq!(png.pixel_dimensions().unwrap(), (1, 1));
+        assert!(png.validate().is_ok());
+        assert_eq!(png.to_hex_string(), MINIMAL_PNG_HEX.replace(char::is_whitespace, ""));
+
+        // Uppercase and line breaks are accepted too
+        let split = "89504E470D0A1A0A\n".to_string() + &MINIMAL_PNG_HEX[16..];
+        assert_eq!(Png::from_hex_string(&split).unwrap(), png);
+    }
+
+    #[test]
+    fn test_from_invalid_hex_string() {
+        assert!(matches!(Png::from_hex_string("89504g"), Err(PngMeError::InvalidHex(_))));
+        assert!(matches!(Png::from_hex_string("89504"), Err(PngMeError::InvalidHex(_))));
+    }
+
+    #[test]
+    fn test_from_truncated_hex_string() {
+        assert!(matches!(Png::from_hex_string("89504e470d0a1a0a0000"), Err(PngMeError::InvalidLength { .. })));
+        let truncated = &MINIMAL_PNG_HEX[..MINIMAL_PNG_HEX.len() - 8];
+        assert!(Png::from_hex_string(truncated).is_err());
+    }
+
+    #[test]
+    fn test_data_uri_round_trip() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let uri = png.to_base64_data_uri();
+        assert!(uri.starts_with("data:image/png;base64,iVBORw0K"));
+
+        let parsed = Png::from_base64_data_uri(&uri).unwrap();
+        assert_eq!(parsed.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_data_uri_invalid() {
+        assert!(matches!(Png::from_base64_data_uri("data:image/jpeg;base64,/9j/"), Err(PngMeError::InvalidInput(_))));
+        assert!(matches!(Png::from_base64_data_uri("data:image/png,raw"), Err(PngMeError::InvalidInput(_))));
+        assert!(matches!(Png::from_base64_data_uri("data:image/png;base64,***="), Err(PngMeError::InvalidBase64(_))));
+    }
+
+    #[test]
+    fn test_annotations() {
+        let mut png = testing_png();
+        png.annotate_chunk("version", "1.2").unwrap();
+        png.annotate_chunk("author", "Jorge").unwrap();
+        png.annotate_chunk("source", "camera=front").unwrap();
+
+        assert_eq!(png.get_annotation("version").unwrap(), "1.2");
+        assert_eq!(png.get_annotation("author").unwrap(), "Jorge");
+        assert_eq!(png.get_annotation("source").unwrap(), "camera=front");
+        assert!(png.get_annotation("missing").is_none());
+
+        let all = png.all_annotations();
+        assert_eq!(all.len(), 3);
+        assert!(all.contains(&(String::from("version"), String::from("1.2"))));
+        assert!(all.contains(&(String::from("author"), String::from("Jorge"))));
+        assert!(all.contains(&(String::from("source"), String::from("camera=front"))));
+        // All of them share a single chunk
+        assert_eq!(png.chunks().len(), 4);
+    }
+
+    #[test]
+    fn test_annotation_latest_value_wins() {
+        let mut png = testing_png();
+        png.annotate_chunk("version", "1").unwrap();
+        png.annotate_chunk("version", "2").unwrap();
+        assert_eq!(png.get_annotation("version").unwrap(), "2");
+    }
+
+    #[test]
+    fn test_invalid_annotation() {
+        let mut png = testing_png();
+        assert!(png.annotate_chunk("a=b", "c").is_err());
+        assert!(png.annotate_chunk("", "c").is_err());
+        assert!(png.annotate_chunk("key", "two\nlines").is_err());
+        assert!(png.chunk_by_type(Png::ANNOTATION_CHUNK_TYPE).is_none());
+    }
+
+    fn framed_png() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "0123456789abc").unwrap(),
+            chunk_from_strings("IDAT", "pixels").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_structurally_complete() {
+        assert!(framed_png().is_structurally_complete());
+        assert!(Png::try_from(&PNG_FILE[..]).unwrap().is_structurally_complete());
+        assert!(!testing_png().is_structurally_complete());
+        assert!(!Png::from_chunks(vec!()).is_structurally_complete());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Png::try_from(&PNG_FILE[..]).unwrap().validate().is_ok());
+        assert!(Png::new_blank(2, 2, 3, 8).unwrap().validate().is_ok());
+        assert!(testing_png().validate().is_err());
+
+        // A palette in a grayscale image
+        let mut png = Png::new_blank(2, 2, 0, 8).unwrap();
+        png.add_after_ihdr(Chunk::new(ChunkType::PLTE, vec![0, 0, 0]).unwrap());
+        let error = png.validate().unwrap_err();
+        assert!(error.to_string().contains("PLTE chunk at index 1"));
+    }
+
+    #[test]
+    fn test_interlace_method() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.interlace_method().unwrap(), 0);
+        assert!(png.is_non_interlaced());
+        assert!(!png.is_progressive());
+
+        let mut ihdr = IhdrData::try_from(png.ihdr_or_err().unwrap()).unwrap();
+        ihdr.interlace_method = 1;
+        let mut chunks = png.chunks().to_vec();
+        chunks[0] = Chunk::new(ChunkType::IHDR, ihdr.to_bytes()).unwrap();
+        let interlaced = Png::from_chunks(chunks);
+        assert_eq!(interlaced.interlace_method().unwrap(), 1);
+        assert!(interlaced.is_progressive());
+        assert!(!interlaced.is_non_interlaced());
+
+        let png = testing_png();
+        assert!(matches!(png.interlace_method(), Err(PngMeError::ChunkNotFound(_))));
+        assert!(!png.is_progressive() && !png.is_non_interlaced());
+    }
+
+    #[test]
+    fn test_repair() {
+        let blank = Png::new_blank(2, 2, 3, 8).unwrap();
+        let [ihdr, plte, idat, iend] = [0, 1, 2, 3].map(|i| blank.chunks()[i].clone());
+        let chunks = vec![
+            chunk_from_strings("tEXt", "a\0b").unwrap(),
+            iend,
+            idat,
+            plte,
+            chunk_from_strings("gAMA", "gama").unwrap(),
+            ihdr,
+            chunk_from_strings("ruSt", "custom").unwrap(),
+        ];
+        let mut png = Png::from_chunks_unchecked(chunks);
+        assert!(png.validate().is_err());
+
+        png.repair().unwrap();
+        assert!(png.validate().is_ok());
+        assert_eq!(types_of(png.chunks()), vec!["IHDR", "gAMA", "PLTE", "tEXt", "ruSt", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_repair_adds_iend() {
+        let mut png = framed_png();
+        png.remove_first_chunk("IEND").unwrap();
+        png.repair().unwrap();
+        assert!(png.is_structurally_complete());
+
+        let mut png = testing_png();
+        assert!(matches!(png.repair(), Err(PngMeError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_has_valid_ihdr() {
+        assert!(framed_png().has_valid_ihdr());
+
+        let mut chunks = framed_png().chunks().to_vec();
+        chunks[0] = chunk_from_strings("IHDR", "too short").unwrap();
+        let png = Png::from_chunks(chunks);
+        assert!(!png.has_valid_ihdr());
+        assert!(png.has_valid_iend());
+        assert!(png.has_any_idat());
+
+        let mut chunks = framed_png().chunks().to_vec();
+        chunks.swap(0, 1);
+        assert!(!Png::from_chunks(chunks).has_valid_ihdr());
+    }
+
+    #[test]
+    fn test_has_valid_iend() {
+        assert!(framed_png().has_valid_iend());
+
+        let mut chunks = framed_png().chunks().to_vec();
+        chunks[2] = chunk_from_strings("IEND", "data").unwrap();
+        let png = Png::from_chunks(chunks);
+        assert!(!png.has_valid_iend());
+        assert!(png.has_valid_ihdr());
+        assert!(png.has_any_idat());
+
+        let mut chunks = framed_png().chunks().to_vec();
+        chunks.push(chunk_from_strings("TeSt", "after the end").unwrap());
+        assert!(!Png::from_chunks(chunks).has_valid_iend());
+    }
+
+    #[test]
+    fn test_has_any_idat() {
+        let mut chunks = framed_png().chunks().to_vec();
+        chunks.remove(1);
+        let png = Png::from_chunks(chunks);
+        assert!(!png.has_any_idat());
+        assert!(png.has_valid_ihdr());
+        assert!(png.has_valid_iend());
+        assert!(!png.is_structurally_complete());
+    }
+
+    #[test]
+    fn test_new_blank() {
+        let png = Png::new_blank(1, 1, 0, 8).unwrap();
+        let parsed = Png::try_from(png.as_bytes().as_ref()).unwrap();
+
+        assert!(parsed.is_structurally_complete());
+        assert_eq!(parsed.chunks().len(), 3);
+        let ihdr = IhdrData::try_from(&parsed.chunks()[0]).unwrap();
+        assert_eq!((ihdr.width, ihdr.height, ihdr.bit_depth, ihdr.color_type), (1, 1, 8, 0));
+        // A single scanline with the filter byte and one black pixel
+        assert_eq!(parsed.chunk_by_type("IDAT").unwrap().data(), &zlib::compress_stored(&[0, 0])[..]);
+    }
+
+    #[test]
+    fn test_new_blank_indexed_has_palette() {
+        let png = Png::new_blank(10, 3, 3, 2).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "PLTE", "IDAT", "IEND"]);
+        // 10 pixels of 2 bits need 3 bytes, plus the filter byte, for 3 scanlines
+        assert_eq!(png.chunk_by_type("IDAT").unwrap().data(), &zlib::compress_stored(&[0; 12])[..]);
+    }
+
+    #[test]
+    fn test_from_critical_and_ancillary() {
+        let source = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ancillary = vec![
+            chunk_from_strings("tEXt", "Author\0me").unwrap(),
+            chunk_from_strings("gAMA", "gama").unwrap(),
+            chunk_from_strings("ruSt", "custom").unwrap(),
+        ];
+        let png = Png::from_critical_and_ancillary(&source, ancillary.clone()).unwrap();
+
+        // The image file hides its message in RuSt, which is critical so it is kept
+        assert_eq!(types_of(png.chunks()), vec!["IHDR", "gAMA", "tEXt", "ruSt", "IDAT", "RuSt", "IEND"]);
+        assert_eq!(png.ihdr_or_err().unwrap(), source.ihdr_or_err().unwrap());
+        assert_eq!(png.first_idat().unwrap(), source.first_idat().unwrap());
+        assert_eq!(png.chunks().last(), source.chunks().last());
+        let (_, png_ancillary) = png.partition_chunks();
+        assert_eq!(png_ancillary.len(), 3);
+        for c in &ancillary {
+            assert!(png_ancillary.contains(&c));
+        }
+    }
+
+    #[test]
+    fn test_from_critical_and_ancillary_rejects_critical() {
+        let source = framed_png();
+        let ancillary = vec![chunk_from_strings("tEXt", "a\0b").unwrap(), chunk_from_strings("RuSt", "critical").unwrap()];
+        let png = Png::from_critical_and_ancillary(&source, ancillary);
+        assert!(matches!(png, Err(PngMeError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_new_blank_invalid() {
+        assert!(Png::new_blank(1, 1, 2, 4).is_err());
+        assert!(Png::new_blank(1, 1, 7, 8).is_err());
+        assert!(Png::new_blank(0, 1, 0, 8).is_err());
+        assert!(matches!(Png::new_blank(100_000, 100_000, 6, 8), Err(PngMeError::DataTooLarge(_))));
+        assert!(matches!(Png::new_blank(0x7fff_ffff, 0x7fff_ffff, 6, 16), Err(PngMeError::DataTooLarge(_))));
+    }
+
+    #[test]
+    fn test_encode_compressed() {
+        let mut png = testing_png();
+        let message = "This message repeats itself a lot. ".repeat(300).into_bytes();
+        assert!(message.len() > 10000);
+
+        png.encode_compressed(ChunkType::from_str("ruSt").unwrap(), &message, 9).unwrap();
+        let stored = png.chunk_by_type("ruSt").unwrap();
+        assert!(stored.data().len() < message.len() / 10);
+        assert_eq!(png.decode_compressed("ruSt").unwrap(), message);
+    }
+
+    // A blank indexed image whose palette has `entries` black entries instead of one
+    fn indexed_png(entries: usize) -> Png {
+        let mut png = Png::new_blank(2, 2, 3, 8).unwrap();
+        let plte = png.position_of(|c| *c.chunk_type() == ChunkType::PLTE).unwrap();
+        png.chunk_list[plte] = Chunk::new(ChunkType::PLTE, vec![0; entries * 3]).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_background_color_round_trip() {
+        let colors = [
+            (0, BackgroundColor::Grayscale(0x8000)),
+            (2, BackgroundColor::Rgb(255, 128, 0)),
+            (3, BackgroundColor::Indexed(4)),
+        ];
+        for (color_type, color) in colors {
+            let mut png = match color_type {
+                3 => indexed_png(5),
+                _ => Png::new_blank(2, 2, color_type, 8).unwrap(),
+            };
+            assert_eq!(png.background_color().unwrap(), None);
+
+            png.set_background_color(color).unwrap();
+            assert_eq!(png.background_color().unwrap(), Some(color));
+            assert_eq!(png.chunk_by_type("bKGD").unwrap().data(), color.to_bytes().as_slice());
+            let bkgd = png.position_of(|c| c.chunk_type().to_string() == "bKGD").unwrap();
+            assert_eq!(png.position_of(|c| *c.chunk_type() == ChunkType::IDAT), Some(bkgd + 1));
+            assert!(png.validate().is_ok());
+            assert!(png.find_chunks(|c| *c.chunk_type() == ChunkType::PLTE).len() <= 1);
+        }
+    }
+
+    #[test]
+    fn test_set_background_color_replaces() {
+        let mut png = Png::new_blank(2, 2, 6, 8).unwrap();
+        png.set_background_color(BackgroundColor::Rgb(1, 2, 3)).unwrap();
+        png.set_background_color(BackgroundColor::Rgb(4, 5, 6)).unwrap();
+        assert_eq!(png.find_chunks(|c| c.chunk_type().to_string() == "bKGD").len(), 1);
+        assert_eq!(png.background_color().unwrap(), Some(BackgroundColor::Rgb(4, 5, 6)));
+        assert_eq!(png.chunk_by_type("bKGD").unwrap().data(), &[0, 4, 0, 5, 0, 6]);
+
+        assert!(png.set_background_color(BackgroundColor::Indexed(0)).is_err());
+        assert!(testing_png().set_background_color(BackgroundColor::Grayscale(0)).is_err());
+    }
+
+    #[test]
+    fn test_transparency_round_trip() {
+        let cases = [
+            (0, Transparency::Grayscale(0)),
+            (2, Transparency::Rgb(255, 255, 255)),
+            (3, Transparency::Indexed(vec![0, 255, 128])),
+        ];
+        for (color_type, transparency) in cases {
+            let mut png = match color_type {
+                3 => indexed_png(4),
+                _ => Png::new_blank(2, 2, color_type, 8).unwrap(),
+            };
+            assert_eq!(png.transparency().unwrap(), None);
+
+            png.set_transparency(transparency.clone()).unwrap();
+            assert_eq!(png.transparency().unwrap(), Some(transparency.clone()));
+            let trns = png.position_of(|c| c.chunk_type().to_string() == "tRNS").unwrap();
+            assert_eq!(png.position_of(|c| *c.chunk_type() == ChunkType::IDAT), Some(trns + 1));
+            assert!(png.validate().is_ok());
+            assert!(png.find_chunks(|c| *c.chunk_type() == ChunkType::PLTE).len() <= 1);
+        }
+    }
+
+    #[test]
+    fn test_set_transparency_errors() {
+        let mut png = Png::new_blank(2, 2, 2, 8).unwrap();
+        assert!(png.set_transparency(Transparency::Grayscale(0)).is_err());
+        assert!(png.set_transparency(Transparency::Indexed(vec![0])).is_err());
+        assert!(png.chunk_by_type("tRNS").is_none());
+
+        // Color types with alpha can not have tRNS at all
+        let mut png = Png::new_blank(2, 2, 6, 8).unwrap();
+        assert!(png.set_transparency(Transparency::Rgb(0, 0, 0)).is_err());
+
+        // More alpha values than palette entries
+        let mut png = indexed_png(2);
+        assert!(png.set_transparency(Transparency::Indexed(vec![0; 3])).is_err());
+        assert!(png.set_transparency(Transparency::Indexed(vec![0; 2])).is_ok());
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut old = framed_png();
+        old.add_before_iend(chunk_from_strings("tEXt", "Comment\0one").unwrap());
+        old.add_before_iend(chunk_from_strings("tEXt", "Comment\0two").unwrap());
+        let mut new = framed_png();
+        new.replace_image_data(b"other pixels");
+        new.add_before_iend(chunk_from_strings("tEXt", "Comment\0one").unwrap());
+        new.add_before_iend(chunk_from_strings("ruSt", "new").unwrap());
+
+        assert_eq!(old.diff(&new), vec![
+            ChunkDiff::Modified{old: old.chunks()[1].clone(), new: new.chunks()[1].clone()},
+            ChunkDiff::Removed(old.chunks()[3].clone()),
+            ChunkDiff::Added(new.chunks()[3].clone()),
+        ]);
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn test_compare_metadata() {
+        let mut old = framed_png();
+        old.add_before_iend(chunk_from_strings("tEXt", "Comment\0before").unwrap());
+        let mut new = framed_png();
+        new.replace_image_data(b"other pixels");
+        new.add_before_iend(chunk_from_strings("tEXt", "Comment\0after").unwrap());
+
+        let diffs = old.compare_metadata(&new);
+        assert_eq!(diffs.len(), 1);
+        assert!(matches!(&diffs[0], ChunkDiff::Modified{old, new}
+            if old.data() == b"Comment\0before" && new.data() == b"Comment\0after"));
+        assert_eq!(old.diff(&new).len(), 2);
+        assert!(!old.ancillary_chunks_equal(&new));
+
+        // Only the image data differs
+        new.remove_comment_chunks();
+        new.add_before_iend(chunk_from_strings("tEXt", "Comment\0before").unwrap());
+        assert!(old.compare_metadata(&new).is_empty());
+        assert!(old.ancillary_chunks_equal(&new));
+        assert_ne!(old, new);
+    }
+
+    #[test]
+    fn test_from_file_if_exists() {
+        let dir = std::env::temp_dir().join(format!("pngme-{}-if-exists", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("image.png");
+
+        assert!(Png::from_file_if_exists(&path).unwrap().is_none());
+        framed_png().save_to_file(&path).unwrap();
+        assert_eq!(Png::from_file_if_exists(&path).unwrap(), Some(framed_png()));
+        assert!(Png::from_file_if_exists(&dir).is_err());
+
+        fs::write(&path, b"not a png").unwrap();
+        assert!(Png::from_file_if_exists(&path).is_err());
+        let bytes = framed_png().as_bytes();
+        fs::write(&path, &bytes[..bytes.len() - 5]).unwrap();
+        assert!(Png::from_file_if_exists(&path).is_err());
+        let mut bad_crc = bytes.clone();
+        bad_crc[8 + 8] ^= 1;
+        fs::write(&path, &bad_crc).unwrap();
+        assert!(matches!(Png::from_file_if_exists(&path), Err(PngMeError::Context { .. })));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_chunks_report() {
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0hi").unwrap());
+        png.add_before_iend(Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![0xFF, 0xFE]).unwrap());
+        let mut out: Vec<u8> = vec!();
+        png.write_chunks_report(&mut out).unwrap();
+        let report = String::from_utf8(out).unwrap();
+
+        let mut reader = csv::Reader::from_reader(report.as_bytes());
+        let header: Vec<String> = reader.headers().unwrap().iter().map(String::from).collect();
+        assert_eq!(header, vec!["index", "chunk_type", "length", "crc_hex", "critical", "public", "safe_to_copy", "is_standard", "data_is_utf8"]);
+        let records: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        let rows: Vec<Vec<&str>> = records.iter().map(|r| r.iter().collect()).collect();
+        assert_eq!(rows.len(), 5);
+
+        let crc = |i: usize| format!("{:08x}", png.chunks()[i].crc());
+        assert_eq!(rows[0], vec!["0", "IHDR", "13", crc(0).as_str(), "true", "true", "false", "true", "true"]);
+        assert_eq!(rows[1], vec!["1", "IDAT", "6", crc(1).as_str(), "true", "true", "false", "true", "true"]);
+        assert_eq!(rows[2], vec!["2", "tEXt", "10", crc(2).as_str(), "false", "true", "true", "true", "true"]);
+        assert_eq!(rows[3], vec!["3", "ruSt", "2", crc(3).as_str(), "false", "false", "true", "false", "false"]);
+        assert_eq!(rows[4], vec!["4", "IEND", "0", "ae426082", "true", "true", "false", "true", "true"]);
+
+        // Chunk types are letters only, so check the quoting on a row of its own
+        let fields = ["plain", "a,b", "say \"hi\"", "two\nlines"].map(String::from);
+        let mut out: Vec<u8> = vec!();
+        Png::write_csv_row(&mut out, &fields).unwrap();
+        assert_eq!(String::from_utf8(out.clone()).unwrap(), "plain,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\"\n");
+        let mut reader = csv::ReaderBuilder::new().has_headers(false).from_reader(out.as_slice());
+        let record = reader.records().next().unwrap().unwrap();
+        assert_eq!(record.iter().collect::<Vec<&str>>(), fields.iter().map(|f| f.as_str()).collect::<Vec<&str>>());
+    }
+
+    #[test]
+    fn test_pad_to_size() {
+        let mut png = framed_png();
+        assert_eq!(png.file_size(), png.as_bytes().len() as u64);
+        let size = png.file_size();
+
+        png.pad_to_size(size + 100).unwrap();
+        assert_eq!(png.file_size(), size + 100);
+        assert_eq!(png.as_bytes().len() as u64, size + 100);
+        let padding = png.chunk_by_type("pADD").unwrap();
+        assert_eq!(padding.data(), vec![0; 88].as_slice());
+        assert_eq!(png.chunks().last().unwrap().chunk_type(), &ChunkType::IEND);
+
+        // Already at the target, nothing to do
+        png.pad_to_size(size + 100).unwrap();
+        assert_eq!(png.chunks().len(), 4);
+
+        let mut png = framed_png();
+        png.pad_to_size(size + 12).unwrap();
+        assert_eq!(png.file_size(), size + 12);
+    }
+
+    #[test]
+    fn test_pad_to_size_errors() {
+        let mut png = framed_png();
+        let size = png.file_size();
+        assert!(matches!(png.pad_to_size(size - 1), Err(PngMeError::AlreadyTooLarge{target, ..}) if target == size - 1));
+        assert!(matches!(png.pad_to_size(size + 11), Err(PngMeError::InvalidInput(_))));
+        assert!(matches!(png.pad_to_size(u64::MAX), Err(PngMeError::DataTooLarge(_))));
+        assert_eq!(png, framed_png());
+    }
+
+    #[test]
+    fn test_chunk_statistics() {
+        // Data lengths 13, 6, 2, 4, 4 and 0 for IEND
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("ruSt", "ab").unwrap());
+        png.add_before_iend(chunk_from_strings("ruSt", "abcd").unwrap());
+        png.add_before_iend(chunk_from_strings("ruSt", "efgh").unwrap());
+
+        let stats = png.chunk_statistics();
+        assert_eq!(stats.count, 6);
+        assert_eq!(stats.total_bytes, 29);
+        assert_eq!(stats.min_length, 0);
+        assert_eq!(stats.max_length, 13);
+        assert!((stats.mean_length - 29.0 / 6.0).abs() < 1e-9);
+        // Variance of 241 / 6 - (29 / 6)^2
+        assert!((stats.std_dev - (605.0f64 / 36.0).sqrt()).abs() < 1e-9);
+
+        let rust = png.chunk_statistics_for_type("ruSt").unwrap();
+        assert_eq!((rust.count, rust.total_bytes, rust.min_length, rust.max_length), (3, 10, 2, 4));
+        assert!((rust.mean_length - 10.0 / 3.0).abs() < 1e-9);
+        assert!((rust.std_dev - (8.0f64 / 9.0).sqrt()).abs() < 1e-9);
+        assert_eq!(rust.to_string(), "3 chunks, 10 bytes, min 2, max 4, mean 3.33, std dev 0.94");
+
+        assert!(png.chunk_statistics_for_type("tEXt").is_none());
+        assert_eq!(Png::from_chunks(vec![]).chunk_statistics().count, 0);
+    }
+
+    #[test]
+    fn test_chunks_sorted_by_size() {
+        // Data lengths 13, 500, 400, 10, 4 and 0
+        let mut png = framed_png();
+        png.chunk_list.retain(|c| *c.chunk_type() != ChunkType::IDAT);
+        png.insert_idat(vec![1; 500]).unwrap();
+        png.insert_idat(vec![2; 400]).unwrap();
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0hi").unwrap());
+        png.add_before_iend(chunk_from_strings("ruSt", "tiny").unwrap());
+
+        let top: Vec<String> = png.top_n_chunks_by_size(2).iter().map(|(_, c)| c.chunk_type().to_string()).collect();
+        assert_eq!(top, vec!["IDAT", "IDAT"]);
+        assert_eq!(png.top_n_chunks_by_size(1)[0].0, 1);
+
+        let desc: Vec<usize> = png.chunks_sorted_by_size_desc().iter().map(|(i, _)| *i).collect();
+        let asc: Vec<usize> = png.chunks_sorted_by_size_asc().iter().map(|(i, _)| *i).collect();
+        assert_eq!(desc.len(), 6);
+        assert_eq!(&desc[2..], &[0, 3, 4, 5]);
+        assert_eq!(&asc[..4], &[5, 4, 3, 0]);
+        let lengths: Vec<u32> = png.chunks_sorted_by_size_asc().iter().map(|(_, c)| c.length()).collect();
+        assert!(lengths.windows(2).all(|w| w[0] <= w[1]));
+
+        assert_eq!(png.top_n_chunks_by_size(100).len(), 6);
+        assert!(png.top_n_chunks_by_size(0).is_empty());
+    }
+
+    #[test]
+    fn test_from_bytes_lenient() {
+        let corrupted = [2, 5, 7];
+        let mut png = framed_png();
+        for i in 0..7 {
+            png.add_before_iend(chunk_from_strings("ruSt", &format!("chunk {}", i)).unwrap());
+        }
+        assert_eq!(png.chunks().len(), 10);
+
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        for (i, c) in png.chunks().iter().enumerate() {
+            match corrupted.contains(&i) {
+                true => bytes.extend(Chunk::new_with_crc_override(c.chunk_type().clone(), c.data().to_vec(), 0).as_bytes()),
+                false => bytes.extend(c.as_bytes()),
+            }
+        }
+
+        let (parsed, warnings) = Png::from_bytes_lenient(&bytes);
+        assert_eq!(parsed, png);
+        assert_eq!(warnings.len(), 3);
+        for ((index, warning), expected_index) in warnings.iter().zip(corrupted) {
+            assert_eq!(*index, expected_index);
+            assert!(matches!(warning, PngMeError::CrcMismatch{index, expected: 0, ..} if *index == expected_index));
+        }
+
+        let (parsed, warnings) = Png::from_bytes_lenient(&png.as_bytes());
+        assert_eq!(parsed, png);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_from_bytes_lenient_truncated() {
+        let bytes = framed_png().as_bytes();
+        // Cut in the middle of IDAT
+        let (parsed, warnings) = Png::from_bytes_lenient(&bytes[..8 + 25 + 10]);
+        assert_eq!(parsed.chunks().len(), 1);
+        assert!(matches!(warnings.as_slice(), [(1, PngMeError::InvalidLength{..})]));
+
+        let mut damaged_header = bytes.clone();
+        damaged_header[1] = b'p';
+        let (parsed, warnings) = Png::from_bytes_lenient(&damaged_header);
+        assert_eq!(parsed, framed_png());
+        assert!(matches!(warnings.as_slice(), [(0, PngMeError::InvalidInput(_))]));
+
+        let (parsed, warnings) = Png::from_bytes_lenient(&[]);
+        assert!(parsed.chunks().is_empty());
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_count_bytes_by_category() {
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0hi").unwrap());
+        png.add_before_iend(chunk_from_strings("gAMA", "0000").unwrap());
+        png.set_modification_time(PngTime::new(2024, 1, 2, 3, 4, 5).unwrap());
+        png.add_before_iend(chunk_from_strings("ruSt", "custom").unwrap());
+
+        let breakdown = png.count_bytes_by_category();
+        assert_eq!(breakdown, ByteBreakdown {
+            critical_bytes: 13 + 6,
+            text_bytes: 10,
+            color_management_bytes: 4,
+            time_bytes: 7,
+            custom_bytes: 6,
+            overhead_bytes: 8 + 12 * 7,
+        });
+        assert_eq!(breakdown.total(), png.file_size());
+        assert_eq!(breakdown.total(), png.as_bytes().len() as u64);
+    }
+
+    #[test]
+    fn test_text_convenience_setters() {
+        let mut png = framed_png();
+        assert_eq!(png.get_description(), None);
+
+        png.set_description("A small test image").unwrap();
+        png.set_author("Jörg").unwrap();
+        png.set_copyright("© nobody").unwrap();
+        png.set_software("pngme").unwrap();
+        png.set_comment("no comment").unwrap();
+
+        assert_eq!(png.get_description().unwrap(), "A small test image");
+        assert_eq!(png.get_author().unwrap(), "Jörg");
+        assert_eq!(png.get_copyright().unwrap(), "© nobody");
+        assert_eq!(png.get_software().unwrap(), "pngme");
+        assert_eq!(png.get_comment().unwrap(), "no comment");
+        assert_eq!(png.find_chunks(|c| c.chunk_type().to_string() == "tEXt").len(), 5);
+        assert_eq!(png.chunks().last().unwrap().chunk_type(), &ChunkType::IEND);
+        // Latin-1, one byte per character
+        assert_eq!(png.find_chunk(|c| c.data().starts_with(b"Author")).unwrap().data(), b"Author\0J\xF6rg");
+    }
+
+    #[test]
+    fn test_set_text_chunk_replaces() {
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0first").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "Author\0someone").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0second").unwrap());
+
+        png.set_comment("replaced").unwrap();
+        let texts: Vec<&[u8]> = png.find_chunks(|c| c.chunk_type().to_string() == "tEXt").iter().map(|c| c.data()).collect();
+        assert_eq!(texts, vec![&b"Comment\0replaced"[..], &b"Author\0someone"[..]]);
+        assert_eq!(png.chunks()[2].data(), b"Comment\0replaced");
+
+        assert!(png.set_text_chunk("", "text").is_err());
+        assert!(png.set_text_chunk(&"k".repeat(80), "text").is_err());
+        assert!(png.set_text_chunk("Comment", "null \0 inside").is_err());
+        assert!(png.set_text_chunk("Comment", "not latin-1 ✓").is_err());
+        assert_eq!(png.get_comment().unwrap(), "replaced");
+    }
+
+    #[test]
+    fn test_collect_into_png() {
+        let blank = Png::new_blank(2, 2, 2, 8).unwrap();
+        let png = blank.chunks().iter().cloned().collect::<Result<Png, _>>().unwrap();
+        assert_eq!(png, blank);
+
+        let without_ihdr = blank.chunks().iter().skip(1).cloned().collect::<Result<Png, PngMeError>>();
+        assert!(matches!(without_ihdr, Err(PngMeError::InvalidChunkData(_))));
+        assert!(Vec::<Chunk>::new().into_iter().collect::<Result<Png, _>>().is_err());
+    }
+
+    #[test]
+    fn test_extend_png() {
+        let mut png = framed_png();
+        png.extend(vec![
+            chunk_from_strings("tEXt", "Comment\0hi").unwrap(),
+            chunk_from_strings("ruSt", "custom").unwrap(),
+        ]);
+        png.extend(std::iter::once(chunk_from_strings("ruSt", "more").unwrap()));
+
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "IDAT", "tEXt", "ruSt", "ruSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_with_chunk() {
+        let path = std::env::temp_dir().join(format!("pngme-{}-with-chunk.png", std::process::id()));
+        let png = framed_png();
+        // `png` is moved into the chain and can not be used afterwards
+        png.with_chunk(chunk_from_strings("tEXt", "Author\0Jane Doe").unwrap()).unwrap()
+            .with_chunk(chunk_from_strings("gAMA", "gama").unwrap()).unwrap()
+            .with_chunk(chunk_from_strings("ruSt", "secret").unwrap()).unwrap()
+            .save_to_file(&path).unwrap();
+
+        let saved = Png::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let types: Vec<String> = saved.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "IDAT", "tEXt", "gAMA", "ruSt", "IEND"]);
+
+        assert!(Png::from_chunks(saved.chunks().to_vec()).with_chunk(chunk_from_strings("gAMA", "more").unwrap()).is_err());
+        assert!(framed_png().with_chunk(Chunk::new_iend()).is_err());
+    }
+
+    #[test]
+    fn test_copy_chunk_from() {
+        let mut source = framed_png();
+        source.add_before_iend(chunk_from_strings("tEXt", "Author\0Jane Doe").unwrap());
+        source.add_before_iend(chunk_from_strings("tEXt", "Title\0Night").unwrap());
+        let source_bytes = source.as_bytes();
+
+        let mut target = framed_png();
+        target.copy_chunk_from(&source, "tEXt").unwrap();
+        assert_eq!(source.as_bytes(), source_bytes);
+        assert_eq!(target.chunk_by_type("tEXt"), source.chunk_by_type("tEXt"));
+        assert_eq!(target.chunks().last().unwrap().chunk_type(), &ChunkType::IEND);
+
+        let mut target = framed_png();
+        assert_eq!(target.copy_all_chunks_of_type_from(&source, "tEXt").unwrap(), 2);
+        assert_eq!(source.as_bytes(), source_bytes);
+        assert_eq!(target.find_chunks(|c| c.chunk_type().to_string() == "tEXt"), source.find_chunks(|c| c.chunk_type().to_string() == "tEXt"));
+
+        assert!(matches!(target.copy_chunk_from(&source, "gAMA"), Err(PngMeError::ChunkNotFound(_))));
+        assert!(matches!(target.copy_all_chunks_of_type_from(&source, "gAMA"), Err(PngMeError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_byte_range_of_chunk() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let bytes = png.as_bytes();
+        for (i, c) in png.chunks().iter().enumerate() {
+            let range = png.byte_range_of_chunk(i).unwrap();
+            assert_eq!(&bytes[range], c.as_bytes().as_slice());
+        }
+        assert_eq!(png.byte_range_of_chunk(0).unwrap().start, 8);
+        assert_eq!(png.byte_range_of_chunk(png.chunks().len() - 1).unwrap().end, bytes.len());
+        assert_eq!(png.byte_range_of_chunk(png.chunks().len()), None);
+    }
+
+    #[test]
+    fn test_get_or_insert_chunk() {
+        let mut png = framed_png();
+        png.add_after_ihdr(chunk_from_strings("gAMA", "gama").unwrap());
+        let existing = png.get_or_insert_chunk(chunk_from_strings("gAMA", "default").unwrap());
+        assert_eq!(existing.data(), b"gama");
+        assert_eq!(png.chunks().len(), 4);
+
+        let inserted = png.get_or_insert_chunk(chunk_from_strings("tEXt", "Author\0default").unwrap());
+        assert_eq!(inserted.data(), b"Author\0default");
+        *inserted = chunk_from_strings("tEXt", "Author\0Jane Doe").unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "gAMA", "IDAT", "tEXt", "IEND"]);
+        let range = png.byte_range_of_chunk(3).unwrap();
+        assert_eq!(&png.as_bytes()[range], chunk_from_strings("tEXt", "Author\0Jane Doe").unwrap().as_bytes().as_slice());
+
+        // The reference may be used to change the chunk, so even getting one counts as a change
+        let mut png = framed_png();
+        assert!(!png.is_dirty());
+        png.get_or_insert_chunk(Chunk::new(ChunkType::IDAT, b"unused".to_vec()).unwrap());
+        assert!(png.is_dirty());
+        assert_eq!(png.version(), 1);
+        assert_eq!(png, framed_png());
+        png.get_or_insert_chunk(chunk_from_strings("tEXt", "Author\0default").unwrap());
+        assert_eq!(png.version(), 2);
+    }
+
+    #[test]
+    fn test_diff_patch_adding_a_chunk() {
+        let mut png = framed_png();
+        let mut other = framed_png();
+        other.add_before_iend(chunk_from_strings("tEXt", "Author\0Jane Doe").unwrap());
+
+        let patch = png.write_diff_patch(&other).unwrap();
+        png.apply_patch(&patch).unwrap();
+        assert_eq!(png, other);
+
+        // The patch was made for the PNG before the chunk was added
+        assert!(matches!(png.apply_patch(&patch), Err(PngMeError::InvalidInput(_))));
+        assert_eq!(png, other);
+    }
+
+    #[test]
+    fn test_diff_patch_of_many_changed_chunks() {
+        let chunks = |prefix: &str| -> Vec<Chunk> {
+            (0..1100).map(|i| chunk_from_strings("tEXt", &format!("{}\0{}", prefix, i)).unwrap()).collect()
+        };
+        let mut png = framed_png();
+        png.append_chunks(chunks("Old"));
+        let mut other = framed_png();
+        other.append_chunks(chunks("New"));
+
+        // Past `MAX_DIFF_TABLE_CELLS` every changed chunk is replaced by a single splice
+        let patch = png.write_diff_patch(&other).unwrap();
+        assert_eq!(&patch[12..16], &1u32.to_be_bytes());
+        assert_eq!(&patch[16..20], &2u32.to_be_bytes());
+        png.apply_patch(&patch).unwrap();
+        assert_eq!(png, other);
+    }
+
+    #[test]
+    fn test_diff_patch_leaves_out_common_chunks() {
+        let image_data = vec![0xAB; 10_000];
+        let mut png = framed_png();
+        png.insert_idat(image_data.clone()).unwrap();
+        png.add_after_ihdr(chunk_from_strings("gAMA", "old gama").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0removed").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "Title\0kept").unwrap());
+
+        let mut other = Png::from_chunks(png.chunks().to_vec());
+        other.chunk_list_mut()[1] = chunk_from_strings("gAMA", "new gama").unwrap();
+        other.remove_first_chunk("tEXt").unwrap();
+        other.add_before_iend(chunk_from_strings("ruSt", "added").unwrap());
+
+        let patch = png.write_diff_patch(&other).unwrap();
+        assert!(patch.len() < 200);
+        png.apply_patch(&patch).unwrap();
+        assert_eq!(png, other);
+
+        let unchanged = framed_png().write_diff_patch(&framed_png()).unwrap();
+        let mut png = framed_png();
+        png.apply_patch(&unchanged).unwrap();
+        assert_eq!(png, framed_png());
+    }
+
+    #[test]
+    fn test_apply_invalid_patch() {
+        let mut other = framed_png();
+        other.add_before_iend(chunk_from_strings("tEXt", "Author\0Jane Doe").unwrap());
+        let patch = framed_png().write_diff_patch(&other).unwrap();
+
+        let mut png = framed_png();
+        assert!(png.apply_patch(b"nope").is_err());
+        assert!(png.apply_patch(&patch[..patch.len() - 1]).is_err());
+        let mut trailing = patch.clone();
+        trailing.push(0);
+        assert!(png.apply_patch(&trailing).is_err());
+        let mut bad_crc = patch.clone();
+        *bad_crc.last_mut().unwrap() ^= 1;
+        assert!(png.apply_patch(&bad_crc).is_err());
+        assert_eq!(png, framed_png());
+    }
+
+    #[test]
+    fn test_touch() {
+        let path = std::env::temp_dir().join(format!("pngme-{}-touch.png", std::process::id()));
+        let mut png = framed_png();
+        png.touch().unwrap();
+        png.save_to_file(&path).unwrap();
+
+        let time = Png::from_file(&path).unwrap().modification_time().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(time.unwrap().year >= 2024);
+    }
+
+    #[test]
+    fn test_optimize() {
+        let mut png = framed_png();
+        png.insert_idat(b"more pixels".to_vec()).unwrap();
+        png.insert_idat(b"last pixels".to_vec()).unwrap();
+        png.add_after_ihdr(chunk_from_strings("gAMA", "gama").unwrap());
+        png.add_after_ihdr(chunk_from_strings("gAMA", "amag").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", &format!("Comment\0{}", "again and again ".repeat(20))).unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "Author\0Jane").unwrap());
+        png.add_before_iend(Chunk::new_watermark("pngme", "0.1.0"));
+        png.pad_to_size(png.file_size() + 100).unwrap();
+        let image_data = png.image_data();
+        let original_size = png.file_size();
+
+        let report = png.optimize().unwrap();
+        assert_eq!(report, OptimizationReport {
+            original_size,
+            final_size: png.file_size(),
+            // A gAMA, the watermark, the padding and two IDAT
+            chunks_removed: 5,
+            chunks_compressed: 1,
+        });
+        assert!(report.final_size < report.original_size);
+
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "gAMA", "IDAT", "zTXt", "tEXt", "IEND"]);
+        assert_eq!(png.image_data(), image_data);
+        assert_eq!(png.get_text_chunk("Comment").unwrap(), "again and again ".repeat(20));
+
+        // Nothing left to do the second time
+        let size = png.file_size();
+        let report = png.optimize().unwrap();
+        assert_eq!((report.original_size, report.final_size, report.chunks_removed, report.chunks_compressed), (size, size, 0, 0));
+    }
+
+    #[test]
+    fn test_save_to_file_leaves_no_temp_file() {
+        let dir = std::env::temp_dir().join(format!("pngme-{}-atomic", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("image.png");
+        let entries = || fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().to_string()).collect::<Vec<String>>();
+
+        fs::write(&path, b"old content").unwrap();
+        framed_png().save_to_file(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), framed_png().as_bytes());
+        assert_eq!(entries(), vec!["image.png"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_to_file_failure_removes_temp_file() {
+        let dir = std::env::temp_dir().join(format!("pngme-{}-atomic-failure", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let entries = || {
+            let mut names = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().to_string()).collect::<Vec<String>>();
+            names.sort();
+            names
+        };
+
+        // The temporary file is written, but renaming it over a directory fails
+        let blocked = dir.join("blocked.png");
+        fs::create_dir(&blocked).unwrap();
+        fs::write(blocked.join("inside"), b"kept").unwrap();
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("ruSt", "unsaved").unwrap());
+        assert!(matches!(png.save_to_file(&blocked), Err(PngMeError::Io(_))));
+        assert!(png.is_dirty());
+        assert_eq!(entries(), vec!["blocked.png"]);
+        assert_eq!(fs::read(blocked.join("inside")).unwrap(), b"kept");
+
+        // The temporary file cannot even be created
+        assert!(framed_png().save_to_file(&dir.join("missing").join("image.png")).is_err());
+        assert_eq!(entries(), vec!["blocked.png"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_dirty() {
+        let path = std::env::temp_dir().join(format!("pngme-{}-dirty.png", std::process::id()));
+        let mut png = framed_png();
+        assert!(!png.is_dirty());
+
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0hi").unwrap());
+        assert!(png.is_dirty());
+        assert_eq!(png.version(), 1);
+
+        png.save_to_file(&path).unwrap();
+        assert!(!png.is_dirty());
+        assert!(!png.is_dirty());
+        assert_eq!(Png::try_from(fs::read(&path).unwrap().as_ref()).unwrap(), png);
+
+        png.remove_chunks_where(|c| c.chunk_type().to_string() == "tEXt");
+        assert!(png.is_dirty());
+        png.save_to_file(&path).unwrap();
+        assert!(!png.is_dirty());
+        fs::remove_file(&path).unwrap();
+
+        // Equality ignores the version
+        assert_eq!(png, framed_png());
+    }
+
+    #[test]
+    fn test_version_counts_actual_changes() {
+        let mut png = framed_png();
+        assert_eq!(png.remove_comment_chunks(), 0);
+        assert_eq!(png.find_and_replace_in_text_chunks("a", "b"), 0);
+        assert!(png.remove_chunks_where(|c| c.chunk_type().to_string() == "tEXt").is_empty());
+        assert!(png.deduplicate_chunks().is_empty());
+        png.to_single_idat().unwrap();
+        png.repair().unwrap();
+        png.remove_color_management_chunks();
+        png.append_chunks(vec!());
+        assert!(!png.is_dirty());
+        assert_eq!(png.version(), 0);
+
+        // One change per operation, whatever it does internally
+        png.insert_idat(b"more pixels".to_vec()).unwrap();
+        png.to_single_idat().unwrap();
+        assert_eq!(png.version(), 2);
+        png.add_before_iend(chunk_from_strings("gAMA", "gama").unwrap());
+        png.add_before_iend(chunk_from_strings("gAMA", "gama").unwrap());
+        png.deduplicate_chunks();
+        assert_eq!(png.version(), 5);
+        png.add_srgb_chunk(0).unwrap();
+        png.add_srgb_chunk(1).unwrap();
+        assert_eq!(png.version(), 7);
+        assert!(png.is_dirty());
+    }
+
+    #[test]
+    fn test_apply_transform() {
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0compress me").unwrap());
+        png.add_before_iend(chunk_from_strings("ruSt", "drop me").unwrap());
+
+        let transformed = png.apply_transform(|c| {
+            return match c.chunk_type().to_string().as_str() {
+                "tEXt" => {
+                    let (keyword, text) = c.data().split_at(c.data().iter().position(|b| *b == 0).unwrap());
+                    let mut data = keyword.to_vec();
+                    data.extend([0, 0]);
+                    data.extend(zlib::compress(&text[1..], 9)?);
+                    Ok(Some(Chunk::new(ChunkType::from_str("zTXt").unwrap(), data)?))
+                }
+                "ruSt" => Ok(None),
+                _ => Ok(Some(c)),
+            }
+        }).unwrap();
+
+        let types: Vec<String> = transformed.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "IDAT", "zTXt", "IEND"]);
+        assert_eq!(transformed.find_text("compress me").len(), 1);
+        assert_eq!(png.chunks().len(), 5);
+
+        let failed = png.apply_transform(|c| match c.chunk_type().to_string().as_str() {
+            "ruSt" => Err(PngMeError::InvalidChunkData(String::from("no"))),
+            _ => Ok(Some(c)),
+        });
+        assert!(matches!(failed, Err(PngMeError::InvalidChunkData(_))));
+        assert_eq!(png.apply_transform(|c| Ok(Some(c))).unwrap(), png);
+    }
+
+    #[test]
+    fn test_remove_chunks_where() {
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("tEXt", &format!("Comment\0{}", "long ".repeat(300))).unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0short").unwrap());
+        png.add_before_iend(chunk_from_strings("prIv", "private").unwrap());
+        png.add_before_iend(chunk_from_strings("prOt", "also private").unwrap());
 
-    /// Creates a `Png` from a list of chunks using the correct header
-    pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
-        return Png{chunk_list: chunks}
+        let removed = png.remove_chunks_where(|c| c.length() > 1000);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].chunk_type().to_string(), "tEXt");
+
+        let removed = png.remove_chunks_where(|c| c.chunk_type().to_string().starts_with("pr"));
+        let removed_types: Vec<String> = removed.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(removed_types, vec!["prIv", "prOt"]);
+
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "IDAT", "tEXt", "IEND"]);
+        assert_eq!(png.chunks()[2].data(), b"Comment\0short");
+
+        // IHDR and IEND survive a predicate that matches everything
+        let removed = png.remove_chunks_where(|_| true);
+        assert_eq!(removed.len(), 2);
+        assert_eq!(png, Png::from_chunks(vec![framed_png().chunks()[0].clone(), Chunk::new_iend()]));
     }
-    /// Appends a chunk to the end of this `Png` file's `Chunk` list.
-    pub fn append_chunk(&mut self, chunk: Chunk) {
-        self.chunk_list.push(chunk);
+
+    #[test]
+    fn test_insert_idat() {
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0after the image").unwrap());
+        png.insert_idat(b"more".to_vec()).unwrap();
+        png.insert_idat(b"last".to_vec()).unwrap();
+
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "IDAT", "IDAT", "IDAT", "tEXt", "IEND"]);
+        assert_eq!(png.image_data(), b"pixelsmorelast");
+        assert!(png.is_structurally_complete());
     }
-    /// Searches for a `Chunk` with the specified `chunk_type` and removes the first
-    /// matching `Chunk` from this `Png` list of chunks.
-    pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk, &str> {
-        for (i, c) in self.chunks().to_owned().iter().enumerate() {
-            if c.chunk_type().to_string() == chunk_type {
-                let v1 = &self.chunks()[..i];
-                let v2 = &self.chunks()[i+1..];
 
-                v1.to_vec().extend(v2.to_vec());
+    #[test]
+    fn test_insert_idat_without_idat() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "0123456789abc").unwrap(),
+            chunk_from_strings("tEXt", "Comment\0hi").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        png.insert_idat(b"pixels".to_vec()).unwrap();
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "IDAT");
 
-                self.chunk_list = v1.to_vec();
+        png.chunk_list.insert(1, chunk_from_strings("PLTE", "rgb").unwrap());
+        png.chunk_list.retain(|c| *c.chunk_type() != ChunkType::IDAT);
+        png.insert_idat(b"pixels".to_vec()).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "PLTE", "IDAT", "tEXt", "IEND"]);
 
-                return Ok(c.to_owned())
-            }
-        }
-        return Err("No chunks of that type where found")
+        let mut png = testing_png();
+        assert!(matches!(png.insert_idat(b"pixels".to_vec()), Err(PngMeError::ChunkNotFound(_))));
+        assert_eq!(png.chunks().len(), 3);
     }
-    /// The header of this PNG.
-    pub fn header(&self) -> &[u8; 8] {
-        return &Png::STANDARD_HEADER
+
+    #[test]
+    fn test_to_single_idat() {
+        let mut png = Png::new_blank(16, 16, 6, 8).unwrap();
+        let stream = png.image_data();
+        png.chunk_list.retain(|c| *c.chunk_type() != ChunkType::IDAT);
+        for i in 0..5 {
+            png.insert_idat(stream[stream.len() * i / 5..stream.len() * (i + 1) / 5].to_vec()).unwrap();
+        }
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0after the image").unwrap());
+        assert_eq!(png.find_chunks(|c| *c.chunk_type() == ChunkType::IDAT).len(), 5);
+
+        png.to_single_idat().unwrap();
+        let idats = png.find_chunks(|c| *c.chunk_type() == ChunkType::IDAT);
+        assert_eq!(idats.len(), 1);
+        assert_eq!(idats[0].data(), stream.as_slice());
+        assert_eq!(png.position_of(|c| *c.chunk_type() == ChunkType::IDAT), Some(1));
+        assert!(png.validate().is_ok());
+
+        assert!(matches!(testing_png().to_single_idat(), Err(PngMeError::ChunkNotFound(_))));
     }
-    /// Lists the `Chunk`s stored in this `Png`
-    pub fn chunks(&self) -> &[Chunk] {
-        return &self.chunk_list
+
+    #[test]
+    fn test_idat_reader() {
+        let mut png = framed_png();
+        png.add_after_ihdr(chunk_from_strings("IDAT", "").unwrap());
+        png.add_before_iend(chunk_from_strings("IDAT", "more pixels").unwrap());
+
+        let mut bytes: Vec<u8> = Vec::new();
+        png.idat_reader().read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, png.image_data());
+        assert_eq!(bytes, b"pixelsmore pixels");
+
+        // Reads never cross a chunk boundary
+        let mut reader = png.idat_reader();
+        let mut buffer = [0; 4];
+        assert_eq!(reader.read(&mut buffer).unwrap(), 4);
+        assert_eq!(reader.read(&mut buffer).unwrap(), 2);
+        assert_eq!(&buffer[..2], b"ls");
+        assert_eq!(reader.read(&mut buffer).unwrap(), 4);
+        assert_eq!(&buffer, b"more");
+
+        assert_eq!(testing_png().idat_reader().read(&mut buffer).unwrap(), 0);
     }
-    /// Searches for a `Chunk` with the specified `chunk_type` and returns the first
-    /// matching `Chunk` from this `Png`.
-    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        for c in self.chunks().iter() {
-            if c.chunk_type().to_string() == chunk_type {    
-                return Option::Some(c)
+
+    #[test]
+    fn test_idat_reader_scanlines() {
+        // 3 by 2 RGB, 8 bit: each scanline is a filter byte and 9 zero bytes
+        let mut png = Png::new_blank(3, 2, 2, 8).unwrap();
+        let stream = png.image_data();
+        // One IDAT per byte of the stream, with an empty one after the first
+        png.replace_image_data(&stream[..1]);
+        png.add_before_iend(Chunk::new(ChunkType::IDAT, vec!()).unwrap());
+        for byte in &stream[1..] {
+            png.add_before_iend(Chunk::new(ChunkType::IDAT, vec![*byte]).unwrap());
+        }
+        assert_eq!(png.find_chunks(|c| *c.chunk_type() == ChunkType::IDAT).len(), stream.len() + 1);
+
+        // The decoder pulls from the reader 2 bytes at a time as it goes, so reads end in the
+        // middle of the stream and cross chunk boundaries
+        let reader = std::io::BufReader::with_capacity(2, png.idat_reader());
+        let mut decoder = flate2::bufread::ZlibDecoder::new(reader);
+        let mut scanlines: Vec<u8> = Vec::new();
+        let mut buffer = [0; 3];
+        loop {
+            let read = decoder.read(&mut buffer).unwrap();
+            if read == 0 {
+                break
             }
+            scanlines.extend(&buffer[..read]);
         }
-        return Option::None
+        assert_eq!(scanlines, vec![0; 20]);
     }
-    /// Returns this `Png` as a byte sequence.
-    /// These bytes will contain the header followed by the bytes of all of the chunks.
-    pub fn as_bytes(&self) -> Vec<u8> {
-        let mut png_as_bytes: Vec<u8> = vec!();
-        png_as_bytes.extend(self.header());
 
-        for c in self.chunks() {
-            png_as_bytes.extend(c.as_bytes());
+    #[test]
+    fn test_pixel_dimensions_and_count() {
+        let png = Png::new_blank(3, 2, 2, 8).unwrap();
+        assert_eq!(png.pixel_dimensions().unwrap(), (3, 2));
+        assert_eq!(png.pixel_count().unwrap(), 6);
+
+        for (width, height) in [(65535, 65535), (1, 65535), (2147483647, 2147483647)] {
+            let png = Png::from_chunks(vec![Chunk::new_ihdr(width, height, 8, 6).unwrap(), Chunk::new_iend()]);
+            assert_eq!(png.pixel_dimensions().unwrap(), (width, height));
+            assert_eq!(png.pixel_count().unwrap(), width as u64 * height as u64);
         }
+        assert_eq!(Png::from_chunks(vec![Chunk::new_ihdr(65535, 65535, 8, 6).unwrap()]).pixel_count().unwrap(), 4294836225);
 
-        return png_as_bytes
+        assert!(matches!(testing_png().pixel_dimensions(), Err(PngMeError::ChunkNotFound(_))));
+        assert!(testing_png().pixel_count().is_err());
     }
-}
 
-// https://doc.rust-lang.org/std/convert/trait.TryFrom.html
-impl TryFrom<&[u8]> for Png {
-    type Error = &'static str;
+    #[test]
+    fn test_print_size() {
+        let phys = |x: u32, y: u32, unit: u8| {
+            let mut data = x.to_be_bytes().to_vec();
+            data.extend(y.to_be_bytes());
+            data.push(unit);
+            Chunk::new(ChunkType::from_str("pHYs").unwrap(), data).unwrap()
+        };
+        // 96 DPI is 3779.5 pixels per metre, 3780 once rounded
+        let mut png = Png::new_blank(960, 480, 2, 8).unwrap();
+        assert!(png.print_size().is_none());
+        png.add_after_ihdr(phys(3780, 3780, 1));
 
-    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
-        // Create a reader and two buffers, one of length four for the various chunks of four bytes
-        let mut reader = BufReader::new(bytes);
-        let mut header: [u8; 8] = [0; 8];
-        
-        reader.read_exact(&mut header).unwrap();
+        let size = png.print_size().unwrap();
+        assert!((size.dpi - 96.012).abs() < 1e-9);
+        assert!((size.width_cm - 25.396825).abs() < 1e-6);
+        assert!((size.height_cm - 12.698413).abs() < 1e-6);
+        // About 10 by 5 inches
+        assert!((size.width_cm / 2.54 - 10.0).abs() < 0.01);
+
+        png.remove_first_chunk("pHYs").unwrap();
+        png.add_after_ihdr(phys(3780, 3780, 0));
+        assert!(png.print_size().is_none());
+        png.remove_first_chunk("pHYs").unwrap();
+        png.add_after_ihdr(phys(0, 3780, 1));
+        assert!(png.print_size().is_none());
+    }
+
+    #[test]
+    fn test_export_import_chunks_directory() {
+        let dir = std::env::temp_dir().join(format!("pngme-{}-export", std::process::id()));
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0hello").unwrap());
+        png.add_before_iend(chunk_from_strings("ruSt", "").unwrap());
+        assert_eq!(png.chunks().len(), 5);
+
+        let paths = png.export_chunks_to_directory(&dir).unwrap();
+        let names: Vec<String> = paths.iter().map(|p| p.file_name().unwrap().to_string_lossy().to_string()).collect();
+        assert_eq!(names, vec!["00-IHDR.bin", "01-IDAT.bin", "02-tEXt.bin", "03-ruSt.bin", "04-IEND.bin"]);
+        assert_eq!(fs::read(&paths[2]).unwrap(), b"Comment\0hello");
+
+        let chunks = Png::import_chunks_from_directory(&dir).unwrap();
+        assert_eq!(Png::from_chunks(chunks), png);
+
+        fs::write(dir.join("05-toolong.bin"), b"").unwrap();
+        assert!(Png::import_chunks_from_directory(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_to_compressed_round_trip() {
+        let mut png = framed_png();
+        let comment = format!("Comment\0{}", "A long comment that repeats. ".repeat(50));
+        png.add_before_iend(chunk_from_strings("tEXt", &comment).unwrap());
+        png.add_before_iend(chunk_from_strings("ruSt", &"secret ".repeat(100)).unwrap());
+        png.add_before_iend(chunk_from_strings("shRt", "tiny").unwrap());
+        png.add_before_iend(chunk_from_strings("sPLT", &"palette ".repeat(50)).unwrap());
+
+        let compressed = png.to_compressed().unwrap();
+        assert!(compressed.as_bytes().len() < png.as_bytes().len());
+        assert!(compressed.chunk_by_type("tEXt").is_none());
+        assert!(compressed.chunk_by_type("zTXt").is_some());
+        assert!(compressed.chunk_by_type("ruSt").unwrap().length() < png.chunk_by_type("ruSt").unwrap().length());
+        // Compressing would make this one bigger
+        assert_eq!(compressed.chunk_by_type("shRt"), png.chunk_by_type("shRt"));
+        // Standard chunks keep their format even when compressing would shrink them
+        assert_eq!(compressed.chunk_by_type("sPLT"), png.chunk_by_type("sPLT"));
+        assert_eq!(compressed.chunk_by_type("IDAT"), png.chunk_by_type("IDAT"));
+        assert_eq!(compressed.chunks()[compressed.chunks().len() - 2].chunk_type().to_string(), "cPRS");
+        assert!(compressed.find_text("repeats").len() == 1);
+
+        assert_eq!(Png::from_compressed(&compressed).unwrap(), png);
+    }
+
+    #[test]
+    fn test_to_compressed_errors() {
+        let png = framed_png();
+        assert!(matches!(Png::from_compressed(&png), Err(PngMeError::ChunkNotFound(_))));
+
+        let compressed = png.to_compressed().unwrap();
+        assert!(compressed.to_compressed().is_err());
+        assert_eq!(Png::from_compressed(&compressed).unwrap(), png);
+    }
+
+    #[test]
+    fn test_embed_and_sign() {
+        let mut png = framed_png();
+        png.embed_and_sign(ChunkType::from_str("sIGn").unwrap(), b"meet at noon", b"secret key").unwrap();
 
-        if header != Png::STANDARD_HEADER {
-            return Err("Header of the file does not match with the standard jpg header")
+        assert_eq!(png.chunk_by_type("sIGn").unwrap().length(), 32 + 12);
+        assert_eq!(png.extract_and_verify("sIGn", b"secret key").unwrap(), b"meet at noon");
+        assert!(matches!(png.extract_and_verify("sIGn", b"wrong key"), Err(PngMeError::AuthenticationFailed)));
+        assert!(matches!(png.extract_and_verify("ruSt", b"secret key"), Err(PngMeError::ChunkNotFound(_))));
+    }
+
+    // Test cases 1, 2 and 6 of https://datatracker.ietf.org/doc/html/rfc4231#section-4
+    #[test]
+    fn test_embed_and_sign_rfc4231_tags() {
+        let cases: [(&[u8], &[u8], &str); 3] = [
+            (&[0x0b; 20], b"Hi There", "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"),
+            (b"Jefe", b"what do ya want for nothing?", "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"),
+            // A key longer than a SHA-256 block
+            (&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First", "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"),
+        ];
+        for (key, message, expected) in cases {
+            let mut png = framed_png();
+            png.embed_and_sign(ChunkType::from_str("sIGn").unwrap(), message, key).unwrap();
+            let data = png.chunk_by_type("sIGn").unwrap().data();
+            let tag: String = data[..Png::SIGNATURE_TAG_LEN].iter().map(|byte| format!("{:02x}", byte)).collect();
+            assert_eq!(tag, expected);
+            assert_eq!(png.extract_and_verify("sIGn", key).unwrap(), message);
         }
+    }
 
-        let mut chunks: Vec<Chunk> = vec!();
-        
-        let mut buffer: [u8; 4] = [0; 4];
-
-        while !reader.buffer().is_empty() {
-            // Read the length of the data of the next chunk
-            reader.read_exact(&mut buffer).unwrap();
-            let data_length: u32 = u32::from_be_bytes(buffer);
-            // Read 4 bytes, the length of the data and another 4 bytes
-            let mut big_buffer: Vec<u8> = vec!(0; (data_length+8).try_into().unwrap());
-            reader.read_exact(&mut big_buffer).unwrap();
-            // Use Chunk::try_from to create a new chunk and push it to the list of chunks
-            let mut v: Vec<u8> = buffer.to_vec();
-            v.extend(big_buffer);
-
-            chunks.push(
-                Chunk::try_from(v.as_ref()).unwrap()
-            );
-        }
-        
-        return Ok(Png{chunk_list: chunks})
+    #[test]
+    fn test_extract_and_verify_detects_tampering() {
+        let mut png = framed_png();
+        png.embed_and_sign(ChunkType::from_str("sIGn").unwrap(), b"meet at noon", b"secret key").unwrap();
+        let signed = png.chunk_by_type("sIGn").unwrap().data().to_vec();
+
+        // Change one bit of the message, then one bit of the tag
+        for i in [signed.len() - 1, 0] {
+            let mut tampered = signed.clone();
+            tampered[i] ^= 1;
+            let png = Png::from_chunks(vec![
+                framed_png().chunks()[0].clone(),
+                Chunk::new(ChunkType::from_str("sIGn").unwrap(), tampered).unwrap(),
+            ]);
+            assert!(matches!(png.extract_and_verify("sIGn", b"secret key"), Err(PngMeError::AuthenticationFailed)));
+        }
+
+        let short = Png::from_chunks(vec![chunk_from_strings("sIGn", "too short").unwrap()]);
+        assert!(matches!(short.extract_and_verify("sIGn", b"secret key"), Err(PngMeError::InvalidLength{..})));
     }
-}
 
-// https://doc.rust-lang.org/std/fmt/trait.Display.html
-impl fmt::Display for Png {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "Png {{")?;
-        let h = self.header();
-        writeln!(f, "  Header: {} {} {} {} {} {} {} {}", h[0], h[1], h[2], h[3], h[4], h[5], h[6], h[7])?;
+    #[test]
+    fn test_chunk_sequence_round_trip() {
+        let mut png = framed_png();
+        let parts = vec![b"first".to_vec(), Vec::new(), b"third".to_vec()];
+        png.add_chunk_sequence(ChunkType::from_str("sEQn").unwrap(), parts.clone()).unwrap();
 
-        for (i, c) in self.chunks().iter().enumerate() {
-            writeln!(f, "  Chunk {}: {}", i, c)?;
+        assert_eq!(png.chunks().len(), 6);
+        assert_eq!(png.chunks()[2].data(), &[0, 0, 0, 0, 0, 0, 0, 3, b'f', b'i', b'r', b's', b't']);
+        assert_eq!(png.read_chunk_sequence("sEQn").unwrap(), parts);
+    }
+
+    #[test]
+    fn test_chunk_sequence_out_of_order() {
+        let mut ordered = framed_png();
+        let parts: Vec<Vec<u8>> = (0..5).map(|i| format!("part {}", i).into_bytes()).collect();
+        ordered.add_chunk_sequence(ChunkType::from_str("sEQn").unwrap(), parts.clone()).unwrap();
+
+        let chunks = ordered.chunks();
+        let shuffled = Png::from_chunks(vec![
+            chunks[0].clone(), chunks[4].clone(), chunks[1].clone(), chunks[6].clone(),
+            chunks[2].clone(), chunks[3].clone(), chunks[5].clone(), chunks[7].clone(),
+        ]);
+        assert_eq!(shuffled.read_chunk_sequence("sEQn").unwrap(), parts);
+    }
+
+    #[test]
+    fn test_chunk_sequence_errors() {
+        let sequence_chunk = |index: u32, total: u32| {
+            let mut data = index.to_be_bytes().to_vec();
+            data.extend(total.to_be_bytes());
+            Chunk::new(ChunkType::from_str("sEQn").unwrap(), data).unwrap()
+        };
+        let png = |chunks: Vec<Chunk>| {
+            let mut png = framed_png();
+            png.append_chunks(chunks);
+            png
+        };
+
+        assert!(matches!(framed_png().read_chunk_sequence("sEQn"), Err(PngMeError::ChunkNotFound(_))));
+        assert!(png(vec![sequence_chunk(0, 2), sequence_chunk(1, 3)]).read_chunk_sequence("sEQn").is_err());
+        assert!(png(vec![sequence_chunk(0, 2), sequence_chunk(0, 2)]).read_chunk_sequence("sEQn").is_err());
+        assert!(png(vec![sequence_chunk(1, 2)]).read_chunk_sequence("sEQn").is_err());
+        assert!(png(vec![chunk_from_strings("sEQn", "short").unwrap()]).read_chunk_sequence("sEQn").is_err());
+        assert_eq!(png(vec![sequence_chunk(1, 2), sequence_chunk(0, 2)]).read_chunk_sequence("sEQn").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_encode_compressed_invalid_level() {
+        let mut png = testing_png();
+        assert!(png.encode_compressed(ChunkType::from_str("ruSt").unwrap(), b"message", 10).is_err());
+        assert!(png.chunk_by_type("ruSt").is_none());
+    }
+
+    #[test]
+    fn test_decode_compressed_errors() {
+        let png = testing_png();
+        assert!(matches!(png.decode_compressed("ruSt"), Err(PngMeError::ChunkNotFound(_))));
+        assert!(matches!(png.decode_compressed("FrSt"), Err(PngMeError::InvalidChunkData(_))));
+    }
+
+    #[test]
+    fn test_decompress_image_data() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let pixels = zlib::decompress(png.chunk_by_type("IDAT").unwrap().data()).unwrap();
+        // 50 scanlines of a filter byte and 50 RGBA pixels
+        assert_eq!(pixels.len(), 50 * (1 + 50 * 4));
+    }
+
+    #[test]
+    fn test_chunk_data_combined() {
+        let mut png = framed_png();
+        png.remove_first_chunk("IDAT").unwrap();
+        for data in ["first ", "second ", "third"] {
+            png.add_before_iend(chunk_from_strings("IDAT", data).unwrap());
         }
+        png.add_after_ihdr(chunk_from_strings("tEXt", "a\0b").unwrap());
 
-        writeln!(f, "}}",)?;
-        Ok(())
+        assert_eq!(png.chunk_data_combined("IDAT"), b"first second third");
+        assert_eq!(png.chunk_data_combined("tEXt"), b"a\0b");
+        assert!(png.chunk_data_combined("ruSt").is_empty());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::chunk_type::ChunkType;
-    use crate::chunk::Chunk;
-    use std::str::FromStr;
-    use std::convert::TryFrom;
+    #[test]
+    fn test_replace_image_data() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let stream = png.image_data();
+        png.replace_image_data(&stream);
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
 
-    fn testing_chunks() -> Vec<Chunk> {
-        vec![
-            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
-            chunk_from_strings("miDl", "I am another chunk").unwrap(),
-            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
-        ]
+        // Large streams are split
+        let stream: Vec<u8> = (0..150000).map(|i| i as u8).collect();
+        png.replace_image_data(&stream);
+        let lengths: Vec<u32> = png.chunks().iter().filter(|c| *c.chunk_type() == ChunkType::IDAT).map(|c| c.length()).collect();
+        assert_eq!(lengths, vec![65535, 65535, 18930]);
+        assert_eq!(png.image_data(), stream);
+        assert!(png.is_structurally_complete());
     }
 
-    fn testing_png() -> Png {
-        let chunks = testing_chunks();
-        Png::from_chunks(chunks)
+    fn types_of<'a>(chunks: impl IntoIterator<Item = &'a Chunk>) -> Vec<String> {
+        chunks.into_iter().map(|c| c.chunk_type().to_string()).collect()
     }
-    // I modified this function because the result needs a second type for the error
-    fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk, String> {
-        // I commented this import
-        //use std::str::FromStr;
 
-        let chunk_type = ChunkType::from_str(chunk_type)?;
-        let data: Vec<u8> = data.bytes().collect();
+    #[test]
+    fn test_remove_comment_chunks() {
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("tEXt", "Author\0me").unwrap());
+        png.add_before_iend(chunk_from_strings("gAMA", "gama").unwrap());
+        png.add_before_iend(chunk_from_strings("zTXt", "Software\0\0zlib").unwrap());
+        png.add_before_iend(chunk_from_strings("iTXt", "Title\0\0\0\0\0title").unwrap());
+        png.add_before_iend(chunk_from_strings("ruSt", "custom").unwrap());
 
-        Ok(Chunk::new(chunk_type, data))
+        assert_eq!(png.remove_comment_chunks(), 3);
+        assert_eq!(types_of(png.chunks()), vec!["IHDR", "IDAT", "gAMA", "ruSt", "IEND"]);
+        assert_eq!(png.remove_comment_chunks(), 0);
     }
 
     #[test]
-    fn test_from_chunks() {
-        let chunks = testing_chunks();
-        let png = Png::from_chunks(chunks);
+    fn test_add_srgb_chunk() {
+        let mut png = Png::new_blank(2, 2, 3, 8).unwrap();
+        png.add_after_ihdr(chunk_from_strings("iCCP", "profile\0\0zlib").unwrap());
+        png.add_after_ihdr(chunk_from_strings("cHRM", "chromaticities").unwrap());
+        png.add_after_ihdr(chunk_from_strings("gAMA", "gama").unwrap());
+        png.add_srgb_chunk(0).unwrap();
+        png.add_srgb_chunk(2).unwrap();
 
-        assert_eq!(png.chunks().len(), 3);
+        let srgb: Vec<&Chunk> = png.find_chunks(|c| c.chunk_type().to_string() == "sRGB");
+        assert_eq!(srgb.len(), 1);
+        assert_eq!(srgb[0].data(), [2]);
+        // gAMA is kept for decoders that do not understand sRGB
+        assert_eq!(types_of(png.chunks()), vec!["IHDR", "sRGB", "gAMA", "PLTE", "IDAT", "IEND"]);
+        assert!(matches!(png.add_srgb_chunk(4), Err(PngMeError::InvalidInput(_))));
     }
 
     #[test]
-    fn test_valid_from_bytes() {
-        let chunk_bytes: Vec<u8> = testing_chunks()
-            .into_iter()
-            .flat_map(|chunk| chunk.as_bytes())
-            .collect();
+    fn test_remove_color_management_chunks() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.remove_color_management_chunks();
+        assert!(png.chunk_by_type("sRGB").is_none());
+        assert!(png.chunk_by_type("gAMA").is_none());
+        assert!(png.chunk_by_type("pHYs").is_some());
+    }
 
-        let bytes: Vec<u8> = Png::STANDARD_HEADER
-            .iter()
-            .chain(chunk_bytes.iter())
-            .copied()
-            .collect();
+    fn text_png() -> Png {
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("tEXt", "Author\0Jane Doe").unwrap());
+        let mut ztxt = b"Comment\0\0".to_vec();
+        ztxt.extend(zlib::compress(b"made with a secret tool", 6).unwrap());
+        png.add_before_iend(Chunk::new(ChunkType::from_str("zTXt").unwrap(), ztxt).unwrap());
+        let mut itxt = b"Title\0\x01\0en\0Titre\0".to_vec();
+        itxt.extend(zlib::compress("caf\u{e9} at night".as_bytes(), 6).unwrap());
+        png.add_before_iend(Chunk::new(ChunkType::from_str("iTXt").unwrap(), itxt).unwrap());
+        png.add_before_iend(chunk_from_strings("ruSt", "secret but not text").unwrap());
+        png
+    }
 
-        let png = Png::try_from(bytes.as_ref());
+    #[test]
+    fn test_contains_text() {
+        let png = text_png();
+        assert!(png.contains_text("Jane"));
+        assert!(png.contains_text("secret tool"));
+        assert!(png.contains_text("caf\u{e9}"));
+        assert!(png.contains_text("Author"));
+        assert!(!png.contains_text("John"));
+        assert!(!png.contains_text("not text"));
+    }
 
-        assert!(png.is_ok());
+    #[test]
+    fn test_find_text() {
+        let png = text_png();
+        let found = png.find_text("e");
+        let found: Vec<(String, &str)> = found.iter().map(|(t, s)| (t.to_string(), s.as_str())).collect();
+        assert_eq!(found, vec![
+            (String::from("tEXt"), "Author: Jane Doe"),
+            (String::from("zTXt"), "Comment: made with a secret tool"),
+            (String::from("iTXt"), "Title: caf\u{e9} at night"),
+        ]);
+        assert!(png.find_text("John").is_empty());
     }
 
     #[test]
-    fn test_invalid_header() {
-        let chunk_bytes: Vec<u8> = testing_chunks()
-            .into_iter()
-            .flat_map(|chunk| chunk.as_bytes())
-            .collect();
+    fn test_all_text_data() {
+        let png = text_png();
+        let entries = png.all_text_data().unwrap();
+        let entries: Vec<(String, &str, &str)> = entries.iter().map(|(t, k, s)| (t.to_string(), k.as_str(), s.as_str())).collect();
+        assert_eq!(entries, vec![
+            (String::from("tEXt"), "Author", "Jane Doe"),
+            (String::from("zTXt"), "Comment", "made with a secret tool"),
+            (String::from("iTXt"), "Title", "caf\u{e9} at night"),
+        ]);
 
-        let bytes: Vec<u8> = [13, 80, 78, 71, 13, 10, 26, 10]
-            .iter()
-            .chain(chunk_bytes.iter())
-            .copied()
-            .collect();
+        let mut broken = framed_png();
+        broken.add_before_iend(chunk_from_strings("zTXt", "no separator").unwrap());
+        assert!(matches!(broken.all_text_data(), Err(PngMeError::InvalidChunkData(_))));
+        assert!(testing_png().all_text_data().unwrap().is_empty());
 
-        let png = Png::try_from(bytes.as_ref());
+        // iTXt is read the same way as `ItxtData`, which rejects unknown compression flags
+        let mut unknown_flag = framed_png();
+        unknown_flag.add_before_iend(Chunk::new(ChunkType::from_str("iTXt").unwrap(), b"Title\0\x02\0\0\0text".to_vec()).unwrap());
+        assert!(unknown_flag.all_text_data().is_err());
+        assert!(unknown_flag.chunks()[2].parse_itxt().is_err());
+    }
 
-        assert!(png.is_err());
+    #[test]
+    fn test_contains_hidden_payload() {
+        // xorshift, a repeatable stand in for encrypted data
+        let mut state: u32 = 0x2545F491;
+        let random: Vec<u8> = (0..4096).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            return state as u8
+        }).collect();
+
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("tEXt", &format!("Comment\0{}", "the quick brown fox jumps over the lazy dog ".repeat(50))).unwrap());
+        let analysis = png.contains_hidden_payload();
+        assert!(!analysis.has_nonstandard_chunks);
+        assert!(!analysis.unusual_data_entropy);
+        assert!(analysis.suspicious_chunk_types.is_empty());
+
+        // Random data is flagged even in a standard chunk type
+        png.add_before_iend(Chunk::new(ChunkType::from_str("tEXt").unwrap(), random.clone()).unwrap());
+        let analysis = png.contains_hidden_payload();
+        assert!(!analysis.has_nonstandard_chunks);
+        assert!(analysis.unusual_data_entropy);
+        assert_eq!(analysis.suspicious_chunk_types, vec![ChunkType::from_str("tEXt").unwrap()]);
+
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("ruSt", "plain text").unwrap());
+        png.add_before_iend(Chunk::new(ChunkType::from_str("seCr").unwrap(), random).unwrap());
+        let analysis = png.contains_hidden_payload();
+        assert!(analysis.has_nonstandard_chunks);
+        assert!(analysis.unusual_data_entropy);
+        let types: Vec<String> = analysis.suspicious_chunk_types.iter().map(|t| t.to_string()).collect();
+        assert_eq!(types, vec!["ruSt", "seCr"]);
     }
 
     #[test]
-    fn test_invalid_chunk() {
-        let mut chunk_bytes: Vec<u8> = testing_chunks()
-            .into_iter()
-            .flat_map(|chunk| chunk.as_bytes())
-            .collect();
+    fn test_find_and_replace_in_text_chunks() {
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("tEXt", "Author\0Jane Doe and Jane Roe").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "Jane\0Made by Jane").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0nothing here").unwrap());
+        png.add_before_iend(chunk_from_strings("ruSt", "Jane").unwrap());
 
-        #[rustfmt::skip]
-        let mut bad_chunk = vec![
-            0, 0, 0, 5,         // length
-            32, 117, 83, 116,   // Chunk Type (bad)
-            65, 64, 65, 66, 67, // Data
-            1, 2, 3, 4, 5       // CRC (bad)
-        ];
+        assert_eq!(png.find_and_replace_in_text_chunks("Jane", "John"), 3);
+        let reparsed = png.reparse().unwrap();
+        let texts: Vec<String> = reparsed.chunks().iter().skip(2).map(|c| c.data_as_string().unwrap()).collect();
+        assert_eq!(texts, vec!["Author\0John Doe and John Roe", "Jane\0Made by John", "Comment\0nothing here", "Jane", ""]);
+        assert_eq!(png.find_and_replace_in_text_chunks("Jane", "John"), 0);
+        assert_eq!(png.find_and_replace_in_text_chunks("", "John"), 0);
+    }
 
-        chunk_bytes.append(&mut bad_chunk);
+    #[test]
+    fn test_extract_metadata() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.add_before_iend(chunk_from_strings("tEXt", "Author\0Jane Doe").unwrap());
+        let time = PngTime::parse_rfc3339("2024-01-02T03:04:05Z").unwrap();
+        png.set_modification_time(time);
 
-        let png = Png::try_from(chunk_bytes.as_ref());
+        let metadata = png.extract_metadata().unwrap();
+        assert_eq!((metadata.width, metadata.height), (50, 50));
+        assert_eq!(metadata.bit_depth, 8);
+        assert_eq!(metadata.color_type, 6);
+        assert_eq!(metadata.gamma, Some(0.45455));
+        assert_eq!(metadata.modification_time, Some(time));
+        assert_eq!(metadata.text_entries, vec![(String::from("Author"), String::from("Jane Doe"))]);
+        assert!(!metadata.is_progressive);
+        assert!(metadata.has_srgb);
+        assert_eq!(PngMetadata::from_json_str(&metadata.to_json_string()).unwrap(), metadata);
 
-        assert!(png.is_err());
+        assert!(testing_png().extract_metadata().is_err());
     }
 
+    #[test]
+    fn test_deduplicate_chunks() {
+        let mut png = framed_png();
+        png.add_after_ihdr(chunk_from_strings("gAMA", "first").unwrap());
+        png.add_before_iend(chunk_from_strings("gAMA", "second").unwrap());
+        png.add_before_iend(chunk_from_strings("IDAT", "more data").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "a\0b").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "c\0d").unwrap());
+
+        let removed = png.deduplicate_chunks();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].data_as_string().unwrap(), "second");
+        // IDAT and text chunks can appear many times
+        assert_eq!(types_of(png.chunks()), vec!["IHDR", "gAMA", "IDAT", "IDAT", "tEXt", "tEXt", "IEND"]);
+        assert_eq!(png.chunk_by_type("gAMA").unwrap().data_as_string().unwrap(), "first");
+        assert!(png.deduplicate_chunks().is_empty());
+    }
 
     #[test]
-    fn test_list_chunks() {
-        let png = testing_png();
-        let chunks = png.chunks();
-        assert_eq!(chunks.len(), 3);
+    fn test_partition_chunks() {
+        let mut png = Png::new_blank(2, 2, 3, 8).unwrap();
+        png.add_before_iend(chunk_from_strings("ruSt", "custom").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "Comment\0text").unwrap());
+
+        let (critical, ancillary) = png.partition_chunks();
+        assert_eq!(types_of(critical), vec!["IHDR", "PLTE", "IDAT", "IEND"]);
+        assert_eq!(types_of(ancillary), vec!["ruSt", "tEXt"]);
     }
 
     #[test]
-    fn test_chunk_by_type() {
-        let png = testing_png();
-        let chunk = png.chunk_by_type("FrSt").unwrap();
-        assert_eq!(&chunk.chunk_type().to_string(), "FrSt");
-        assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
+    fn test_partition_into_chunks() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.add_before_iend(chunk_from_strings("ruSt", "custom").unwrap());
 
+        let (critical, ancillary) = png.partition_into_chunks();
+        // The image file hides its message in RuSt, which is critical because of its first letter
+        assert_eq!(types_of(&critical), vec!["IHDR", "IDAT", "RuSt", "IEND"]);
+        assert_eq!(types_of(&ancillary), vec!["sRGB", "gAMA", "pHYs", "ruSt"]);
     }
 
     #[test]
-    fn test_append_chunk() {
-        let mut png = testing_png();
-        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
-        let chunk = png.chunk_by_type("TeSt").unwrap();
-        assert_eq!(&chunk.chunk_type().to_string(), "TeSt");
-        assert_eq!(&chunk.data_as_string().unwrap(), "Message");
+    fn test_byte_size_of_type() {
+        let mut png = framed_png();
+        png.add_before_iend(chunk_from_strings("IDAT", "more data").unwrap());
+        let idat_data: u64 = png.chunks().iter().filter(|c| c.chunk_type() == &ChunkType::IDAT).map(|c| c.length() as u64).sum();
+        assert_eq!(png.byte_size_of_type("IDAT"), idat_data + 24);
+        assert_eq!(png.byte_size_of_type("IEND"), 12);
+        assert_eq!(png.byte_size_of_type("ruSt"), 0);
     }
 
     #[test]
-    fn test_remove_first_chunk() {
-        let mut png = testing_png();
-        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
-        png.remove_first_chunk("TeSt").unwrap();
-        let chunk = png.chunk_by_type("TeSt");
-        assert!(chunk.is_none());
+    fn test_byte_size_breakdown() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let breakdown = png.byte_size_breakdown();
+        assert_eq!(breakdown.values().sum::<u64>() + 8, PNG_FILE.len() as u64);
+        assert_eq!(breakdown.len(), 7);
+        assert_eq!(breakdown[&ChunkType::IHDR], 25);
+        assert_eq!(breakdown[&ChunkType::IDAT], png.byte_size_of_type("IDAT"));
     }
 
     #[test]
-    fn test_png_from_image_file() {
-        let png = Png::try_from(&PNG_FILE[..]);
-        assert!(png.is_ok());
+    fn test_split_at_idat() {
+        let mut png = framed_png();
+        png.add_after_ihdr(chunk_from_strings("gAMA", "gama").unwrap());
+        png.add_before_iend(chunk_from_strings("IDAT", "more data").unwrap());
+        png.add_before_iend(chunk_from_strings("tEXt", "a\0b").unwrap());
+
+        let (before, idat, after) = png.split_at_idat();
+        assert_eq!(types_of(before.clone()), vec!["IHDR", "gAMA"]);
+        assert_eq!(types_of(idat.clone()), vec!["IDAT", "IDAT"]);
+        assert_eq!(types_of(after.clone()), vec!["tEXt", "IEND"]);
+        // Every chunk is in exactly one group, in order
+        let joined: Vec<&Chunk> = before.into_iter().chain(idat).chain(after).collect();
+        assert_eq!(joined, png.chunks().iter().collect::<Vec<&Chunk>>());
+
+        let png = testing_png();
+        let (before, idat, after) = png.split_at_idat();
+        assert_eq!(before.len(), 3);
+        assert!(idat.is_empty() && after.is_empty());
     }
 
     #[test]
-    fn test_as_bytes() {
+    fn test_chunks_sorted_by_type() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
-        let actual = png.as_bytes();
-        let expected: Vec<u8> = PNG_FILE.to_vec();
-        assert_eq!(actual, expected);
+        let sorted = png.chunks_sorted_by_type();
+        assert_eq!(types_of(sorted.clone()), vec!["IDAT", "IEND", "IHDR", "RuSt", "gAMA", "pHYs", "sRGB"]);
+        // Same result every time and the Png itself is not reordered
+        assert_eq!(types_of(png.chunks_sorted_by_type()), types_of(sorted));
+        assert_eq!(png.chunks()[0].chunk_type().to_string(), "IHDR");
     }
 
     #[test]
-    fn test_png_trait_impls() {
-        let chunk_bytes: Vec<u8> = testing_chunks()
-            .into_iter()
-            .flat_map(|chunk| chunk.as_bytes())
-            .collect();
+    fn test_chunks_sorted_by_type_is_stable() {
+        let png = Png::from_chunks(vec![
+            chunk_from_strings("tEXt", "second type, first").unwrap(),
+            chunk_from_strings("IHDR", "first type").unwrap(),
+            chunk_from_strings("tEXt", "second type, second").unwrap(),
+        ]);
+        let data: Vec<String> = png.chunks_sorted_by_type().iter().map(|c| c.data_as_string().unwrap()).collect();
+        assert_eq!(data, vec!["first type", "second type, first", "second type, second"]);
+    }
 
-        let bytes: Vec<u8> = Png::STANDARD_HEADER
-            .iter()
-            .chain(chunk_bytes.iter())
-            .copied()
-            .collect();
+    #[test]
+    fn test_modification_time() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.modification_time().unwrap().is_none());
 
-        let png: Png = TryFrom::try_from(bytes.as_ref()).unwrap();
+        let time = PngTime::parse_rfc3339("2022-08-15T10:20:30Z").unwrap();
+        png.set_modification_time(time);
+        let parsed = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(parsed.modification_time().unwrap(), Some(time));
 
-        let _png_string = format!("{}", png);
+        // Setting it again replaces the chunk
+        let later = PngTime::parse_rfc3339("2023-01-01T00:00:00Z").unwrap();
+        png.set_modification_time(later);
+        assert_eq!(png.chunks().iter().filter(|c| c.chunk_type().to_string() == "tIME").count(), 1);
+        assert_eq!(png.modification_time().unwrap(), Some(later));
+
+        assert!(png.remove_modification_time());
+        assert!(!png.remove_modification_time());
+        assert!(png.modification_time().unwrap().is_none());
     }
 
     // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
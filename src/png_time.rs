@@ -0,0 +1,246 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::chunk::Chunk;
+use crate::error::PngMeError;
+
+/// The time of the last modification of an image, stored in the tIME chunk. Always UTC.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.tIME
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PngTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+fn is_leap_year(year: i64) -> bool {
+    return (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u8) -> u8 {
+    return match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Conversions between dates and days since 1970-01-01
+// https://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    return era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 };
+    return (year, month, day)
+}
+
+impl PngTime {
+    /// Creates a time checking that every field is within its range.
+    /// A second of 60 is allowed for leap seconds.
+    pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Result<PngTime, PngMeError> {
+        if !(1..=12).contains(&month)
+            || day == 0
+            || day > days_in_month(year as i64, month)
+            || hour > 23
+            || minute > 59
+            || second > 60
+        {
+            return Err(PngMeError::InvalidInput(format!(
+                "invalid date {:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, hour, minute, second
+            )))
+        }
+        return Ok(PngTime{year, month, day, hour, minute, second})
+    }
+
+    /// Creates the time that is `seconds` after 1970-01-01T00:00:00Z
+    pub fn from_unix_seconds(seconds: u64) -> PngTime {
+        let days = (seconds / 86400) as i64;
+        let rest = seconds % 86400;
+        let (year, month, day) = civil_from_days(days);
+        return PngTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (rest / 3600) as u8,
+            minute: (rest / 60 % 60) as u8,
+            second: (rest % 60) as u8,
+        }
+    }
+
+    /// Returns the number of seconds since 1970-01-01T00:00:00Z
+    pub fn to_unix_seconds(self) -> i64 {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        return days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+
+    /// The current UTC time of the system clock
+    pub fn now() -> PngTime {
+        // A clock set before 1970 is treated as the epoch itself
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        return PngTime::from_unix_seconds(seconds)
+    }
+
+    /// Parses an RFC 3339 timestamp such as `2024-05-01T12:30:00Z` or `2024-05-01T14:30:00.25+02:00`.
+    /// The time is converted to UTC and fractions of a second are dropped.
+    /// https://datatracker.ietf.org/doc/html/rfc3339#section-5.6
+    pub fn parse_rfc3339(s: &str) -> Result<PngTime, PngMeError> {
+        let invalid = || PngMeError::InvalidInput(format!("\"{}\" is not an RFC 3339 timestamp", s));
+        let b = s.as_bytes();
+        if !s.is_ascii() || b.len() < 20 || b[4] != b'-' || b[7] != b'-' || !matches!(b[10], b'T' | b't' | b' ') || b[13] != b':' || b[16] != b':' {
+            return Err(invalid())
+        }
+        let number = |range: std::ops::Range<usize>| -> Result<i64, PngMeError> {
+            let digits = &s[range];
+            if !digits.bytes().all(|c| c.is_ascii_digit()) {
+                return Err(invalid())
+            }
+            return digits.parse::<i64>().map_err(|_| invalid())
+        };
+        let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+        let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+
+        // Optional fraction of a second, then the offset
+        let mut rest = &s[19..];
+        if let Some(fraction) = rest.strip_prefix('.') {
+            let digits = fraction.bytes().take_while(|c| c.is_ascii_digit()).count();
+            if digits == 0 {
+                return Err(invalid())
+            }
+            rest = &fraction[digits..];
+        }
+        let offset_seconds: i64 = match rest.as_bytes() {
+            [b'Z'] | [b'z'] => 0,
+            [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+                let (offset_hours, offset_minutes) = (number(s.len() - 5..s.len() - 3)?, number(s.len() - 2..s.len())?);
+                if offset_hours > 23 || offset_minutes > 59 {
+                    return Err(invalid())
+                }
+                let offset = offset_hours * 3600 + offset_minutes * 60;
+                if *sign == b'+' { offset } else { -offset }
+            }
+            _ => return Err(invalid()),
+        };
+
+        // Validate the local time before moving it to UTC
+        let local = PngTime::new(year as u16, month as u8, day as u8, hour as u8, minute as u8, second as u8)?;
+        if offset_seconds == 0 {
+            return Ok(local)
+        }
+        let utc = local.to_unix_seconds() - offset_seconds;
+        if utc < 0 {
+            return Err(invalid())
+        }
+        return Ok(PngTime::from_unix_seconds(utc as u64))
+    }
+
+    /// Returns the 7 bytes of data of the tIME chunk for this time
+    pub fn to_bytes(self) -> [u8; 7] {
+        let [y1, y2] = self.year.to_be_bytes();
+        return [y1, y2, self.month, self.day, self.hour, self.minute, self.second]
+    }
+}
+
+impl TryFrom<&Chunk> for PngTime {
+    type Error = PngMeError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let d = chunk.data();
+        if chunk.chunk_type().to_string() != "tIME" || d.len() != 7 {
+            return Err(PngMeError::InvalidChunkData(String::from("expected a tIME chunk with 7 bytes of data")))
+        }
+        return PngTime::new(u16::from_be_bytes([d[0], d[1]]), d[2], d[3], d[4], d[5], d[6])
+    }
+}
+
+impl FromStr for PngTime {
+    type Err = PngMeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return PngTime::parse_rfc3339(s)
+    }
+}
+
+// Written as RFC 3339
+impl fmt::Display for PngTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rfc3339() {
+        let time = PngTime::parse_rfc3339("2024-05-01T12:30:45Z").unwrap();
+        assert_eq!(time, PngTime::new(2024, 5, 1, 12, 30, 45).unwrap());
+        assert_eq!(time.to_string(), "2024-05-01T12:30:45Z");
+    }
+
+    #[test]
+    fn test_parse_rfc3339_with_offset() {
+        // Crosses a day and a month backwards
+        let time = PngTime::parse_rfc3339("2024-03-01T01:15:00.123+02:00").unwrap();
+        assert_eq!(time, PngTime::new(2024, 2, 29, 23, 15, 0).unwrap());
+        let time = PngTime::parse_rfc3339("2023-12-31t22:00:00-05:30").unwrap();
+        assert_eq!(time, PngTime::new(2024, 1, 1, 3, 30, 0).unwrap());
+    }
+
+    #[test]
+    fn test_parse_rfc3339_invalid() {
+        assert!(PngTime::parse_rfc3339("2024-05-01").is_err());
+        assert!(PngTime::parse_rfc3339("2024-05-01T12:30:45").is_err());
+        assert!(PngTime::parse_rfc3339("2024-02-30T12:30:45Z").is_err());
+        assert!(PngTime::parse_rfc3339("2024-05-01T24:00:00Z").is_err());
+        assert!(PngTime::parse_rfc3339("2024-05-01T12:30:45.Z").is_err());
+        assert!(PngTime::parse_rfc3339("2024-o5-01T12:30:45Z").is_err());
+        assert!(PngTime::parse_rfc3339("2024-05-01T12:30:4éZ").is_err());
+        // Offsets out of range
+        assert!(PngTime::parse_rfc3339("2024-05-01T12:30:45+99:99").is_err());
+        assert!(PngTime::parse_rfc3339("2024-05-01T12:30:45+24:00").is_err());
+        assert!(PngTime::parse_rfc3339("2024-05-01T12:30:45-05:60").is_err());
+        assert!(PngTime::parse_rfc3339("2024-05-01T12:30:45+23:59").is_ok());
+    }
+
+    #[test]
+    fn test_unix_seconds() {
+        assert_eq!(PngTime::from_unix_seconds(0).to_string(), "1970-01-01T00:00:00Z");
+        assert_eq!(PngTime::from_unix_seconds(951782400).to_string(), "2000-02-29T00:00:00Z");
+        let time = PngTime::new(2038, 1, 19, 3, 14, 8).unwrap();
+        assert_eq!(time.to_unix_seconds(), 1 << 31);
+        assert_eq!(PngTime::from_unix_seconds(1 << 31), time);
+    }
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let time = PngTime::new(2021, 11, 7, 8, 9, 10).unwrap();
+        let chunk = Chunk::new(crate::chunk_type::ChunkType::from_str("tIME").unwrap(), time.to_bytes().to_vec()).unwrap();
+        assert_eq!(chunk.data(), &[0x07, 0xe5, 11, 7, 8, 9, 10]);
+        assert_eq!(PngTime::try_from(&chunk).unwrap(), time);
+    }
+}
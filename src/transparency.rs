@@ -0,0 +1,81 @@
+use crate::error::PngMeError;
+
+/// The transparency stored in the tRNS chunk. Its form depends on the color type in IHDR: the
+/// gray level or RGB triple drawn fully transparent, or one alpha value per palette entry.
+/// Color types with an alpha channel (4 and 6) can not have a tRNS chunk.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.tRNS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+    /// Color type 0, the gray level that is transparent
+    Grayscale(u16),
+    /// Color type 2, the color that is transparent
+    Rgb(u16, u16, u16),
+    /// Color type 3, the alpha of the first palette entries. Missing entries are opaque.
+    Indexed(Vec<u8>),
+}
+
+impl Transparency {
+    /// Reads the data of a tRNS chunk for an image of the given color type
+    pub fn from_bytes(data: &[u8], color_type: u8) -> Result<Transparency, PngMeError> {
+        let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+        return match color_type {
+            0 if data.len() == 2 => Ok(Transparency::Grayscale(sample(0))),
+            2 if data.len() == 6 => Ok(Transparency::Rgb(sample(0), sample(2), sample(4))),
+            3 if !data.is_empty() && data.len() <= 256 => Ok(Transparency::Indexed(data.to_vec())),
+            0 => Err(PngMeError::InvalidLength{expected: 2, actual: data.len()}),
+            2 => Err(PngMeError::InvalidLength{expected: 6, actual: data.len()}),
+            3 => Err(PngMeError::InvalidChunkData(format!("tRNS with {} entries for a palette", data.len()))),
+            _ => Err(PngMeError::InvalidInput(format!("color type {} can not have a tRNS chunk", color_type))),
+        }
+    }
+
+    /// Returns the data of the tRNS chunk for this transparency
+    pub fn to_bytes(&self) -> Vec<u8> {
+        return match self {
+            Transparency::Grayscale(gray) => gray.to_be_bytes().to_vec(),
+            Transparency::Rgb(r, g, b) => [r.to_be_bytes(), g.to_be_bytes(), b.to_be_bytes()].concat(),
+            Transparency::Indexed(alphas) => alphas.clone(),
+        }
+    }
+
+    /// Checks that this transparency can be stored for the given color type, including the
+    /// 1 to 256 alpha values of an indexed one
+    pub fn validate_for_color_type(&self, color_type: u8) -> Result<(), PngMeError> {
+        return Transparency::from_bytes(&self.to_bytes(), color_type).and_then(|t| match t == *self {
+            true => Ok(()),
+            false => Err(PngMeError::InvalidInput(format!("{:?} is not a transparency for color type {}", self, color_type))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transparency_bytes_round_trip() {
+        let cases = [
+            (0, Transparency::Grayscale(0x0102), vec![1, 2]),
+            (2, Transparency::Rgb(1, 2, 65535), vec![0, 1, 0, 2, 255, 255]),
+            (3, Transparency::Indexed(vec![0, 128, 255]), vec![0, 128, 255]),
+        ];
+        for (color_type, transparency, bytes) in cases {
+            assert_eq!(transparency.to_bytes(), bytes);
+            assert_eq!(Transparency::from_bytes(&bytes, color_type).unwrap(), transparency);
+            assert!(transparency.validate_for_color_type(color_type).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_transparency_invalid() {
+        assert!(matches!(Transparency::from_bytes(&[1], 0), Err(PngMeError::InvalidLength{expected: 2, actual: 1})));
+        assert!(Transparency::from_bytes(&[], 3).is_err());
+        assert!(Transparency::from_bytes(&[0; 257], 3).is_err());
+        assert!(Transparency::from_bytes(&[0, 0], 4).is_err());
+        assert!(Transparency::from_bytes(&[0; 6], 6).is_err());
+
+        // The same two bytes are a gray level or two palette alphas, but not interchangeable
+        assert!(Transparency::Grayscale(0).validate_for_color_type(3).is_err());
+        assert!(Transparency::Indexed(vec![0; 6]).validate_for_color_type(2).is_err());
+    }
+}
@@ -0,0 +1,590 @@
+// zlib streams as used by IDAT, zTXt and iTXt chunks
+// https://datatracker.ietf.org/doc/html/rfc1950
+// https://datatracker.ietf.org/doc/html/rfc1951
+use crate::error::PngMeError;
+
+/// Largest amount of data a stored deflate block can hold
+const MAX_STORED_BLOCK: usize = 65535;
+/// How far back a match can reference
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Largest output `decompress` produces. A few hundred bytes of deflate can expand to
+/// gigabytes, so streams from untrusted chunks must not be inflated without a limit.
+pub const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+// Base values and extra bits of the length (257..285) and distance (0..29) codes
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073,
+    4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+/// Order in which the code length code lengths of a dynamic block are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Computes the Adler-32 checksum that ends every zlib stream
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    // 5552 is the largest amount of bytes that can be summed before b overflows
+    for block in data.chunks(5552) {
+        for byte in block {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    return (b << 16) | a
+}
+
+/// Wraps the data in a zlib stream without compressing it, using stored deflate blocks
+pub fn compress_stored(data: &[u8]) -> Vec<u8> {
+    // CMF: deflate with a 32K window. FLG: no dictionary, fastest level, check bits.
+    let mut v: Vec<u8> = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        // An empty stream still needs one final block
+        v.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let length = block.len() as u16;
+        v.push(is_final as u8);
+        v.extend(length.to_le_bytes());
+        v.extend((!length).to_le_bytes());
+        v.extend(block);
+    }
+
+    v.extend(adler32(data).to_be_bytes());
+    return v
+}
+
+/// Compresses the data into a zlib stream. `level` goes from 0 (no compression, stored blocks)
+/// to 9 (slowest, best compression). Matches are encoded with the fixed Huffman codes.
+pub fn compress(data: &[u8], level: u32) -> Result<Vec<u8>, PngMeError> {
+    if level > 9 {
+        return Err(PngMeError::InvalidInput(format!("compression level {} is not between 0 and 9", level)))
+    }
+    if level == 0 {
+        return Ok(compress_stored(data))
+    }
+
+    let mut writer = BitWriter::new();
+    // CMF: deflate with a 32K window. FLG: the level hint, and check bits so the header is a multiple of 31.
+    let cmf: u8 = 0x78;
+    let flevel: u8 = match level {
+        1 => 0,
+        2..=5 => 1,
+        6 => 2,
+        _ => 3,
+    };
+    let mut flg: u8 = flevel << 6;
+    flg += ((31 - ((cmf as u16) << 8 | flg as u16) % 31) % 31) as u8;
+    writer.bytes.extend([cmf, flg]);
+
+    // A single final block with fixed Huffman codes
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    // How many earlier positions with the same three starting bytes are tried for every match
+    let max_chain: usize = 1 << (level + 2);
+    let mut head: Vec<usize> = vec![usize::MAX; 1 << 15];
+    let mut prev: Vec<usize> = vec![usize::MAX; data.len()];
+    let mut i = 0;
+    while i < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+        if i + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(data, i)];
+            let mut chain = 0;
+            let max_length = MAX_MATCH.min(data.len() - i);
+            while candidate != usize::MAX && i - candidate <= WINDOW_SIZE && chain < max_chain {
+                let length = data[candidate..].iter().zip(&data[i..i + max_length]).take_while(|(a, b)| a == b).count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = i - candidate;
+                    if length == max_length {
+                        break
+                    }
+                }
+                candidate = prev[candidate];
+                chain += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            writer.write_length(best_length);
+            writer.write_distance(best_distance);
+            for j in i..i + best_length {
+                insert(data, j, &mut head, &mut prev);
+            }
+            i += best_length;
+        } else {
+            writer.write_literal(data[i] as u16);
+            insert(data, i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+    // End of block
+    writer.write_literal(256);
+
+    let mut v = writer.finish();
+    v.extend(adler32(data).to_be_bytes());
+    return Ok(v)
+}
+
+/// Hashes the three bytes starting at `i` into an index of the `head` table
+fn hash(data: &[u8], i: usize) -> usize {
+    return (((data[i] as usize) << 10) ^ ((data[i + 1] as usize) << 5) ^ data[i + 2] as usize) & ((1 << 15) - 1)
+}
+
+/// Records position `i` as the latest one starting with its three bytes, chaining the previous one
+fn insert(data: &[u8], i: usize, head: &mut [usize], prev: &mut [usize]) {
+    if i + MIN_MATCH <= data.len() {
+        let h = hash(data, i);
+        prev[i] = head[h];
+        head[h] = i;
+    }
+}
+
+/// Decompresses a zlib stream, checking its header and Adler-32 checksum.
+/// Fails if the output would be larger than `MAX_DECOMPRESSED_SIZE`.
+pub fn decompress(stream: &[u8]) -> Result<Vec<u8>, PngMeError> {
+    return decompress_limited(stream, MAX_DECOMPRESSED_SIZE)
+}
+
+/// Same as `decompress`, failing if the output would be larger than `max_size` bytes
+pub fn decompress_limited(stream: &[u8], max_size: usize) -> Result<Vec<u8>, PngMeError> {
+    if stream.len() < 6 {
+        return Err(PngMeError::DecompressionFailed(String::from("stream is too short")))
+    }
+    let (cmf, flg) = (stream[0], stream[1]);
+    if cmf & 0x0f != 8 || cmf >> 4 > 7 || !((cmf as u16) << 8 | flg as u16).is_multiple_of(31) {
+        return Err(PngMeError::DecompressionFailed(String::from("invalid zlib header")))
+    }
+    if flg & 0x20 != 0 {
+        return Err(PngMeError::DecompressionFailed(String::from("preset dictionaries are not supported")))
+    }
+
+    let mut reader = BitReader{bytes: &stream[2..], pos: 0, bit: 0, max_output: max_size};
+    let mut out: Vec<u8> = vec!();
+    loop {
+        let is_final = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => reader.stored_block(&mut out)?,
+            1 => {
+                let (literals, distances) = fixed_tables();
+                reader.huffman_block(&mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = reader.dynamic_tables()?;
+                reader.huffman_block(&mut out, &literals, &distances)?;
+            }
+            _ => return Err(PngMeError::DecompressionFailed(String::from("invalid block type"))),
+        }
+        if is_final {
+            break
+        }
+    }
+
+    // The checksum starts at the next byte boundary
+    let end = 2 + reader.pos + (reader.bit > 0) as usize;
+    let checksum = match stream.get(end..end + 4) {
+        Some(c) => u32::from_be_bytes([c[0], c[1], c[2], c[3]]),
+        None => return Err(PngMeError::DecompressionFailed(String::from("missing checksum"))),
+    };
+    if checksum != adler32(&out) {
+        return Err(PngMeError::DecompressionFailed(String::from("checksum does not match")))
+    }
+    return Ok(out)
+}
+
+/// Writes bits starting from the least significant bit of every byte, as deflate requires
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        return BitWriter{bytes: vec!(), buffer: 0, count: 0}
+    }
+
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.buffer |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are stored starting from their most significant bit
+    fn write_code(&mut self, code: u32, length: u32) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.write_bits(reversed, length);
+    }
+
+    /// Writes a literal/length symbol with its fixed Huffman code
+    fn write_literal(&mut self, symbol: u16) {
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol as u32, 8),
+            144..=255 => self.write_code(0x190 + (symbol - 144) as u32, 9),
+            256..=279 => self.write_code((symbol - 256) as u32, 7),
+            _ => self.write_code(0xc0 + (symbol - 280) as u32, 8),
+        }
+    }
+
+    fn write_length(&mut self, length: usize) {
+        let code = LENGTH_BASE.iter().rposition(|base| *base as usize <= length).unwrap();
+        self.write_literal(257 + code as u16);
+        self.write_bits((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+    }
+
+    fn write_distance(&mut self, distance: usize) {
+        let code = DISTANCE_BASE.iter().rposition(|base| *base as usize <= distance).unwrap();
+        self.write_code(code as u32, 5);
+        self.write_bits((distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        return self.bytes
+    }
+}
+
+/// A canonical Huffman code described by how many codes there are of every length
+/// and the symbols sorted by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for i in 1..15 {
+            offsets[i + 1] = offsets[i] + counts[i];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        return Huffman{counts, symbols}
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    return (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    bit: u32,
+    // Largest the output may grow to
+    max_output: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn error(message: &str) -> PngMeError {
+        return PngMeError::DecompressionFailed(message.to_string())
+    }
+
+    /// Fails unless `count` more bytes fit in the output
+    fn check_room(&self, out: &[u8], count: usize) -> Result<(), PngMeError> {
+        if out.len() + count > self.max_output {
+            return Err(BitReader::error(&format!("output is larger than {} bytes", self.max_output)))
+        }
+        return Ok(())
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, PngMeError> {
+        let mut value: u32 = 0;
+        for i in 0..count {
+            let byte = match self.bytes.get(self.pos) {
+                Some(b) => *b,
+                None => return Err(BitReader::error("unexpected end of stream")),
+            };
+            value |= (((byte >> self.bit) & 1) as u32) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        return Ok(value)
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> Result<u16, PngMeError> {
+        // Codes of the same length are consecutive, so walk the lengths until the code falls in a range
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..16 {
+            code |= self.bits(1)? as i32;
+            let count = huffman.counts[length] as i32;
+            if code - count < first {
+                return Ok(huffman.symbols[(index + code - first) as usize])
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        return Err(BitReader::error("invalid Huffman code"))
+    }
+
+    fn stored_block(&mut self, out: &mut Vec<u8>) -> Result<(), PngMeError> {
+        if self.bit > 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        let header = match self.bytes.get(self.pos..self.pos + 4) {
+            Some(h) => h,
+            None => return Err(BitReader::error("unexpected end of stream")),
+        };
+        let length = u16::from_le_bytes([header[0], header[1]]);
+        if length != !u16::from_le_bytes([header[2], header[3]]) {
+            return Err(BitReader::error("stored block length does not match its complement"))
+        }
+        self.pos += 4;
+        self.check_room(out, length as usize)?;
+        match self.bytes.get(self.pos..self.pos + length as usize) {
+            Some(data) => out.extend(data),
+            None => return Err(BitReader::error("unexpected end of stream")),
+        }
+        self.pos += length as usize;
+        return Ok(())
+    }
+
+    fn dynamic_tables(&mut self) -> Result<(Huffman, Huffman), PngMeError> {
+        let literal_count = self.bits(5)? as usize + 257;
+        let distance_count = self.bits(5)? as usize + 1;
+        let code_length_count = self.bits(4)? as usize + 4;
+        if literal_count > 286 || distance_count > 30 {
+            return Err(BitReader::error("too many codes in dynamic block"))
+        }
+
+        let mut code_lengths = [0u8; 19];
+        for i in 0..code_length_count {
+            code_lengths[CODE_LENGTH_ORDER[i]] = self.bits(3)? as u8;
+        }
+        let code_length_huffman = Huffman::new(&code_lengths);
+
+        let mut lengths: Vec<u8> = Vec::with_capacity(literal_count + distance_count);
+        while lengths.len() < literal_count + distance_count {
+            let symbol = self.decode(&code_length_huffman)?;
+            let (value, repeat) = match symbol {
+                0..=15 => (symbol as u8, 1),
+                16 => match lengths.last() {
+                    Some(last) => (*last, 3 + self.bits(2)?),
+                    None => return Err(BitReader::error("repeat with no previous length")),
+                },
+                17 => (0, 3 + self.bits(3)?),
+                _ => (0, 11 + self.bits(7)?),
+            };
+            for _ in 0..repeat {
+                lengths.push(value);
+            }
+        }
+        if lengths.len() > literal_count + distance_count {
+            return Err(BitReader::error("code lengths overflow the dynamic block"))
+        }
+        if lengths[256] == 0 {
+            return Err(BitReader::error("dynamic block has no end of block code"))
+        }
+
+        let (literal_lengths, distance_lengths) = lengths.split_at(literal_count);
+        return Ok((Huffman::new(literal_lengths), Huffman::new(distance_lengths)))
+    }
+
+    fn huffman_block(&mut self, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<(), PngMeError> {
+        loop {
+            let symbol = self.decode(literals)? as usize;
+            if symbol < 256 {
+                self.check_room(out, 1)?;
+                out.push(symbol as u8);
+                continue
+            }
+            if symbol == 256 {
+                return Ok(())
+            }
+
+            let code = symbol - 257;
+            if code >= LENGTH_BASE.len() {
+                return Err(BitReader::error("invalid length code"))
+            }
+            let length = LENGTH_BASE[code] as usize + self.bits(LENGTH_EXTRA[code] as u32)? as usize;
+
+            let code = self.decode(distances)? as usize;
+            if code >= DISTANCE_BASE.len() {
+                return Err(BitReader::error("invalid distance code"))
+            }
+            let distance = DISTANCE_BASE[code] as usize + self.bits(DISTANCE_EXTRA[code] as u32)? as usize;
+            if distance > out.len() {
+                return Err(BitReader::error("distance reaches before the start of the data"))
+            }
+
+            self.check_room(out, length)?;
+            // The copy can overlap with the bytes it produces, so go one byte at a time
+            let start = out.len() - distance;
+            for i in 0..length {
+                out.push(out[start + i]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn test_compress_stored() {
+        assert_eq!(compress_stored(&[0, 0]), vec![0x78, 0x01, 1, 2, 0, 0xfd, 0xff, 0, 0, 0, 2, 0, 1]);
+        assert_eq!(compress_stored(&[]), vec![0x78, 0x01, 1, 0, 0, 0xff, 0xff, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_compress_stored_multiple_blocks() {
+        let data = vec![7; MAX_STORED_BLOCK + 10];
+        let stream = compress_stored(&data);
+        // Header, two block headers, the data and the checksum
+        assert_eq!(stream.len(), 2 + 5 + 5 + data.len() + 4);
+        assert_eq!(stream[2], 0);
+        assert_eq!(stream[2 + 5 + MAX_STORED_BLOCK], 1);
+    }
+
+    fn sample_data() -> Vec<u8> {
+        let mut data: Vec<u8> = "The quick brown fox jumps over the lazy dog. ".repeat(200).into_bytes();
+        // Some bytes that do not repeat
+        let mut x: u32 = 12345;
+        for _ in 0..3000 {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            data.push((x >> 16) as u8);
+        }
+        data
+    }
+
+    #[test]
+    fn test_round_trip_all_levels() {
+        let data = sample_data();
+        for level in 0..=9 {
+            let stream = compress(&data, level).unwrap();
+            assert_eq!(((stream[0] as u16) << 8 | stream[1] as u16) % 31, 0);
+            assert_eq!(decompress(&stream).unwrap(), data, "level {}", level);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_edge_cases() {
+        for data in [vec!(), vec![0], vec![1, 2], vec![0; 100000]] {
+            assert_eq!(decompress(&compress(&data, 6).unwrap()).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_compress_reduces_size() {
+        let data = "The quick brown fox jumps over the lazy dog. ".repeat(200).into_bytes();
+        let stream = compress(&data, 9).unwrap();
+        assert!(stream.len() < data.len() / 20);
+    }
+
+    #[test]
+    fn test_invalid_level() {
+        assert!(compress(b"data", 10).is_err());
+    }
+
+    #[test]
+    fn test_decompress_zlib_stream() {
+        // "hello hello hello hello" compressed by zlib itself at level 9
+        let stream = [
+            0x78, 0xda, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x01, 0x68, 0x03, 0x08, 0xb1,
+        ];
+        assert_eq!(decompress(&stream).unwrap(), b"hello hello hello hello");
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        let mut stream = compress(b"some data to corrupt", 6).unwrap();
+        let last = stream.len() - 1;
+        stream[last] ^= 1;
+        assert!(decompress(&stream).is_err());
+        assert!(decompress(&[0x78, 0x9c]).is_err());
+        assert!(decompress(&[0x12, 0x34, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_decompress_limited() {
+        let stream = compress(&[0; 100000], 6).unwrap();
+        assert_eq!(decompress_limited(&stream, 100000).unwrap().len(), 100000);
+        let error = decompress_limited(&stream, 99999).unwrap_err();
+        assert_eq!(error.to_string(), "DecompressionFailed: output is larger than 99999 bytes");
+
+        let stream = compress_stored(&[1; 1000]);
+        assert!(decompress_limited(&stream, 1000).is_ok());
+        assert!(decompress_limited(&stream, 999).is_err());
+    }
+
+    #[test]
+    fn test_decompress_bomb() {
+        // A zero followed by copies of the last 258 bytes, enough to go past the limit.
+        // Every copy of 258 bytes takes 13 bits, so the stream is around 150 times smaller.
+        let mut writer = BitWriter::new();
+        writer.bytes.extend([0x78, 0x01]);
+        writer.write_bits(1, 1);
+        writer.write_bits(1, 2);
+        writer.write_literal(0);
+        for _ in 0..MAX_DECOMPRESSED_SIZE / MAX_MATCH + 1 {
+            writer.write_length(MAX_MATCH);
+            writer.write_distance(1);
+        }
+        writer.write_literal(256);
+        let mut stream = writer.finish();
+        stream.extend([0; 4]);
+        assert!(stream.len() < MAX_DECOMPRESSED_SIZE / 150);
+
+        let error = decompress(&stream).unwrap_err();
+        assert_eq!(error.to_string(), format!("DecompressionFailed: output is larger than {} bytes", MAX_DECOMPRESSED_SIZE));
+    }
+}